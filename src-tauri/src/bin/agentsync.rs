@@ -0,0 +1,679 @@
+//! AgentSync 命令行版：不开界面也能同步（脚本、git hook、SSH 会话里用）。
+//!
+//! 和桌面 App 共用同一份配置（`~/.agentsync/config.json`）、同一套 rclone 调用和历史记录。
+//! 所有命令都往 stdout 输出 JSON；rclone 的实时输出写到 stderr。
+//! `sync` 默认交给后台守护进程去跑（和界面共用“同一时间只跑一个同步”的规则），`--local` 则在本进程里直接跑。
+
+use chrono::{Local, NaiveDate, TimeZone};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+use tauri_app_lib::bundle::{self, ExportOptions, ImportOptions, PathMapping};
+use tauri_app_lib::config::{self, AgentSyncConfig, DriftAction};
+use tauri_app_lib::daemon;
+use tauri_app_lib::discover;
+use tauri_app_lib::drift;
+use tauri_app_lib::estimate;
+use tauri_app_lib::known_hosts::{self, HostKeyStatus};
+use tauri_app_lib::logs;
+use tauri_app_lib::manifest;
+use tauri_app_lib::rclone::{self, RunOptions, SyncProgress};
+use tauri_app_lib::runs::{self, RunQuery, SyncItemResult};
+use tauri_app_lib::ssh_keys;
+use tauri_app_lib::stats;
+use tauri_app_lib::webhooks;
+use tauri_app_lib::sync_manager::SyncStatus;
+use tauri_app_lib::validate;
+use uuid::Uuid;
+
+const USAGE: &str = "用法：agentsync <命令> [参数]
+
+命令：
+  sync [--item <id>]... [--local] [--on-drift <warn|skip|pull|backup|off>]
+                                  同步全部启用项；--item 可多次指定，只同步这些项
+                                  默认交给守护进程跑；--local 在当前进程里直接跑
+                                  --on-drift 指定这次遇到目标端改动时怎么处理
+  status                          当前 / 最近一次同步的状态（来自守护进程）
+  cancel                          取消守护进程里正在跑的同步
+  verify [--item <id>]...         逐项比较本机和目标端，列出缺少 / 多出 / 内容不同的文件
+  drift [--item <id>]...          列出上次同步后目标端被改过的文件（同步时会被覆盖）
+  estimate [--item <id>]...       同步前估算：每项的文件数、大小、最大的子目录和大文件；超过阈值时提醒
+  test                            逐步诊断连接（DNS、端口、SSH、登录、SFTP、写入），并检查剩余空间、系统、大小写、文件名
+  host-key [--trust <SHA256:指纹>]
+                                  看目标电脑的身份指纹是否和记下的一致；--trust 核对后记下（第一次或指纹变了之后）
+  validate                        检查配置，列出所有问题（error 会拦下同步，warning 只是提醒）
+  discover [--root <目录>]... [--depth <n>] [--add]
+                                  在 ~/code 等目录下找 git 仓库和带 AGENTS.md / .claude 等标记的文件夹，
+                                  以及已知工具的数据目录，列出建议的项目和大小；--add 把没在同步的都加进配置
+  history [--limit <n>] [--offset <n>] [--status <s>] [--ok | --failed] [--target <t>]
+          [--item <名字或 id>] [--from <YYYY-MM-DD>] [--to <YYYY-MM-DD>] [--archived]
+                                  列出历史记录（新的在前）；--archived 连归档一起查
+  history compact [--keep-days <n>]
+                                  整理历史文件：去重，n 天（默认 180）前的挪进归档，坏行单独存放
+  stats [--days <n>]              历史统计：每项 / 每个目标的成功率、用时、字节数、最后一次成功
+  log <run_id>                    读取某次运行的日志（已压缩的自动解压）
+  logs cleanup                    按配置里的保留策略压缩 / 删除旧日志（平时每次同步结束会自动做）
+  manifest show <run_id>          某次运行传输 / 删除 / 备份了哪些文件
+  manifest find <路径> [--item <id>] [--limit <n>]
+                                  查某个文件在哪些运行里被推送 / 删除过（支持 * ? 通配符）
+  notify test                     给配置里的所有通知目标发一条测试消息
+  export <文件> [--redact-keys] [--no-history] [--no-logs]
+                                  把配置、历史、日志打包成一个 .tar.gz（--redact-keys 清空密钥路径）
+  import <文件> [--map <旧路径>=<新路径>]... [--no-history] [--with-hooks] [--dry-run]
+                                  把导出的包合并进本机配置；源电脑的用户目录自动换成本机的
+                                  --map 另外指定项目路径怎么换；--dry-run 只看会导入什么
+  keys ensure [--force]           生成 / 复用 AgentSync 专用 SSH 密钥
+  resume <run_id>                 续跑一次被中断的同步（只跑当时没完成的项）
+  backups list                    列出目标端的备份（按运行）
+  backups restore <run_id> [--item <id>]...
+                                  把某次运行的备份恢复到目标端原位置
+  daemon                          以守护进程身份在前台运行
+  daemon stop                     让守护进程退出（有同步在跑时会拒绝）
+
+同步项 id：项目用配置里的 id；另有 codex:sessions / codex:config / agents";
+
+struct CliError {
+  message: String,
+  usage: bool,
+}
+
+impl From<String> for CliError {
+  fn from(message: String) -> Self {
+    CliError {
+      message,
+      usage: false,
+    }
+  }
+}
+
+fn usage_error(message: impl Into<String>) -> CliError {
+  CliError {
+    message: message.into(),
+    usage: true,
+  }
+}
+
+/// rclone 输出 -> stderr，这样 stdout 只留给最终的 JSON。
+struct StderrProgress;
+
+impl SyncProgress for StderrProgress {
+  fn on_item_start(&mut self, label: &str) {
+    eprintln!("==> {}", label);
+  }
+
+  fn on_line(&mut self, line: &str) {
+    eprintln!("{}", line);
+  }
+
+  fn on_item_done(&mut self, result: &SyncItemResult) {
+    eprintln!("<== {}：{}", result.label, result.message);
+  }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), CliError> {
+  let text = serde_json::to_string_pretty(value).map_err(|e| format!("序列化输出失败（{}）", e))?;
+  println!("{}", text);
+  Ok(())
+}
+
+/// 取出所有 `--item <id>`，剩下的参数原样返回。
+fn take_items(args: &[String]) -> Result<(Vec<String>, Vec<String>), CliError> {
+  let mut items = Vec::new();
+  let mut rest = Vec::new();
+  let mut it = args.iter();
+  while let Some(a) = it.next() {
+    if a == "--item" {
+      let id = it.next().ok_or_else(|| usage_error("--item 后面需要同步项 id"))?;
+      items.push(id.clone());
+    } else {
+      rest.push(a.clone());
+    }
+  }
+  Ok((items, rest))
+}
+
+fn no_extra(args: &[String]) -> Result<(), CliError> {
+  match args.first() {
+    Some(a) => Err(usage_error(format!("无法识别的参数：{}", a))),
+    None => Ok(()),
+  }
+}
+
+fn load_config() -> Result<AgentSyncConfig, CliError> {
+  Ok(config::load_or_default()?)
+}
+
+fn daemon_call<T: DeserializeOwned>(method: &str, params: Value) -> Result<T, CliError> {
+  let exe = std::env::current_exe().map_err(|e| format!("找不到当前程序路径（{}）", e))?;
+  daemon::ensure_running(&exe, &["daemon"])?;
+  let v = daemon::request(method, params)?;
+  Ok(serde_json::from_value(v).map_err(|e| format!("守护进程回复格式无效（{}）", e))?)
+}
+
+fn sync_local(items: Vec<String>, on_drift: Option<DriftAction>) -> Result<bool, CliError> {
+  // 守护进程正在跑同步时不能再在本进程里跑一份：两边会同时往目标端写
+  if daemon::ping().is_ok() {
+    let st: SyncStatus = serde_json::from_value(daemon::request("sync_status", Value::Null)?)
+      .map_err(|e| format!("守护进程回复格式无效（{}）", e))?;
+    if st.running {
+      return Err(
+        format!(
+          "守护进程正在跑同步（{}），请等它结束（agentsync status）或去掉 --local 排队交给守护进程",
+          st.run_id.unwrap_or_default()
+        )
+        .into(),
+      );
+    }
+  }
+  let cfg = load_config()?;
+  let opts = RunOptions {
+    only: if items.is_empty() { None } else { Some(items) },
+    trigger: Some(runs::TRIGGER_CLI.to_string()),
+    on_drift,
+  };
+  let run_id = Uuid::new_v4().simple().to_string();
+  let summary = rclone::run_sync_with_id(&cfg, run_id, &opts, &mut StderrProgress)?;
+  // 通知在后台发：等发完再退出
+  webhooks::wait_pending();
+  print_json(&summary)?;
+  Ok(summary.ok)
+}
+
+/// 交给守护进程跑。
+fn sync_via_daemon(items: Vec<String>, on_drift: Option<DriftAction>) -> Result<bool, CliError> {
+  let params = json!({
+    "itemIds": if items.is_empty() { None } else { Some(items) },
+    "trigger": runs::TRIGGER_CLI,
+    "onDrift": on_drift,
+  });
+  let run_id: String = daemon_call("sync_start", params)?;
+  wait_for_run(run_id)
+}
+
+/// 每秒轮询一次守护进程状态，把进度打到 stderr，跑完输出结果。
+fn wait_for_run(run_id: String) -> Result<bool, CliError> {
+  eprintln!("run_id: {}", run_id);
+
+  let mut reported_items = 0;
+  let mut last_line: Option<String> = None;
+  loop {
+    std::thread::sleep(Duration::from_secs(1));
+    let st: SyncStatus = daemon_call("sync_status", Value::Null)?;
+    if st.run_id.as_deref() != Some(run_id.as_str()) {
+      return Err(format!("守护进程里已经找不到这次同步（{}），请用 history 查看结果", run_id).into());
+    }
+    for item in st.items.iter().skip(reported_items) {
+      StderrProgress.on_item_done(item);
+    }
+    reported_items = st.items.len();
+    if st.last_line != last_line {
+      if let Some(line) = st.last_line.as_deref() {
+        eprintln!("{}", line);
+      }
+      last_line = st.last_line.clone();
+    }
+
+    if st.running {
+      continue;
+    }
+    return match st.summary {
+      Some(summary) => {
+        print_json(&summary)?;
+        Ok(summary.ok)
+      }
+      None => Err(st.error.unwrap_or_else(|| "同步失败（原因未知）".to_string()).into()),
+    };
+  }
+}
+
+fn cmd_resume(args: &[String]) -> Result<bool, CliError> {
+  let [run_id] = args else {
+    return Err(usage_error("用法：agentsync resume <run_id>"));
+  };
+  let new_run_id: String = daemon_call("run_resume", json!({ "runId": run_id }))?;
+  wait_for_run(new_run_id)
+}
+
+fn cmd_sync(args: &[String]) -> Result<bool, CliError> {
+  let (items, rest) = take_items(args)?;
+  let mut local = false;
+  let mut on_drift = None;
+  let mut it = rest.iter();
+  while let Some(a) = it.next() {
+    match a.as_str() {
+      "--local" => local = true,
+      "--on-drift" => {
+        let v = it.next().ok_or_else(|| usage_error("--on-drift 后面需要 warn / skip / pull / backup / off"))?;
+        let action = serde_json::from_value::<DriftAction>(Value::String(v.clone()))
+          .map_err(|_| usage_error(format!("--on-drift 只能是 warn / skip / pull / backup / off：{}", v)))?;
+        on_drift = Some(action);
+      }
+      other => return Err(usage_error(format!("无法识别的参数：{}", other))),
+    }
+  }
+  if local {
+    sync_local(items, on_drift)
+  } else {
+    sync_via_daemon(items, on_drift)
+  }
+}
+
+fn cmd_status(args: &[String]) -> Result<bool, CliError> {
+  no_extra(args)?;
+  if daemon::ping().is_err() {
+    print_json(&json!({ "daemonRunning": false }))?;
+    return Ok(true);
+  }
+  let st: SyncStatus = daemon_call("sync_status", Value::Null)?;
+  print_json(&st)?;
+  Ok(true)
+}
+
+fn cmd_cancel(args: &[String]) -> Result<bool, CliError> {
+  no_extra(args)?;
+  if daemon::ping().is_err() {
+    return Err("守护进程没有在运行，没有可取消的同步".to_string().into());
+  }
+  daemon::request("sync_cancel", Value::Null)?;
+  print_json(&json!({ "ok": true }))?;
+  Ok(true)
+}
+
+fn cmd_daemon(args: &[String]) -> Result<bool, CliError> {
+  match args {
+    [] => {
+      daemon::serve()?;
+      Ok(true)
+    }
+    [sub] if sub == "stop" => {
+      if daemon::ping().is_err() {
+        print_json(&json!({ "ok": true, "message": "守护进程没有在运行" }))?;
+        return Ok(true);
+      }
+      daemon::request("shutdown", Value::Null)?;
+      print_json(&json!({ "ok": true }))?;
+      Ok(true)
+    }
+    _ => Err(usage_error("用法：agentsync daemon [stop]")),
+  }
+}
+
+fn cmd_host_key(args: &[String]) -> Result<bool, CliError> {
+  let cfg = load_config()?;
+  let (host, port) = (&cfg.connection.host, cfg.connection.port);
+  match args {
+    [] => {
+      let info = known_hosts::check(host, port)?;
+      print_json(&info)?;
+      Ok(info.status == HostKeyStatus::Trusted)
+    }
+    [flag, fingerprint] if flag == "--trust" => {
+      print_json(&known_hosts::trust(host, port, fingerprint)?)?;
+      Ok(true)
+    }
+    _ => Err(usage_error("用法：agentsync host-key [--trust <SHA256:指纹>]")),
+  }
+}
+
+fn cmd_test(args: &[String]) -> Result<bool, CliError> {
+  no_extra(args)?;
+  let res = rclone::test_connection(&load_config()?)?;
+  print_json(&res)?;
+  Ok(res.ok)
+}
+
+/// `YYYY-MM-DD` -> 本机时区那天 0 点（`end_of_day` 时取 23:59:59.999）的毫秒数。
+fn parse_day(flag: &str, s: &str, end_of_day: bool) -> Result<u64, CliError> {
+  let bad = || usage_error(format!("{} 需要 YYYY-MM-DD 格式的日期：{}", flag, s));
+  let day = NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|_| bad())?;
+  let start = day.and_hms_opt(0, 0, 0).ok_or_else(bad)?;
+  let ms = Local
+    .from_local_datetime(&start)
+    .earliest()
+    .ok_or_else(bad)?
+    .timestamp_millis() as u64;
+  Ok(if end_of_day { ms + 24 * 3600 * 1000 - 1 } else { ms })
+}
+
+fn cmd_history_compact(args: &[String]) -> Result<bool, CliError> {
+  let keep_days = match args {
+    [] => runs::DEFAULT_KEEP_DAYS,
+    [flag, n] if flag == "--keep-days" => {
+      n.parse().map_err(|_| usage_error(format!("--keep-days 不是有效数字：{}", n)))?
+    }
+    _ => return Err(usage_error("用法：agentsync history compact [--keep-days <n>]")),
+  };
+  print_json(&runs::compact_history(keep_days)?)?;
+  Ok(true)
+}
+
+fn cmd_history(args: &[String]) -> Result<bool, CliError> {
+  if let [sub, rest @ ..] = args {
+    if sub == "compact" {
+      return cmd_history_compact(rest);
+    }
+  }
+
+  let mut q = RunQuery::default();
+  let mut it = args.iter();
+  while let Some(a) = it.next() {
+    let mut value = || it.next().ok_or_else(|| usage_error(format!("{} 后面需要一个值", a)));
+    match a.as_str() {
+      "--limit" => {
+        let n = value()?;
+        q.limit = Some(n.parse().map_err(|_| usage_error(format!("--limit 不是有效数字：{}", n)))?);
+      }
+      "--offset" => {
+        let n = value()?;
+        q.offset = n.parse().map_err(|_| usage_error(format!("--offset 不是有效数字：{}", n)))?;
+      }
+      "--status" => {
+        let s = value()?;
+        q.status = Some(
+          serde_json::from_value(Value::String(s.clone()))
+            .map_err(|_| usage_error(format!("--status 只能是 completed / missed / interrupted / cancelled：{}", s)))?,
+        );
+      }
+      "--ok" => q.ok = Some(true),
+      "--failed" => q.ok = Some(false),
+      "--target" => q.target = Some(value()?.clone()),
+      "--item" => q.item = Some(value()?.clone()),
+      "--from" => q.from_ms = Some(parse_day("--from", value()?, false)?),
+      "--to" => q.to_ms = Some(parse_day("--to", value()?, true)?),
+      "--archived" => q.include_archived = true,
+      other => return Err(usage_error(format!("无法识别的参数：{}", other))),
+    }
+  }
+
+  let page = runs::query_runs(&q)?;
+  if page.corrupt_count > 0 {
+    eprintln!(
+      "警告：历史文件里有 {} 行解析不了（已跳过），可以用 agentsync history compact 把它们挪到 history.corrupt.jsonl",
+      page.corrupt_count
+    );
+    for c in &page.corrupt {
+      eprintln!("  {}:{}：{}", c.file, c.line_no, c.error);
+    }
+  }
+  print_json(&page.runs)?;
+  Ok(true)
+}
+
+fn cmd_stats(args: &[String]) -> Result<bool, CliError> {
+  let days = match args {
+    [] => None,
+    [flag, n] if flag == "--days" => {
+      Some(n.parse().map_err(|_| usage_error(format!("--days 不是有效数字：{}", n)))?)
+    }
+    _ => return Err(usage_error("用法：agentsync stats [--days <n>]")),
+  };
+  print_json(&stats::history_stats(days)?)?;
+  Ok(true)
+}
+
+fn cmd_log(args: &[String]) -> Result<bool, CliError> {
+  let [run_id] = args else {
+    return Err(usage_error("用法：agentsync log <run_id>"));
+  };
+  let log = runs::read_log(run_id)?;
+  print_json(&json!({ "runId": run_id, "log": log }))?;
+  Ok(true)
+}
+
+fn cmd_logs(args: &[String]) -> Result<bool, CliError> {
+  match args {
+    [sub] if sub == "cleanup" => {
+      let res = logs::enforce(&load_config()?.log_retention, None)?;
+      print_json(&res)?;
+      Ok(res.errors.is_empty())
+    }
+    _ => Err(usage_error("用法：agentsync logs cleanup")),
+  }
+}
+
+fn cmd_export(args: &[String]) -> Result<bool, CliError> {
+  let [dest, flags @ ..] = args else {
+    return Err(usage_error("用法：agentsync export <文件> [--redact-keys] [--no-history] [--no-logs]"));
+  };
+  let mut opts = ExportOptions::default();
+  for f in flags {
+    match f.as_str() {
+      "--redact-keys" => opts.redact_key_paths = true,
+      "--no-history" => opts.include_history = false,
+      "--no-logs" => opts.include_logs = false,
+      other => return Err(usage_error(format!("无法识别的参数：{}", other))),
+    }
+  }
+  print_json(&bundle::export(Path::new(dest), &opts)?)?;
+  Ok(true)
+}
+
+fn cmd_import(args: &[String]) -> Result<bool, CliError> {
+  let [src, flags @ ..] = args else {
+    return Err(usage_error("用法：agentsync import <文件> [--map <旧路径>=<新路径>]... [--no-history] [--with-hooks] [--dry-run]"));
+  };
+  let mut opts = ImportOptions::default();
+  let mut it = flags.iter();
+  while let Some(f) = it.next() {
+    match f.as_str() {
+      "--map" => {
+        let m = it.next().ok_or_else(|| usage_error("--map 后面需要 <旧路径>=<新路径>"))?;
+        let (from, to) = m
+          .split_once('=')
+          .ok_or_else(|| usage_error(format!("--map 的格式是 <旧路径>=<新路径>：{}", m)))?;
+        opts.path_map.push(PathMapping {
+          from: from.to_string(),
+          to: to.to_string(),
+        });
+      }
+      "--no-history" => opts.include_history = false,
+      "--with-hooks" => opts.include_hooks = true,
+      "--dry-run" => opts.dry_run = true,
+      other => return Err(usage_error(format!("无法识别的参数：{}", other))),
+    }
+  }
+  let res = bundle::import(Path::new(src), &opts)?;
+  print_json(&res)?;
+  if !res.dry_run {
+    // 守护进程在跑的话，让它按新配置调整监听模式
+    if daemon::ping().is_ok() {
+      let _ = daemon::request("config_reload", Value::Null);
+    }
+  }
+  Ok(true)
+}
+
+fn cmd_validate(args: &[String]) -> Result<bool, CliError> {
+  no_extra(args)?;
+  let report = validate::validate(&load_config()?);
+  print_json(&report)?;
+  Ok(report.ok)
+}
+
+fn cmd_discover(args: &[String]) -> Result<bool, CliError> {
+  let mut roots: Vec<String> = Vec::new();
+  let mut depth: Option<u32> = None;
+  let mut add = false;
+  let mut it = args.iter();
+  while let Some(a) = it.next() {
+    let mut value = || it.next().ok_or_else(|| usage_error(format!("{} 后面需要一个值", a)));
+    match a.as_str() {
+      "--root" => roots.push(value()?.clone()),
+      "--depth" => {
+        let n = value()?;
+        depth = Some(n.parse().map_err(|_| usage_error(format!("--depth 不是有效数字：{}", n)))?);
+      }
+      "--add" => add = true,
+      other => return Err(usage_error(format!("无法识别的参数：{}", other))),
+    }
+  }
+
+  let mut cfg = load_config()?;
+  let only_roots = if roots.is_empty() { None } else { Some(roots.as_slice()) };
+  let res = discover::discover(&cfg, only_roots, depth)?;
+  print_json(&res)?;
+  if add {
+    let added = discover::apply(&mut cfg, &res.items);
+    if added > 0 {
+      config::save_config(&cfg)?;
+      if daemon::ping().is_ok() {
+        let _ = daemon::request("config_reload", Value::Null);
+      }
+    }
+    eprintln!("已加入 {} 项", added);
+  }
+  Ok(true)
+}
+
+fn cmd_verify(args: &[String]) -> Result<bool, CliError> {
+  let (items, extra) = take_items(args)?;
+  no_extra(&extra)?;
+  let only = if items.is_empty() { None } else { Some(items.as_slice()) };
+  let res = rclone::verify(&load_config()?, only)?;
+  print_json(&res)?;
+  Ok(res.ok)
+}
+
+fn cmd_estimate(args: &[String]) -> Result<bool, CliError> {
+  let (items, extra) = take_items(args)?;
+  no_extra(&extra)?;
+  let only = if items.is_empty() { None } else { Some(items.as_slice()) };
+  let res = estimate::estimate(&load_config()?, only)?;
+  print_json(&res)?;
+  Ok(res.warnings.is_empty())
+}
+
+fn cmd_drift(args: &[String]) -> Result<bool, CliError> {
+  let (items, extra) = take_items(args)?;
+  no_extra(&extra)?;
+  let only = if items.is_empty() { None } else { Some(items.as_slice()) };
+  let reports = drift::check(&load_config()?, only)?;
+  print_json(&reports)?;
+  Ok(reports.iter().all(|r| r.files.is_empty() && r.error.is_none()))
+}
+
+fn cmd_manifest(args: &[String]) -> Result<bool, CliError> {
+  const USAGE_MANIFEST: &str = "用法：agentsync manifest show <run_id> | agentsync manifest find <路径> [--item <id>] [--limit <n>]";
+  match args {
+    [sub, run_id] if sub == "show" => {
+      print_json(&manifest::load(run_id)?)?;
+      Ok(true)
+    }
+    [sub, query, rest @ ..] if sub == "find" => {
+      let (items, rest) = take_items(rest)?;
+      let item_id = match items.as_slice() {
+        [] => None,
+        [id] => Some(id.as_str()),
+        _ => return Err(usage_error("manifest find 只能指定一个 --item")),
+      };
+      let limit = match rest.as_slice() {
+        [] => None,
+        [flag, n] if flag == "--limit" => {
+          Some(n.parse().map_err(|_| usage_error(format!("--limit 不是有效数字：{}", n)))?)
+        }
+        _ => return Err(usage_error(USAGE_MANIFEST)),
+      };
+      print_json(&manifest::find(query, item_id, limit)?)?;
+      Ok(true)
+    }
+    _ => Err(usage_error(USAGE_MANIFEST)),
+  }
+}
+
+fn cmd_notify(args: &[String]) -> Result<bool, CliError> {
+  match args {
+    [sub] if sub == "test" => {
+      let results = webhooks::send_test(&load_config()?);
+      print_json(&results)?;
+      Ok(results.iter().all(|r| r.ok))
+    }
+    _ => Err(usage_error("用法：agentsync notify test")),
+  }
+}
+
+fn cmd_keys(args: &[String]) -> Result<bool, CliError> {
+  match args {
+    [sub, rest @ ..] if sub == "ensure" => {
+      let force = match rest {
+        [] => false,
+        [f] if f == "--force" => true,
+        _ => return Err(usage_error("用法：agentsync keys ensure [--force]")),
+      };
+      print_json(&ssh_keys::ensure_keypair(force)?)?;
+      Ok(true)
+    }
+    _ => Err(usage_error("用法：agentsync keys ensure [--force]")),
+  }
+}
+
+fn cmd_backups(args: &[String]) -> Result<bool, CliError> {
+  match args {
+    [sub] if sub == "list" => {
+      print_json(&rclone::list_backups(&load_config()?)?)?;
+      Ok(true)
+    }
+    [sub, run_id, rest @ ..] if sub == "restore" => {
+      let (items, extra) = take_items(rest)?;
+      no_extra(&extra)?;
+      let only = if items.is_empty() { None } else { Some(items.as_slice()) };
+      let res = rclone::restore_backup(&load_config()?, run_id, only)?;
+      print_json(&res)?;
+      Ok(res.ok)
+    }
+    _ => Err(usage_error("用法：agentsync backups list | agentsync backups restore <run_id> [--item <id>]...")),
+  }
+}
+
+fn dispatch(args: &[String]) -> Result<bool, CliError> {
+  let Some((cmd, rest)) = args.split_first() else {
+    return Err(usage_error("缺少命令"));
+  };
+  match cmd.as_str() {
+    "sync" => cmd_sync(rest),
+    "resume" => cmd_resume(rest),
+    "status" => cmd_status(rest),
+    "cancel" => cmd_cancel(rest),
+    "verify" => cmd_verify(rest),
+    "validate" => cmd_validate(rest),
+    "discover" => cmd_discover(rest),
+    "drift" => cmd_drift(rest),
+    "estimate" => cmd_estimate(rest),
+    "daemon" => cmd_daemon(rest),
+    "test" => cmd_test(rest),
+    "host-key" => cmd_host_key(rest),
+    "history" => cmd_history(rest),
+    "stats" => cmd_stats(rest),
+    "log" => cmd_log(rest),
+    "logs" => cmd_logs(rest),
+    "manifest" => cmd_manifest(rest),
+    "notify" => cmd_notify(rest),
+    "export" => cmd_export(rest),
+    "import" => cmd_import(rest),
+    "keys" => cmd_keys(rest),
+    "backups" => cmd_backups(rest),
+    "help" | "--help" | "-h" => {
+      println!("{}", USAGE);
+      Ok(true)
+    }
+    other => Err(usage_error(format!("未知命令：{}", other))),
+  }
+}
+
+fn main() -> ExitCode {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  match dispatch(&args) {
+    Ok(true) => ExitCode::SUCCESS,
+    Ok(false) => ExitCode::from(1),
+    Err(e) => {
+      println!("{}", json!({ "ok": false, "error": e.message }));
+      if e.usage {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+      }
+      ExitCode::from(1)
+    }
+  }
+}
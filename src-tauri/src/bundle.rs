@@ -0,0 +1,529 @@
+//! 导出 / 导入：把配置、历史、日志打成一个 `.tar.gz`，在另一台电脑上合并进去。
+//!
+//! 用法（大白话）：同事要配一台新的源电脑时，不用在“同步项”页面里再点一遍，直接导入你的项目和排除规则。
+//!
+//! 包里的内容：
+//! - `bundle.json`：打包时间、源电脑的用户目录（导入时自动换成本机的）、是否隐去了密钥路径
+//! - `config.json`：配置（可选把 `keyPath` 清空）
+//! - `history.jsonl`、`logs/*`：可选
+//!
+//! 导入时的合并规则：
+//! - 项目：id 或（换路径后的）本机目录已经有的跳过；本机目录不存在的照样加进来，但先不启用
+//! - 排除规则、定时同步（按 id）、路径变量：本机没有的才加（变量本机已经定义的保留本机的值）
+//! - 单项命令：会在本机 / 目标端执行，默认不导入，只在结果里列出来；`includeHooks` 打开才加（同样只加本机没有的）
+//! - 连接 / 目标端目录：本机还没填目标电脑时才用包里的（包里的密钥路径为空时保留本机的）
+//! - 历史放进 `history-archive/imported-<时间>.jsonl`（能查，但不影响本机的统计和目标端改动检查），日志复制到本机日志目录
+//!   （日志可能有几个 G：解包时直接写到 `~/.agentsync` 下的临时目录，不读进内存）
+
+use crate::config::{self, AgentSyncConfig};
+use crate::runs::{self, SyncRunSummary};
+use crate::vars;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BUNDLE_VERSION: u32 = 1;
+const ROOT: &str = "agentsync-bundle";
+
+/// `bundle.json`：包的说明。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleInfo {
+  pub version: u32,
+  pub created_at_ms: u64,
+  /// 源电脑的用户目录：导入时项目路径里的这一段自动换成本机的用户目录
+  #[serde(default)]
+  pub source_home: Option<String>,
+  #[serde(default)]
+  pub key_paths_redacted: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportOptions {
+  /// 清空 `connection.keyPath`（别人的电脑上这个路径没用，也不想暴露）
+  pub redact_key_paths: bool,
+  pub include_history: bool,
+  pub include_logs: bool,
+}
+
+impl Default for ExportOptions {
+  fn default() -> Self {
+    ExportOptions {
+      redact_key_paths: false,
+      include_history: true,
+      include_logs: true,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportResult {
+  pub path: String,
+  pub projects: usize,
+  pub runs: usize,
+  pub logs: usize,
+  pub bytes: u64,
+}
+
+/// 项目路径的替换规则：以 `from` 开头（按整段目录算）的换成 `to`。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathMapping {
+  pub from: String,
+  pub to: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImportOptions {
+  /// 按顺序试，第一条匹配的生效；都不匹配时再试“源电脑用户目录 -> 本机用户目录”
+  pub path_map: Vec<PathMapping>,
+  pub include_history: bool,
+  /// 导入单项命令（同步前 / 同步后在本机和目标端执行的命令）；默认不导入
+  pub include_hooks: bool,
+  /// 只看会导入什么，不写任何文件
+  pub dry_run: bool,
+}
+
+impl Default for ImportOptions {
+  fn default() -> Self {
+    ImportOptions {
+      path_map: vec![],
+      include_history: true,
+      include_hooks: false,
+      dry_run: false,
+    }
+  }
+}
+
+/// 导入的一个项目。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedProject {
+  pub id: String,
+  pub name: String,
+  /// 包里的路径
+  pub source_path: String,
+  /// 换成本机后的路径
+  pub local_path: String,
+  /// 本机目录不存在（这种项目先不启用）
+  pub missing: bool,
+}
+
+/// 包里的一条单项命令。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedHook {
+  pub item_id: String,
+  /// `preSync` / `postSyncRemote`
+  pub stage: String,
+  pub command: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResult {
+  pub dry_run: bool,
+  pub bundle: BundleInfo,
+  pub projects_added: Vec<ImportedProject>,
+  /// 本机已经有的项目（按 id 或目录）
+  pub projects_skipped: Vec<String>,
+  pub excludes_added: Vec<String>,
+  /// 新加的路径变量名
+  pub variables_added: Vec<String>,
+  pub schedules_added: usize,
+  /// 加进本机配置的单项命令（`includeHooks` 时）
+  pub hooks_added: Vec<ImportedHook>,
+  /// 包里有、本机没有，但因为没开 `includeHooks` 没导入的单项命令
+  pub hooks_ignored: Vec<ImportedHook>,
+  /// 本机还没填目标电脑，用了包里的连接信息
+  pub connection_imported: bool,
+  pub runs_imported: usize,
+  pub logs_imported: usize,
+}
+
+fn now_ms() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_millis() as u64
+}
+
+fn to_json<T: Serialize>(v: &T) -> Result<Vec<u8>, String> {
+  serde_json::to_vec_pretty(v).map_err(|e| format!("序列化失败（{}）", e))
+}
+
+fn add_bytes<W: Write>(tar: &mut tar::Builder<W>, name: &str, data: &[u8]) -> Result<(), String> {
+  let mut header = tar::Header::new_gnu();
+  header.set_size(data.len() as u64);
+  header.set_mode(0o600);
+  header.set_mtime(now_ms() / 1000);
+  header.set_cksum();
+  tar
+    .append_data(&mut header, format!("{}/{}", ROOT, name), data)
+    .map_err(|e| format!("打包失败：{}（{}）", name, e))
+}
+
+/// 导出到 `dest`（一般叫 `agentsync-bundle.tar.gz`）。
+pub fn export(dest: &Path, opts: &ExportOptions) -> Result<ExportResult, String> {
+  let mut cfg = config::load_or_default()?;
+  if opts.redact_key_paths {
+    cfg.connection.key_path = String::new();
+  }
+  let info = BundleInfo {
+    version: BUNDLE_VERSION,
+    created_at_ms: now_ms(),
+    source_home: dirs::home_dir().map(|h| h.display().to_string()),
+    key_paths_redacted: opts.redact_key_paths,
+  };
+
+  let tmp = dest.with_extension("tmp");
+  let file = fs::File::create(&tmp).map_err(|e| format!("创建导出文件失败：{}（{}）", tmp.display(), e))?;
+  let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+  add_bytes(&mut tar, "bundle.json", &to_json(&info)?)?;
+  add_bytes(&mut tar, "config.json", &to_json(&cfg)?)?;
+
+  let mut runs_count = 0;
+  if opts.include_history {
+    let path = config::history_file()?;
+    if path.exists() {
+      let data = fs::read(&path).map_err(|e| format!("读取历史记录失败：{}（{}）", path.display(), e))?;
+      runs_count = data.split(|b| *b == b'\n').filter(|l| !l.is_empty()).count();
+      add_bytes(&mut tar, "history.jsonl", &data)?;
+    }
+  }
+
+  let mut logs_count = 0;
+  if opts.include_logs {
+    if let Ok(entries) = fs::read_dir(config::logs_dir()?) {
+      for e in entries.map_while(Result::ok) {
+        let path = e.path();
+        let name = e.file_name().to_string_lossy().into_owned();
+        if !path.is_file() || !(name.ends_with(".log") || name.ends_with(".log.gz")) {
+          continue;
+        }
+        tar
+          .append_path_with_name(&path, format!("{}/logs/{}", ROOT, name))
+          .map_err(|e| format!("打包日志失败：{}（{}）", path.display(), e))?;
+        logs_count += 1;
+      }
+    }
+  }
+
+  let enc = tar.into_inner().map_err(|e| format!("写入导出文件失败（{}）", e))?;
+  enc.finish().map_err(|e| format!("写入导出文件失败（{}）", e))?;
+  fs::rename(&tmp, dest).map_err(|e| format!("写入导出文件失败：{}（{}）", dest.display(), e))?;
+
+  Ok(ExportResult {
+    path: dest.display().to_string(),
+    projects: cfg.projects.len(),
+    runs: runs_count,
+    logs: logs_count,
+    bytes: fs::metadata(dest).map(|m| m.len()).unwrap_or(0),
+  })
+}
+
+/// 包里读出来的东西（日志只记文件名，内容直接写进临时目录）。
+struct Unpacked {
+  info: BundleInfo,
+  config: AgentSyncConfig,
+  history: Vec<u8>,
+  logs: Vec<String>,
+}
+
+/// 解包日志用的临时目录（在 `~/.agentsync` 下，和日志目录同一个盘，挪过去只是改名），用完删掉。
+struct StagingDir(PathBuf);
+
+impl StagingDir {
+  fn new() -> Result<Self, String> {
+    let path = config::agentsync_dir()?.join(format!("import-{}", now_ms()));
+    config::ensure_dir(&path)?;
+    Ok(StagingDir(path))
+  }
+}
+
+impl Drop for StagingDir {
+  fn drop(&mut self) {
+    let _ = fs::remove_dir_all(&self.0);
+  }
+}
+
+/// `stage` 为空时（只看不写）日志只记文件名，不解出来。
+fn unpack(src: &Path, stage: Option<&Path>) -> Result<Unpacked, String> {
+  let file = fs::File::open(src).map_err(|e| format!("打开导入文件失败：{}（{}）", src.display(), e))?;
+  let mut archive = tar::Archive::new(GzDecoder::new(file));
+  let entries = archive
+    .entries()
+    .map_err(|e| format!("不是有效的 AgentSync 导出文件：{}（{}）", src.display(), e))?;
+
+  let mut info = None;
+  let mut config = None;
+  let mut history = Vec::new();
+  let mut logs = Vec::new();
+  for entry in entries {
+    let mut entry = entry.map_err(|e| format!("读取导入文件失败（{}）", e))?;
+    let name = entry
+      .path()
+      .map_err(|e| format!("读取导入文件失败（{}）", e))?
+      .to_string_lossy()
+      .into_owned();
+    let Some(name) = name.strip_prefix(ROOT).map(|n| n.trim_start_matches('/').to_string()) else {
+      continue;
+    };
+    if let Some(file) = name.strip_prefix("logs/") {
+      // 只认 logs/ 下面的一层文件名，防止包里带 `../` 之类的路径
+      if file.is_empty() || file.contains(['/', '\\']) || file == ".." {
+        continue;
+      }
+      if let Some(stage) = stage {
+        let path = stage.join(file);
+        let mut out =
+          fs::File::create(&path).map_err(|e| format!("解出日志失败：{}（{}）", path.display(), e))?;
+        std::io::copy(&mut entry, &mut out).map_err(|e| format!("解出日志失败：{}（{}）", name, e))?;
+      }
+      logs.push(file.to_string());
+      continue;
+    }
+    let mut data = Vec::new();
+    entry
+      .read_to_end(&mut data)
+      .map_err(|e| format!("读取导入文件失败：{}（{}）", name, e))?;
+    match name.as_str() {
+      "bundle.json" => {
+        info = Some(serde_json::from_slice::<BundleInfo>(&data).map_err(|e| format!("bundle.json 格式无效（{}）", e))?)
+      }
+      "config.json" => {
+        config = Some(serde_json::from_slice::<AgentSyncConfig>(&data).map_err(|e| format!("包里的配置格式无效（{}）", e))?)
+      }
+      "history.jsonl" => history = data,
+      _ => {}
+    }
+  }
+
+  let info = info.ok_or_else(|| format!("不是有效的 AgentSync 导出文件（缺少 bundle.json）：{}", src.display()))?;
+  if info.version > BUNDLE_VERSION {
+    return Err(format!("导出文件来自更新版本的 AgentSync（格式 {}），请先升级", info.version));
+  }
+  let config = config.ok_or_else(|| "导出文件里没有配置（config.json）".to_string())?;
+  Ok(Unpacked {
+    info,
+    config,
+    history,
+    logs,
+  })
+}
+
+/// `path` 以 `from` 开头（整段目录）时换成 `to`。
+fn remap_one(path: &str, from: &str, to: &str) -> Option<String> {
+  let from = from.trim_end_matches(['/', '\\']);
+  if from.is_empty() {
+    return None;
+  }
+  let rest = path.strip_prefix(from)?;
+  if !rest.is_empty() && !rest.starts_with(['/', '\\']) {
+    return None;
+  }
+  Some(format!("{}{}", to.trim_end_matches(['/', '\\']), rest))
+}
+
+fn remap(path: &str, opts: &ImportOptions, source_home: Option<&str>, local_home: Option<&str>) -> String {
+  opts
+    .path_map
+    .iter()
+    .find_map(|m| remap_one(path, &m.from, &m.to))
+    .or_else(|| remap_one(path, source_home?, local_home?))
+    .unwrap_or_else(|| path.to_string())
+}
+
+/// 把导出文件合并进本机配置（和历史、日志）。`dry_run` 时只返回会做什么。
+pub fn import(src: &Path, opts: &ImportOptions) -> Result<ImportResult, String> {
+  let staging = if opts.dry_run { None } else { Some(StagingDir::new()?) };
+  let Unpacked {
+    info,
+    config: theirs,
+    history,
+    logs,
+  } = unpack(src, staging.as_ref().map(|s| s.0.as_path()))?;
+  let mut cfg = config::load_or_default()?;
+  let local_home = dirs::home_dir().map(|h| h.display().to_string());
+
+  // 路径变量先合并：下面判断项目目录在不在要用到
+  let variables_added: Vec<String> = theirs
+    .variables
+    .keys()
+    .filter(|name| !cfg.variables.contains_key(*name))
+    .cloned()
+    .collect();
+  for name in &variables_added {
+    cfg.variables.insert(name.clone(), theirs.variables[name].clone());
+  }
+  let variables = cfg.variables.clone();
+  let expand = |path: &str| vars::expand_local(path, &variables).unwrap_or_else(|_| path.to_string());
+
+  // 项目
+  let mut projects_added = Vec::new();
+  let mut projects_skipped = Vec::new();
+  for p in &theirs.projects {
+    let local_path = remap(&p.local_path, opts, info.source_home.as_deref(), local_home.as_deref());
+    let exists = cfg
+      .projects
+      .iter()
+      .any(|mine| mine.id == p.id || Path::new(&expand(&mine.local_path)) == Path::new(&expand(&local_path)));
+    if exists {
+      projects_skipped.push(p.name.clone());
+      continue;
+    }
+    let missing = !Path::new(&expand(&local_path)).is_dir();
+    let mut project = p.clone();
+    project.local_path = local_path.clone();
+    project.enabled = p.enabled && !missing;
+    cfg.projects.push(project);
+    projects_added.push(ImportedProject {
+      id: p.id.clone(),
+      name: p.name.clone(),
+      source_path: p.local_path.clone(),
+      local_path,
+      missing,
+    });
+  }
+
+  // 排除规则、定时同步、单项命令：本机没有的才加
+  let excludes_added: Vec<String> = theirs
+    .excludes
+    .iter()
+    .filter(|e| !e.trim().is_empty() && !cfg.excludes.contains(e))
+    .cloned()
+    .collect();
+  cfg.excludes.extend(excludes_added.iter().cloned());
+
+  let new_schedules: Vec<_> = theirs
+    .schedules
+    .iter()
+    .filter(|s| cfg.schedules.iter().all(|mine| mine.id != s.id))
+    .cloned()
+    .collect();
+  let schedules_added = new_schedules.len();
+  cfg.schedules.extend(new_schedules);
+
+  // 单项命令会在本机 / 目标端执行：别人给的包不能悄悄带进来，默认只列出不导入
+  let mut hooks_added = Vec::new();
+  let mut hooks_ignored = Vec::new();
+  for (id, hooks) in &theirs.hooks.items {
+    if cfg.hooks.items.contains_key(id) {
+      continue;
+    }
+    let listed: Vec<ImportedHook> = [
+      (runs::HOOK_PRE_SYNC, &hooks.pre_sync),
+      (runs::HOOK_POST_SYNC_REMOTE, &hooks.post_sync_remote),
+    ]
+    .into_iter()
+    .filter(|(_, command)| !command.trim().is_empty())
+    .map(|(stage, command)| ImportedHook {
+      item_id: id.clone(),
+      stage: stage.to_string(),
+      command: command.clone(),
+    })
+    .collect();
+    if listed.is_empty() {
+      continue;
+    }
+    if opts.include_hooks {
+      cfg.hooks.items.insert(id.clone(), hooks.clone());
+      hooks_added.extend(listed);
+    } else {
+      hooks_ignored.extend(listed);
+    }
+  }
+
+  // 本机还没配目标电脑：连接和目标端目录都用包里的
+  let connection_imported = cfg.connection.host.trim().is_empty() && !theirs.connection.host.trim().is_empty();
+  if connection_imported {
+    let key_path = cfg.connection.key_path.clone();
+    cfg.connection = theirs.connection.clone();
+    if cfg.connection.key_path.trim().is_empty() {
+      cfg.connection.key_path = key_path;
+    }
+    cfg.remote = theirs.remote.clone();
+  }
+
+  // 历史：本机已有的 run_id 跳过；日志路径换成本机日志目录
+  let logs_dir = config::logs_dir()?;
+  let known: Vec<String> = if opts.include_history {
+    let all = runs::RunQuery {
+      include_archived: true,
+      ..Default::default()
+    };
+    runs::query_runs(&all)?.runs.into_iter().map(|r| r.run_id).collect()
+  } else {
+    vec![]
+  };
+  let mut imported_runs: BTreeMap<String, String> = BTreeMap::new();
+  if opts.include_history {
+    for line in String::from_utf8_lossy(&history).lines() {
+      let Ok(mut r) = serde_json::from_str::<SyncRunSummary>(line) else {
+        continue;
+      };
+      if known.contains(&r.run_id) || imported_runs.contains_key(&r.run_id) {
+        continue;
+      }
+      if let Some(name) = Path::new(&r.log_path).file_name() {
+        r.log_path = logs_dir.join(name).display().to_string();
+      }
+      let text = serde_json::to_string(&r).map_err(|e| format!("序列化历史记录失败（{}）", e))?;
+      imported_runs.insert(r.run_id.clone(), text);
+    }
+  }
+  let run_ids: Vec<&String> = imported_runs.keys().collect();
+  let logs: Vec<&String> = logs
+    .iter()
+    .filter(|name| {
+      let id = name.trim_end_matches(".gz").trim_end_matches(".log");
+      run_ids.iter().any(|r| r.as_str() == id) && !logs_dir.join(name).exists()
+    })
+    .collect();
+
+  let result = ImportResult {
+    dry_run: opts.dry_run,
+    bundle: info,
+    projects_added,
+    projects_skipped,
+    excludes_added,
+    variables_added,
+    schedules_added,
+    hooks_added,
+    hooks_ignored,
+    connection_imported,
+    runs_imported: imported_runs.len(),
+    logs_imported: logs.len(),
+  };
+  if opts.dry_run {
+    return Ok(result);
+  }
+
+  config::save_config(&cfg)?;
+  if !imported_runs.is_empty() {
+    let archive_dir = config::history_archive_dir()?;
+    config::ensure_dir(&archive_dir)?;
+    let path = archive_dir.join(format!("imported-{}.jsonl", now_ms()));
+    let text: String = imported_runs.values().map(|l| format!("{}\n", l)).collect();
+    fs::write(&path, text).map_err(|e| format!("写入导入的历史失败：{}（{}）", path.display(), e))?;
+  }
+  if let (false, Some(staging)) = (logs.is_empty(), &staging) {
+    config::ensure_dir(&logs_dir)?;
+    for name in logs {
+      let path = logs_dir.join(name);
+      fs::rename(staging.0.join(name), &path)
+        .map_err(|e| format!("写入导入的日志失败：{}（{}）", path.display(), e))?;
+    }
+  }
+  Ok(result)
+}
@@ -1,8 +1,12 @@
 //! AgentSync 配置：保存/读取本机配置文件（不包含任何密钥内容）。
 
+use crate::lockfile;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -12,18 +16,58 @@ pub struct ProjectItem {
   pub local_path: String,
   pub remote_dir_name: String,
   pub enabled: bool,
+  /// 项目是 git 仓库时 `.git` 怎么同步（见 `GitMode`）
+  #[serde(default)]
+  pub git: GitMode,
+}
+
+/// 项目里的 `.git` 怎么同步。默认排除规则里有 `.git/**`，目标端拿到的是没有历史的工作区。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GitMode {
+  /// 不特别处理（`.git` 按排除规则走：排除了就不同步，没排除就一个个对象文件传，很慢）
+  #[default]
+  Off,
+  /// 工作区照常同步（`.git` 强制排除），`.git` 打成一个 bundle 推过去，在目标端 fetch 进仓库，
+  /// 分支 / HEAD 和本机一致（目标端要装 git）
+  Bundle,
+  /// 工作区照常同步（`.git` 强制排除），目标端仓库里本机没有的提交打成 bundle 取回来，
+  /// 目标端的分支放到本机 `refs/remotes/agentsync/`，不动本机分支和工作区（目标端要装 git）
+  Fetch,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AgentSyncConfig {
   pub schema_version: u32,
+  /// 每保存一次加 1：保存时和磁盘上的对不上，说明别的窗口 / 命令行已经改过，拒绝覆盖
+  #[serde(default)]
+  pub revision: u64,
   pub rclone_path: Option<String>,
   pub connection: ConnectionConfig,
   pub remote: RemoteConfig,
+  #[serde(default)]
+  pub performance: PerformanceConfig,
   pub flags: FlagsConfig,
   pub excludes: Vec<String>,
   pub projects: Vec<ProjectItem>,
+  #[serde(default)]
+  pub watch: WatchConfig,
+  #[serde(default)]
+  pub schedules: Vec<ScheduleConfig>,
+  #[serde(default)]
+  pub hooks: HooksConfig,
+  #[serde(default)]
+  pub notifications: Vec<NotifyTarget>,
+  #[serde(default)]
+  pub log_retention: LogRetentionConfig,
+  /// 自定义变量：路径里写 `${名字}`，每台电脑填自己的值（例如 `drive` = `D:`、`code` = `~/work`）
+  #[serde(default)]
+  pub variables: BTreeMap<String, String>,
+  #[serde(default)]
+  pub discover: DiscoverConfig,
+  #[serde(default)]
+  pub estimate: EstimateConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,6 +79,13 @@ pub struct ConnectionConfig {
   pub key_path: String,
 }
 
+impl ConnectionConfig {
+  /// 目标电脑的简短写法 `user@host:port`（历史记录、通知里用）。
+  pub fn target_text(&self) -> String {
+    format!("{}@{}:{}", self.user.trim(), self.host.trim(), self.port)
+  }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RemoteConfig {
@@ -42,17 +93,215 @@ pub struct RemoteConfig {
   pub backup_root: String,
 }
 
+/// 传输调优（对当前目标生效）：不填就用 rclone 的默认值。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PerformanceConfig {
+  /// 限速，rclone `--bwlimit` 的写法：`10M`、`off`，或按时间段 `08:00,2M 18:00,off`（也可写 `Mon-08:00,2M`）
+  #[serde(default)]
+  pub bwlimit: String,
+  /// 同时传几个文件（rclone 默认 4）
+  #[serde(default)]
+  pub transfers: Option<u32>,
+  /// 同时比较几个文件（rclone 默认 8）
+  #[serde(default)]
+  pub checkers: Option<u32>,
+  /// 单个 SFTP 连接上同时挂几个请求（rclone 默认 64）
+  #[serde(default)]
+  pub sftp_concurrency: Option<u32>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FlagsConfig {
   pub mirror_delete: bool,
   pub sync_codex: bool,
   pub sync_agents: bool,
+  /// 按文件哈希（目标端的 md5sum / sha1sum）判断是否需要传输，而不是大小 + 修改时间。
+  /// 跨系统（比如 Windows -> macOS）修改时间精度不一致、老是重复传输时打开。
+  #[serde(default)]
+  pub checksum: bool,
+  /// 目标端在上次同步后被人改过的文件怎么处理（见 `DriftAction`）
+  #[serde(default)]
+  pub on_drift: DriftAction,
+}
+
+/// 同步前发现目标端有改动（文件修改时间晚于上次成功同步）时怎么办。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DriftAction {
+  /// 照常同步（改动会被本机版本替换，目标端的版本挪进备份目录），在结果里列出来
+  #[default]
+  Warn,
+  /// 有改动的项这次不同步
+  Skip,
+  /// 先把改动拉回本机再同步（本机被覆盖的版本另存到 `~/.agentsync/drift/<run_id>/`）
+  Pull,
+  /// 先把改动另存到本机 `~/.agentsync/drift/<run_id>/` 再同步
+  Backup,
+  /// 不检查（省一次目标端目录列表）
+  Off,
+}
+
+/// 监听模式：本机文件有改动、并且安静一段时间后，自动同步改动过的项。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchConfig {
+  pub enabled: bool,
+  pub quiet_secs: u64,
+}
+
+impl Default for WatchConfig {
+  fn default() -> Self {
+    WatchConfig {
+      enabled: false,
+      quiet_secs: 10,
+    }
+  }
+}
+
+/// 运行日志（`~/.agentsync/logs/`）保留多久：每次同步结束、守护进程启动时按这里清理一次。
+/// 数字填 0 表示不按这一条限制。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LogRetentionConfig {
+  /// 超过几天的日志压缩成 `.log.gz`（读日志时自动解压）
+  pub compress_after_days: u32,
+  /// 超过几天的日志删掉
+  pub max_age_days: u32,
+  /// 最多留几份日志（新的优先）
+  pub max_count: u32,
+  /// 所有日志加起来最多占多少 MB（超出时从最旧的删起）
+  pub max_total_mb: u64,
+}
+
+impl Default for LogRetentionConfig {
+  fn default() -> Self {
+    LogRetentionConfig {
+      compress_after_days: 1,
+      max_age_days: 90,
+      max_count: 0,
+      max_total_mb: 1024,
+    }
+  }
+}
+
+/// 同步前估算大小时的提醒阈值（MB，填 0 表示不按这一条提醒）。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EstimateConfig {
+  /// 单个同步项超过多少 MB 提醒（多半是忘了排除 `target/` 之类的目录）
+  pub warn_item_mb: u64,
+  /// 这次同步加起来超过多少 MB 提醒
+  pub warn_total_mb: u64,
+  /// 单个文件超过多少 MB 算大文件，列出来
+  pub huge_file_mb: u64,
+}
+
+impl Default for EstimateConfig {
+  fn default() -> Self {
+    EstimateConfig {
+      warn_item_mb: 1024,
+      warn_total_mb: 4096,
+      huge_file_mb: 100,
+    }
+  }
+}
+
+/// 自动发现项目：在这些目录下找 git 仓库和带 `AGENTS.md`、`.codex`、`.claude` 等标记的文件夹。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DiscoverConfig {
+  /// 扫描的根目录（可以写 `~`、`${HOME}`、自定义变量），不存在的跳过
+  pub roots: Vec<String>,
+  /// 往下找几层（根目录下面第一层算 1）
+  pub max_depth: u32,
+}
+
+impl Default for DiscoverConfig {
+  fn default() -> Self {
+    DiscoverConfig {
+      roots: ["~/code", "~/src", "~/projects", "~/dev", "~/work"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+      max_depth: 3,
+    }
+  }
+}
+
+/// 定时同步：`spec` 例如 `every 2h`、`daily 03:00`、`weekdays 18:00`、`mon,wed,fri 09:30`。
+/// `item_ids` 为空表示同步当前目标的全部启用项；否则只同步这些项（项目 id 或 `codex:sessions` 等）。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleConfig {
+  pub id: String,
+  pub name: String,
+  pub enabled: bool,
+  pub spec: String,
+  #[serde(default)]
+  pub item_ids: Vec<String>,
+}
+
+/// 同步前后要跑的命令（空字符串表示不跑）。
+/// - `pre_run` / `post_run`：整次同步开始前 / 结束后，在本机跑
+/// - `items`：按同步项 id（项目 id 或 `codex:sessions` 等）配置单项的命令
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HooksConfig {
+  #[serde(default)]
+  pub pre_run: String,
+  #[serde(default)]
+  pub post_run: String,
+  #[serde(default)]
+  pub items: BTreeMap<String, ItemHooks>,
+}
+
+/// 单个同步项的命令：
+/// - `pre_sync`：同步前在本机跑（工作目录是本机的项目目录）；失败就跳过这一项
+/// - `post_sync_remote`：同步成功后通过 SSH 在目标端跑（工作目录是目标端的项目目录），例如 `npm ci`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemHooks {
+  #[serde(default)]
+  pub pre_sync: String,
+  #[serde(default)]
+  pub post_sync_remote: String,
+}
+
+/// 同步结束后往外发通知的格式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NotifyKind {
+  /// 通用 JSON（POST 目标、用时、字节数、失败项等）
+  Webhook,
+  /// Slack 兼容的 Incoming Webhook（`{"text": "..."}`，飞书 / Mattermost 等也能用）
+  Slack,
+  /// ntfy（`url` 写完整的 topic 地址，例如 `https://ntfy.sh/my-agentsync`）
+  Ntfy,
+}
+
+/// 一个通知目标：每次同步结束、历史写入后发一次，失败会重试。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotifyTarget {
+  pub id: String,
+  pub name: String,
+  pub enabled: bool,
+  pub kind: NotifyKind,
+  pub url: String,
+  /// 只在同步失败时通知
+  #[serde(default)]
+  pub only_failures: bool,
+  /// 额外的请求头（比如鉴权用的 `Authorization`）
+  #[serde(default)]
+  pub headers: BTreeMap<String, String>,
 }
 
 pub fn default_config() -> AgentSyncConfig {
   AgentSyncConfig {
     schema_version: 1,
+    revision: 0,
     rclone_path: None,
     connection: ConnectionConfig {
       host: "".to_string(),
@@ -64,10 +313,13 @@ pub fn default_config() -> AgentSyncConfig {
       projects_root: "AgentSync/projects".to_string(),
       backup_root: "AgentSync/.agentsync-backup".to_string(),
     },
+    performance: PerformanceConfig::default(),
     flags: FlagsConfig {
       mirror_delete: true,
       sync_codex: true,
       sync_agents: true,
+      checksum: false,
+      on_drift: DriftAction::Warn,
     },
     excludes: vec![
       "node_modules/**".to_string(),
@@ -78,6 +330,14 @@ pub fn default_config() -> AgentSyncConfig {
       ".DS_Store".to_string(),
     ],
     projects: vec![],
+    watch: WatchConfig::default(),
+    schedules: vec![],
+    hooks: HooksConfig::default(),
+    notifications: vec![],
+    log_retention: LogRetentionConfig::default(),
+    variables: BTreeMap::new(),
+    discover: DiscoverConfig::default(),
+    estimate: EstimateConfig::default(),
   }
 }
 
@@ -94,14 +354,71 @@ pub fn history_file() -> Result<PathBuf, String> {
   Ok(agentsync_dir()?.join("history.jsonl"))
 }
 
+/// 整理历史时挪出去的旧记录：`history-archive/<YYYY-MM>.jsonl`
+pub fn history_archive_dir() -> Result<PathBuf, String> {
+  Ok(agentsync_dir()?.join("history-archive"))
+}
+
+/// 整理历史时发现的坏行原样存在这里，不直接丢掉
+pub fn history_corrupt_file() -> Result<PathBuf, String> {
+  Ok(agentsync_dir()?.join("history.corrupt.jsonl"))
+}
+
+/// 正在跑的同步的运行日志（`<run_id>.json`），正常结束后删除。
+pub fn runs_dir() -> Result<PathBuf, String> {
+  Ok(agentsync_dir()?.join("runs"))
+}
+
+/// 每次运行的文件清单：`manifests/<run_id>.jsonl`
+pub fn manifests_dir() -> Result<PathBuf, String> {
+  Ok(agentsync_dir()?.join("manifests"))
+}
+
+/// 目标端改动拉回 / 另存的位置：`drift/<run_id>/<备份子路径>/`
+pub fn drift_dir() -> Result<PathBuf, String> {
+  Ok(agentsync_dir()?.join("drift"))
+}
+
+pub fn schedule_state_file() -> Result<PathBuf, String> {
+  Ok(agentsync_dir()?.join("schedule-state.json"))
+}
+
 pub fn config_file() -> Result<PathBuf, String> {
   Ok(agentsync_dir()?.join("config.json"))
 }
 
 pub fn ensure_dir(path: &Path) -> Result<(), String> {
+  if let Ok(root) = agentsync_dir() {
+    if path.starts_with(&root) {
+      ensure_private_dir(&root)?;
+    }
+  }
   fs::create_dir_all(path).map_err(|e| format!("创建目录失败：{}（{}）", path.display(), e))
 }
 
+/// `~/.agentsync` 里有配置、日志和守护进程的 socket：只给自己访问（0700），以前按默认权限建的也改过来。
+fn ensure_private_dir(root: &Path) -> Result<(), String> {
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    fs::DirBuilder::new()
+      .recursive(true)
+      .mode(0o700)
+      .create(root)
+      .map_err(|e| format!("创建目录失败：{}（{}）", root.display(), e))?;
+    let mode = fs::metadata(root).map(|m| m.permissions().mode() & 0o777).unwrap_or(0o700);
+    if mode != 0o700 {
+      fs::set_permissions(root, fs::Permissions::from_mode(0o700))
+        .map_err(|e| format!("修改目录权限失败：{}（{}）", root.display(), e))?;
+    }
+    Ok(())
+  }
+  #[cfg(not(unix))]
+  {
+    fs::create_dir_all(root).map_err(|e| format!("创建目录失败：{}（{}）", root.display(), e))
+  }
+}
+
 pub fn load_or_default() -> Result<AgentSyncConfig, String> {
   let path = config_file()?;
   if !path.exists() {
@@ -114,12 +431,53 @@ pub fn load_or_default() -> Result<AgentSyncConfig, String> {
     .map_err(|e| format!("解析配置失败：{}（{}）", path.display(), e))
 }
 
-pub fn save_config(cfg: &AgentSyncConfig) -> Result<(), String> {
+/// 保存冲突的说明以这个开头（界面据此提示“重新载入”）。
+pub const CONFLICT_PREFIX: &str = "配置冲突";
+
+/// 磁盘上配置的版本号；文件不存在或解析不了时为空。
+pub fn disk_revision() -> Option<u64> {
+  let text = fs::read_to_string(config_file().ok()?).ok()?;
+  let v: serde_json::Value = serde_json::from_str(&text).ok()?;
+  Some(v.get("revision").and_then(|r| r.as_u64()).unwrap_or(0))
+}
+
+/// 保存配置，返回新的版本号。
+/// - `cfg.revision` 必须等于磁盘上的版本（读进来之后没人改过），否则报冲突
+/// - 先写临时文件再改名，写到一半崩溃也不会留下半截的 config.json
+/// - 从检查版本到改名都拿着 `config.json.lock`：界面和命令行同时保存时，后到的一方看到的是新版本，报冲突
+pub fn save_config(cfg: &AgentSyncConfig) -> Result<u64, String> {
   let dir = agentsync_dir()?;
   ensure_dir(&dir)?;
-  let path = dir.join("config.json");
-  let text = serde_json::to_string_pretty(cfg).map_err(|e| format!("序列化配置失败（{}）", e))?;
-  fs::write(&path, text).map_err(|e| format!("写入配置失败：{}（{}）", path.display(), e))?;
-  Ok(())
-}
+  let path = config_file()?;
+  let _lock = lockfile::lock(&path)?;
 
+  // 磁盘上的文件坏了（解析不了）时允许直接覆盖，不然永远存不进去
+  if let Some(disk) = disk_revision() {
+    if disk != cfg.revision {
+      return Err(format!(
+        "{}：配置已在别处（另一个窗口或命令行）修改过（磁盘上是第 {} 版，这里改的是第 {} 版），请重新载入后再保存",
+        CONFLICT_PREFIX, disk, cfg.revision
+      ));
+    }
+  }
+
+  let mut next = cfg.clone();
+  next.revision = cfg.revision + 1;
+  let text = serde_json::to_string_pretty(&next).map_err(|e| format!("序列化配置失败（{}）", e))?;
+  // 每次保存用自己的临时文件名，万一有不走锁的写入方也不会写坏别人的
+  let tmp = dir.join(format!("config.json.{}.tmp", Uuid::new_v4().simple()));
+  let write = || -> std::io::Result<()> {
+    let mut f = fs::File::create(&tmp)?;
+    f.write_all(text.as_bytes())?;
+    f.sync_all()
+  };
+  if let Err(e) = write() {
+    let _ = fs::remove_file(&tmp);
+    return Err(format!("写入配置失败：{}（{}）", tmp.display(), e));
+  }
+  if let Err(e) = fs::rename(&tmp, &path) {
+    let _ = fs::remove_file(&tmp);
+    return Err(format!("写入配置失败：{}（{}）", path.display(), e));
+  }
+  Ok(next.revision)
+}
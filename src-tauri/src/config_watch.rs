@@ -0,0 +1,63 @@
+//! 配置文件变化提醒（只在界面进程里用）。
+//!
+//! 大白话：命令行（`agentsync import` 等）或另一个窗口改了 `~/.agentsync/config.json`，
+//! 这边就发一个 `config-changed` 事件（带新的版本号），界面没有没保存的改动时自动重新载入。
+
+use crate::config;
+use notify::{Event, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::fs;
+use std::sync::mpsc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+pub const EVENT_CONFIG_CHANGED: &str = "config-changed";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigChanged {
+  revision: u64,
+}
+
+/// 开始监听配置文件（监听整个 `~/.agentsync`：保存是“写临时文件再改名”，直接盯文件会跟丢）。
+pub fn start(app: AppHandle) -> Result<(), String> {
+  let dir = config::agentsync_dir()?;
+  config::ensure_dir(&dir)?;
+  let file = config::config_file()?;
+
+  let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
+  let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+    let _ = tx.send(res);
+  })
+  .map_err(|e| format!("监听配置文件失败（{}）", e))?;
+  watcher
+    .watch(&dir, RecursiveMode::NonRecursive)
+    .map_err(|e| format!("监听配置文件失败：{}（{}）", dir.display(), e))?;
+
+  std::thread::spawn(move || {
+    // watcher 放进线程里：线程活着就一直监听
+    let _watcher = watcher;
+    let mut last = fs::read_to_string(&file).ok();
+    while let Ok(res) = rx.recv() {
+      let Ok(event) = res else {
+        continue;
+      };
+      if !event.paths.iter().any(|p| p == &file) {
+        continue;
+      }
+      // 一次保存会连着来好几个事件：稍等一下，把攒下的都丢掉再读
+      std::thread::sleep(Duration::from_millis(200));
+      while rx.try_recv().is_ok() {}
+
+      // 内容没变（例如只是 touch）不提醒；手改文件没动版本号也照样提醒
+      let text = fs::read_to_string(&file).ok();
+      if text.is_none() || text == last {
+        continue;
+      }
+      last = text;
+      let revision = config::disk_revision().unwrap_or(0);
+      let _ = app.emit(EVENT_CONFIG_CHANGED, ConfigChanged { revision });
+    }
+  });
+  Ok(())
+}
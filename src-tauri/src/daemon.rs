@@ -0,0 +1,309 @@
+//! 后台守护进程：同步引擎常驻在这里，界面和命令行都通过本机 IPC 跟它说话。
+//!
+//! 为什么要拆出来（大白话）：
+//! - 以前同步状态都在界面进程里（sync_manager 的静态变量），一关窗口正在跑的同步就没了
+//! - 现在同步、监听模式、定时同步都跑在守护进程里，窗口关了也照样继续
+//!
+//! 启动时会先把上次跑到一半中断的同步补记到历史里（见 journal）。
+//!
+//! 通信方式：
+//! - macOS / Linux：Unix socket（`~/.agentsync/daemon.sock`；目录是 0700、socket 是 0600，只有自己能连）
+//! - Windows：命名管道（`\\.\pipe\agentsync-daemon-<用户名>`；访问权限只给管道的所有者和 SYSTEM）
+//! - 一次连接 = 一行 JSON 请求 `{"method": "...", "params": {...}}` + 一行 JSON 回复
+//! - 请求里不收配置：同步、监听一律用磁盘上的配置（配置里有会被执行的命令，不能让连上来的人随便塞一份）
+
+use crate::config::{self, AgentSyncConfig, DriftAction};
+use crate::journal;
+use crate::logs;
+use crate::rclone::RunOptions;
+use crate::scheduler;
+use crate::sync_manager;
+use crate::watcher;
+use interprocess::local_socket::{prelude::*, GenericFilePath, GenericNamespaced, ListenerOptions, Name, Stream};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// 拉起守护进程后最多等这么久让它就绪。
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+struct Request {
+  method: String,
+  #[serde(default)]
+  params: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Response {
+  ok: bool,
+  #[serde(default)]
+  result: Value,
+  #[serde(default)]
+  error: Option<String>,
+}
+
+/// 不认识的字段（比如老版本界面带的 `config`）直接报错，不悄悄忽略
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct SyncStartParams {
+  item_ids: Option<Vec<String>>,
+  trigger: Option<String>,
+  /// 这次遇到目标端改动时怎么处理；不传用配置里的
+  #[serde(default)]
+  on_drift: Option<DriftAction>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoParams {}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RunIdParams {
+  run_id: String,
+}
+
+fn socket_path() -> Result<PathBuf, String> {
+  Ok(config::agentsync_dir()?.join("daemon.sock"))
+}
+
+fn socket_name() -> Result<Name<'static>, String> {
+  if cfg!(windows) {
+    let user = std::env::var("USERNAME").unwrap_or_else(|_| "default".to_string());
+    format!("agentsync-daemon-{}", user)
+      .to_ns_name::<GenericNamespaced>()
+      .map(|n| n.into_owned())
+      .map_err(|e| format!("生成命名管道名称失败（{}）", e))
+  } else {
+    let path = socket_path()?;
+    path
+      .clone()
+      .to_fs_name::<GenericFilePath>()
+      .map(|n| n.into_owned())
+      .map_err(|e| format!("生成 socket 路径失败：{}（{}）", path.display(), e))
+  }
+}
+
+fn parse_params<T: Default + for<'de> Deserialize<'de>>(params: Value) -> Result<T, String> {
+  if params.is_null() {
+    return Ok(T::default());
+  }
+  serde_json::from_value(params).map_err(|e| format!("请求参数无效（{}）", e))
+}
+
+fn to_value<T: Serialize>(v: T) -> Result<Value, String> {
+  serde_json::to_value(v).map_err(|e| format!("序列化回复失败（{}）", e))
+}
+
+/// 监听模式跟着配置走：开着就用新配置重启，关掉就停止。
+fn apply_watch_config(cfg: AgentSyncConfig) -> Result<(), String> {
+  if cfg.watch.enabled {
+    watcher::start(cfg)?;
+  } else if watcher::is_running() {
+    watcher::stop()?;
+  }
+  Ok(())
+}
+
+fn handle(req: Request) -> Result<Value, String> {
+  match req.method.as_str() {
+    "ping" => Ok(json!({ "pid": std::process::id() })),
+    "sync_start" => {
+      let p: SyncStartParams = parse_params(req.params)?;
+      let cfg = config::load_or_default()?;
+      let opts = RunOptions {
+        only: p.item_ids,
+        trigger: p.trigger,
+        on_drift: p.on_drift,
+      };
+      to_value(sync_manager::spawn_run(cfg, opts)?)
+    }
+    "sync_status" => to_value(sync_manager::get_status()?),
+    "sync_cancel" => to_value(sync_manager::cancel()?),
+    "run_resume" => {
+      let p: RunIdParams = parse_params(req.params)?;
+      to_value(journal::resume(&p.run_id)?)
+    }
+    "watch_start" => {
+      parse_params::<NoParams>(req.params)?;
+      to_value(watcher::start(config::load_or_default()?)?)
+    }
+    "watch_stop" => to_value(watcher::stop()?),
+    "watch_status" => to_value(watcher::status()?),
+    "schedule_status" => to_value(scheduler::status()?),
+    "config_reload" => {
+      apply_watch_config(config::load_or_default()?)?;
+      Ok(Value::Null)
+    }
+    "shutdown" => {
+      if sync_manager::is_running() {
+        return Err("有同步正在运行，暂不能退出守护进程".to_string());
+      }
+      // 先把回复发出去再退出
+      std::thread::spawn(|| {
+        std::thread::sleep(Duration::from_millis(200));
+        std::process::exit(0);
+      });
+      Ok(Value::Null)
+    }
+    other => Err(format!("未知的守护进程方法：{}", other)),
+  }
+}
+
+fn serve_conn(conn: Stream) -> Result<(), String> {
+  let mut conn = BufReader::new(conn);
+  let mut line = String::new();
+  conn
+    .read_line(&mut line)
+    .map_err(|e| format!("读取请求失败（{}）", e))?;
+
+  let resp = match serde_json::from_str::<Request>(line.trim()) {
+    Ok(req) => match handle(req) {
+      Ok(result) => Response {
+        ok: true,
+        result,
+        error: None,
+      },
+      Err(e) => Response {
+        ok: false,
+        result: Value::Null,
+        error: Some(e),
+      },
+    },
+    Err(e) => Response {
+      ok: false,
+      result: Value::Null,
+      error: Some(format!("请求格式无效（{}）", e)),
+    },
+  };
+
+  let mut text = serde_json::to_string(&resp).map_err(|e| format!("序列化回复失败（{}）", e))?;
+  text.push('\n');
+  conn
+    .get_mut()
+    .write_all(text.as_bytes())
+    .map_err(|e| format!("发送回复失败（{}）", e))
+}
+
+/// 以守护进程身份运行（阻塞，直到被 shutdown 或进程被杀）。
+pub fn serve() -> Result<(), String> {
+  if ping().is_ok() {
+    return Err("守护进程已经在运行了".to_string());
+  }
+  config::ensure_dir(&config::agentsync_dir()?)?;
+
+  // 上次异常退出会留下无人监听的 socket 文件，try_overwrite 负责清掉
+  let options = ListenerOptions::new().name(socket_name()?).try_overwrite(true);
+  #[cfg(windows)]
+  let options = {
+    use interprocess::os::windows::{local_socket::ListenerOptionsExt, security_descriptor::SecurityDescriptor};
+    // 只允许管道的所有者（也就是自己）和 SYSTEM 连接；P = 不继承上级的权限
+    let sddl = widestring::U16CString::from_str("D:P(A;;GA;;;OW)(A;;GA;;;SY)")
+      .map_err(|e| format!("生成命名管道权限失败（{}）", e))?;
+    let sd = SecurityDescriptor::deserialize(&sddl).map_err(|e| format!("生成命名管道权限失败（{}）", e))?;
+    options.security_descriptor(sd)
+  };
+  let listener = options
+    .create_sync()
+    .map_err(|e| format!("启动守护进程失败（{}）", e))?;
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    let path = socket_path()?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+      .map_err(|e| format!("设置 socket 权限失败：{}（{}）", path.display(), e))?;
+  }
+
+  // 上次守护进程 / 命令行同步跑到一半没了：先补记历史，再开始新的同步
+  let _ = journal::recover_interrupted();
+
+  if let Ok(cfg) = config::load_or_default() {
+    // 攒了很久的日志先按保留策略清理一遍（之后每次同步结束都会再清理）
+    let _ = logs::enforce(&cfg.log_retention, None);
+    if cfg.watch.enabled {
+      // 开不成的原因记在监听状态里（watch_status 的 lastError）
+      let _ = watcher::start(cfg);
+    }
+  }
+  scheduler::start();
+
+  for conn in listener.incoming() {
+    let Ok(conn) = conn else {
+      continue;
+    };
+    std::thread::spawn(move || {
+      let _ = serve_conn(conn);
+    });
+  }
+  Ok(())
+}
+
+/// 发一个请求给守护进程并等回复。
+pub fn request(method: &str, params: Value) -> Result<Value, String> {
+  let conn = Stream::connect(socket_name()?).map_err(|e| format!("连不上 AgentSync 守护进程（{}）", e))?;
+  let mut conn = BufReader::new(conn);
+
+  let mut text = serde_json::to_string(&json!({ "method": method, "params": params }))
+    .map_err(|e| format!("序列化请求失败（{}）", e))?;
+  text.push('\n');
+  conn
+    .get_mut()
+    .write_all(text.as_bytes())
+    .map_err(|e| format!("发送请求失败（{}）", e))?;
+
+  let mut line = String::new();
+  conn
+    .read_line(&mut line)
+    .map_err(|e| format!("读取守护进程回复失败（{}）", e))?;
+  let resp: Response =
+    serde_json::from_str(line.trim()).map_err(|e| format!("守护进程回复格式无效（{}）", e))?;
+  if resp.ok {
+    Ok(resp.result)
+  } else {
+    Err(resp.error.unwrap_or_else(|| "守护进程返回了未知错误".to_string()))
+  }
+}
+
+pub fn ping() -> Result<(), String> {
+  request("ping", Value::Null).map(|_| ())
+}
+
+/// 守护进程没在跑就拉起来：`exe args...` 应该进入 `serve()`。
+pub fn ensure_running(exe: &Path, args: &[&str]) -> Result<(), String> {
+  if ping().is_ok() {
+    return Ok(());
+  }
+
+  let mut cmd = Command::new(exe);
+  cmd
+    .args(args)
+    .stdin(Stdio::null())
+    .stdout(Stdio::null())
+    .stderr(Stdio::null());
+  #[cfg(windows)]
+  {
+    use std::os::windows::process::CommandExt;
+    // DETACHED_PROCESS | CREATE_NO_WINDOW：不弹黑窗口，也不跟着界面一起退出
+    cmd.creation_flags(0x0000_0008 | 0x0800_0000);
+  }
+  let mut child = cmd
+    .spawn()
+    .map_err(|e| format!("启动守护进程失败：{}（{}）", exe.display(), e))?;
+  // 守护进程退出时回收一下，避免留下僵尸进程
+  std::thread::spawn(move || {
+    let _ = child.wait();
+  });
+
+  let deadline = Instant::now() + STARTUP_TIMEOUT;
+  while Instant::now() < deadline {
+    std::thread::sleep(Duration::from_millis(100));
+    if ping().is_ok() {
+      return Ok(());
+    }
+  }
+  Err("守护进程启动超时，请稍后重试".to_string())
+}
@@ -0,0 +1,409 @@
+//! 测试连接的逐步诊断：一步步查，哪一步不通就停在哪一步，告诉用户是什么原因。
+//!
+//! 顺序（大白话）：
+//! 1. `dns`：主机名能不能解析成 IP
+//! 2. `tcp`：端口能不能连上（连接被拒一般是目标端没开“远程登录”）
+//! 3. `banner`：端口上是不是 SSH 服务（读第一行 `SSH-2.0-...`）
+//! 4. `hostKey`：目标电脑的身份指纹（`ssh-keyscan`），和记下的比（见 `known_hosts`）；没确认过、或者变了都停在这一步
+//! 5. `auth`：用私钥登录（`ssh ... exit`）
+//! 6. `sftp`：SFTP 能不能用（`rclone lsd`，同步走的就是它）
+//! 7. `writeProjectsRoot` / `writeBackupRoot`：两个目录能不能建、能不能写（真的写一个小文件再删掉）
+//!
+//! 每一步记耗时；失败时给一个固定的原因（`reason`），界面按原因给出怎么修。
+//! 这些都通过之后再做同步前检查（见 `preflight`）。
+
+use crate::config::AgentSyncConfig;
+use crate::hooks;
+use crate::known_hosts::{self, HostKeyInfo, HostKeyStatus};
+use crate::rclone;
+use serde::Serialize;
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// 连端口、等 SSH 问候各自最多等这么久
+const NET_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 所有步骤（含同步前检查）按顺序：（id, 名字）。没做到的步骤补成 `skipped`
+const ALL_STEPS: &[(&str, &str)] = &[
+  ("dns", "解析主机名"),
+  ("tcp", "连接端口"),
+  ("banner", "SSH 服务"),
+  ("hostKey", "主机指纹"),
+  ("auth", "私钥登录"),
+  ("sftp", "SFTP"),
+  ("writeProjectsRoot", "项目目录可写"),
+  ("writeBackupRoot", "备份目录可写"),
+  ("remoteOs", "目标端系统"),
+  ("diskSpace", "剩余空间"),
+  ("caseSensitivity", "大小写"),
+  ("fileNames", "文件名"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CheckStatus {
+  Ok,
+  Warn,
+  Fail,
+  Skipped,
+}
+
+/// 失败原因（界面按这个给修复建议）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FailureReason {
+  DnsFailed,
+  /// 端口连接被拒：目标端没开远程登录 / sshd 没在跑 / 端口写错
+  RemoteLoginDisabled,
+  Timeout,
+  NetworkUnreachable,
+  NotSsh,
+  /// 第一次连接，指纹还没确认过
+  HostKeyUnconfirmed,
+  HostKeyChanged,
+  /// 拿不到目标电脑的指纹（没有 ssh-keyscan 等）
+  HostKeyUnavailable,
+  KeyFileProblem,
+  /// 公钥不在目标端的 authorized_keys 里（或用户名不对）
+  KeyNotAuthorized,
+  SftpDisabled,
+  PermissionDenied,
+  DiskFull,
+  ToolMissing,
+  Other,
+}
+
+impl FailureReason {
+  /// 一句话说清楚是什么问题、大概怎么修。
+  pub fn hint(self) -> &'static str {
+    match self {
+      FailureReason::DnsFailed => "找不到这个主机名（看看 Host 有没有写错；.local 名字要两台电脑在同一个网络里）",
+      FailureReason::RemoteLoginDisabled => {
+        "端口连接被拒：目标端没开“远程登录”（macOS：系统设置 → 通用 → 共享 → 远程登录；Linux：启动 sshd），或者端口写错了"
+      }
+      FailureReason::Timeout => "连不上，等超时了（IP 写错、不在同一个网络，或者被防火墙挡了）",
+      FailureReason::NetworkUnreachable => "网络不通（本机到目标电脑没有路由）",
+      FailureReason::NotSsh => "这个端口上跑的不是 SSH 服务（端口写错了？）",
+      FailureReason::HostKeyUnconfirmed => {
+        "第一次连接这台电脑：请核对身份指纹（在目标电脑上跑 ssh-keygen -lf /etc/ssh/ssh_host_ed25519_key.pub），确认后记下"
+      }
+      FailureReason::HostKeyChanged => {
+        "目标电脑的身份指纹变了（重装过系统，或者有人在中间冒充），已拒绝连接；核对无误后重新确认"
+      }
+      FailureReason::HostKeyUnavailable => "拿不到目标电脑的身份指纹，没法核对",
+      FailureReason::KeyFileProblem => "私钥文件用不了（路径不对、格式不对，或者文件权限太宽）",
+      FailureReason::KeyNotAuthorized => {
+        "目标端不认这把钥匙：公钥没加进目标端的 ~/.ssh/authorized_keys（或者用户名写错了）"
+      }
+      FailureReason::SftpDisabled => "能登录但 SFTP 没开（目标端 sshd_config 里的 Subsystem sftp 被关了）",
+      FailureReason::PermissionDenied => "目录没有写权限（permission denied）",
+      FailureReason::DiskFull => "目标端磁盘满了",
+      FailureReason::ToolMissing => "本机找不到需要的程序",
+      FailureReason::Other => "失败",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionCheck {
+  /// 步骤 id，见 `ALL_STEPS`
+  pub id: String,
+  pub label: String,
+  pub status: CheckStatus,
+  pub message: String,
+  /// 失败原因（只有 `fail` 有）
+  pub reason: Option<FailureReason>,
+  /// 这一步用了多久
+  pub elapsed_ms: u64,
+  /// 有问题的文件（同步项名：相对路径，最多列 10 个）
+  pub examples: Vec<String>,
+}
+
+impl ConnectionCheck {
+  pub(crate) fn new(id: &str, label: &str, status: CheckStatus, message: impl Into<String>) -> Self {
+    ConnectionCheck {
+      id: id.to_string(),
+      label: label.to_string(),
+      status,
+      message: message.into(),
+      reason: None,
+      elapsed_ms: 0,
+      examples: vec![],
+    }
+  }
+
+  pub(crate) fn failed(id: &str, label: &str, reason: FailureReason, detail: &str) -> Self {
+    let message = if detail.trim().is_empty() {
+      reason.hint().to_string()
+    } else {
+      format!("{}：{}", reason.hint(), detail.trim())
+    };
+    let mut check = ConnectionCheck::new(id, label, CheckStatus::Fail, message);
+    check.reason = Some(reason);
+    check
+  }
+
+  pub(crate) fn timed(self, started: Instant) -> Self {
+    self.took(started.elapsed())
+  }
+
+  /// 耗时不是一段连续时间时（几步共用一次扫描）直接给总耗时
+  pub(crate) fn took(mut self, elapsed: Duration) -> Self {
+    self.elapsed_ms = elapsed.as_millis() as u64;
+    self
+  }
+}
+
+/// 一步的结果：成功（状态 + 说明）或失败（原因 + 原始报错）
+type StepOutcome = Result<(CheckStatus, String), (FailureReason, String)>;
+
+/// 按 ssh / rclone 的报错文字判断是什么原因。
+pub(crate) fn classify(stderr: &str) -> FailureReason {
+  let s = stderr.to_lowercase();
+  let has = |words: &[&str]| words.iter().any(|w| s.contains(w));
+  if has(&["remote host identification has changed", "host key verification failed", "key mismatch"]) {
+    FailureReason::HostKeyChanged
+  } else if has(&["load key", "bad permissions", "invalid format", "failed to read private key", "couldn't read ssh key"]) {
+    FailureReason::KeyFileProblem
+  } else if has(&["permission denied (publickey", "unable to authenticate", "no supported methods remain"]) {
+    FailureReason::KeyNotAuthorized
+  } else if has(&["could not resolve hostname", "no such host", "name or service not known"]) {
+    FailureReason::DnsFailed
+  } else if has(&["connection refused"]) {
+    FailureReason::RemoteLoginDisabled
+  } else if has(&["no route to host", "network is unreachable"]) {
+    FailureReason::NetworkUnreachable
+  } else if has(&["timed out", "i/o timeout"]) {
+    FailureReason::Timeout
+  } else if has(&["subsystem request failed"]) {
+    FailureReason::SftpDisabled
+  } else if has(&["no space left", "quota exceeded", "disk full"]) {
+    FailureReason::DiskFull
+  } else if has(&["permission denied", "access is denied"]) {
+    FailureReason::PermissionDenied
+  } else {
+    FailureReason::Other
+  }
+}
+
+/// 跑一步、计时、记下结果；返回这一步有没有通过（`fail` 以外都算通过）。
+fn step(checks: &mut Vec<ConnectionCheck>, id: &str, f: impl FnOnce() -> StepOutcome) -> bool {
+  let label = ALL_STEPS.iter().find(|(i, _)| *i == id).map(|(_, l)| *l).unwrap_or(id);
+  let started = Instant::now();
+  let check = match f() {
+    Ok((status, message)) => ConnectionCheck::new(id, label, status, message),
+    Err((reason, detail)) => ConnectionCheck::failed(id, label, reason, &detail),
+  };
+  let passed = check.status != CheckStatus::Fail;
+  checks.push(check.timed(started));
+  passed
+}
+
+fn tcp_connect(addrs: &[SocketAddr]) -> Result<TcpStream, (FailureReason, String)> {
+  let mut last = (FailureReason::Other, "没有可用的地址".to_string());
+  for addr in addrs {
+    match TcpStream::connect_timeout(addr, NET_TIMEOUT) {
+      Ok(s) => return Ok(s),
+      Err(e) => {
+        let reason = match e.kind() {
+          ErrorKind::ConnectionRefused => FailureReason::RemoteLoginDisabled,
+          ErrorKind::TimedOut | ErrorKind::WouldBlock => FailureReason::Timeout,
+          _ => match classify(&e.to_string()) {
+            FailureReason::Other => FailureReason::NetworkUnreachable,
+            r => r,
+          },
+        };
+        last = (reason, format!("{}（{}）", addr, e));
+      }
+    }
+  }
+  Err(last)
+}
+
+/// 读 SSH 服务的问候行（`SSH-2.0-...`）；前面可能有几行别的文字。
+fn read_banner(stream: &TcpStream) -> StepOutcome {
+  let _ = stream.set_read_timeout(Some(NET_TIMEOUT));
+  let mut reader = BufReader::new(stream);
+  let mut first = String::new();
+  for _ in 0..8 {
+    let mut line = String::new();
+    match reader.read_line(&mut line) {
+      Ok(0) => break,
+      Ok(_) => {
+        let line = line.trim_end().to_string();
+        if line.starts_with("SSH-") {
+          return Ok((CheckStatus::Ok, line));
+        }
+        if first.is_empty() {
+          first = line;
+        }
+      }
+      Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+        return Err((FailureReason::NotSsh, format!("连上了，但 {} 秒内没收到 SSH 问候", NET_TIMEOUT.as_secs())));
+      }
+      Err(e) => return Err((FailureReason::Other, e.to_string())),
+    }
+  }
+  Err((FailureReason::NotSsh, format!("收到的是：{}", if first.is_empty() { "（空）" } else { &first })))
+}
+
+fn host_key_step(host: &str, port: u16, info: &mut Option<HostKeyInfo>) -> StepOutcome {
+  let key = match known_hosts::check(host, port) {
+    Ok(k) => k,
+    Err(e) => return Err((FailureReason::HostKeyUnavailable, e)),
+  };
+  let shown = format!("{} {}", key.key_type, key.fingerprint);
+  let outcome = match key.status {
+    HostKeyStatus::Trusted => Ok((CheckStatus::Ok, format!("{}；和记下的一致", shown))),
+    HostKeyStatus::New => Err((FailureReason::HostKeyUnconfirmed, shown)),
+    HostKeyStatus::Changed => Err((
+      FailureReason::HostKeyChanged,
+      format!(
+        "记下的是 {}，现在是 {}",
+        key.pinned_fingerprint.as_deref().unwrap_or("（读不出来）"),
+        shown
+      ),
+    )),
+  };
+  *info = Some(key);
+  outcome
+}
+
+fn auth_step(cfg: &AgentSyncConfig) -> StepOutcome {
+  let key_path = cfg.connection.key_path.trim();
+  if !Path::new(key_path).is_file() {
+    return Err((FailureReason::KeyFileProblem, format!("文件不存在：{}", key_path)));
+  }
+  // `exit` 在 sh、cmd、PowerShell 里都能跑
+  let out = match hooks::ssh_command(cfg, "exit").output() {
+    Ok(o) => o,
+    Err(e) => {
+      return Ok((
+        CheckStatus::Skipped,
+        format!("本机没有 ssh 命令，没查（{}）；同步用 rclone 自带的 SSH，不受影响", e),
+      ))
+    }
+  };
+  if out.status.success() {
+    return Ok((CheckStatus::Ok, format!("用私钥登录成功（{}）", cfg.connection.user.trim())));
+  }
+  let err = String::from_utf8_lossy(&out.stderr).trim().to_string();
+  match classify(&err) {
+    // ssh 自己出错时退出码是 255；别的退出码是登录成功了、只是命令没跑成（比如只允许 SFTP）
+    FailureReason::Other if out.status.code() != Some(255) => {
+      Ok((CheckStatus::Warn, format!("能登录，但跑不了命令（git bundle、目标端命令用不了）：{}", err)))
+    }
+    reason => Err((reason, err)),
+  }
+}
+
+fn rclone_step(rclone_path: &Path, args: &[String]) -> Result<std::process::Output, (FailureReason, String)> {
+  let out = rclone::rclone_output(rclone_path, args).map_err(|e| (FailureReason::ToolMissing, e))?;
+  if out.status.success() {
+    Ok(out)
+  } else {
+    let err = String::from_utf8_lossy(&out.stderr).trim().to_string();
+    Err((classify(&err), err))
+  }
+}
+
+/// 在目标端建目录，写一个小文件再删掉。
+fn write_step(cfg: &AgentSyncConfig, rclone_path: &Path, rclone_conf: &Path, root: &str) -> StepOutcome {
+  let run = |cmd: &str, target: String| {
+    let mut args = rclone::make_common_args(cfg, rclone_conf);
+    args.extend([cmd.to_string(), format!("remote:{}", target)]);
+    rclone_step(rclone_path, &args).map(|_| ())
+  };
+  let probe = rclone::join_remote(root, &format!(".agentsync-write-{}", Uuid::new_v4().simple()));
+  run("mkdir", root.to_string())?;
+  run("touch", probe.clone())?;
+  run("deletefile", probe)?;
+  let shown = if root.is_empty() { "~".to_string() } else { format!("~/{}", root) };
+  Ok((CheckStatus::Ok, format!("{} 能创建、能写入", shown)))
+}
+
+/// 按顺序跑网络、指纹、登录、SFTP、写入这几步，哪步失败就停。`cfg` 是展开过变量的配置。
+/// 同时返回拿到的目标电脑指纹（界面据此让用户确认）。
+pub(crate) fn run(
+  cfg: &AgentSyncConfig,
+  rclone_path: &Path,
+  rclone_conf: &Path,
+) -> (Vec<ConnectionCheck>, Option<HostKeyInfo>) {
+  let mut checks = Vec::new();
+  let mut host_key = None;
+  let host = cfg.connection.host.trim();
+  let port = cfg.connection.port;
+
+  let mut addrs: Vec<SocketAddr> = Vec::new();
+  let ok = step(&mut checks, "dns", || {
+    addrs = (host, port)
+      .to_socket_addrs()
+      .map_err(|e| (FailureReason::DnsFailed, e.to_string()))?
+      .collect();
+    if addrs.is_empty() {
+      return Err((FailureReason::DnsFailed, String::new()));
+    }
+    let ips: Vec<String> = addrs.iter().map(|a| a.ip().to_string()).collect();
+    Ok((CheckStatus::Ok, format!("{} → {}", host, ips.join("、"))))
+  });
+  if !ok {
+    return (checks, host_key);
+  }
+
+  let mut stream: Option<TcpStream> = None;
+  let ok = step(&mut checks, "tcp", || {
+    let s = tcp_connect(&addrs)?;
+    let peer = s.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+    stream = Some(s);
+    Ok((CheckStatus::Ok, format!("{} 端口能连上", peer)))
+  });
+  let Some(stream) = stream.filter(|_| ok) else {
+    return (checks, host_key);
+  };
+
+  if !step(&mut checks, "banner", || read_banner(&stream)) {
+    return (checks, host_key);
+  }
+  drop(stream);
+
+  if !step(&mut checks, "hostKey", || host_key_step(host, port, &mut host_key)) {
+    return (checks, host_key);
+  }
+  if !step(&mut checks, "auth", || auth_step(cfg)) {
+    return (checks, host_key);
+  }
+
+  let ok = step(&mut checks, "sftp", || {
+    let mut args = rclone::make_common_args(cfg, rclone_conf);
+    args.extend(["lsd".to_string(), "remote:".to_string()]);
+    rclone_step(rclone_path, &args)?;
+    Ok((CheckStatus::Ok, "SFTP 能用".to_string()))
+  });
+  if !ok {
+    return (checks, host_key);
+  }
+
+  let projects_root = rclone::normalize_remote_path(&cfg.remote.projects_root);
+  let backup_root = rclone::normalize_remote_path(&cfg.remote.backup_root);
+  step(&mut checks, "writeProjectsRoot", || write_step(cfg, rclone_path, rclone_conf, &projects_root));
+  step(&mut checks, "writeBackupRoot", || write_step(cfg, rclone_path, rclone_conf, &backup_root));
+  (checks, host_key)
+}
+
+/// 没做到的步骤补成 `skipped`，按固定顺序排好。
+pub(crate) fn fill_skipped(checks: Vec<ConnectionCheck>) -> Vec<ConnectionCheck> {
+  let mut out = Vec::with_capacity(ALL_STEPS.len());
+  let mut rest = checks;
+  for (id, label) in ALL_STEPS {
+    match rest.iter().position(|c| c.id == *id) {
+      Some(i) => out.push(rest.remove(i)),
+      None => out.push(ConnectionCheck::new(id, label, CheckStatus::Skipped, "前面的步骤没通过，没做")),
+    }
+  }
+  out.extend(rest);
+  out
+}
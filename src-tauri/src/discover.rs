@@ -0,0 +1,328 @@
+//! 自动发现要同步的项目：一个个点“添加项目”选 30 个文件夹太累，扫一遍直接勾选。
+//!
+//! 找什么（大白话）：
+//! - `discover.roots`（默认 `~/code`、`~/src` 等）下面的 git 仓库，和带 `AGENTS.md`、`CLAUDE.md`、`.codex`、`.claude` 等标记的文件夹；
+//!   找到一个就不再往它里面找（里面的子仓库会跟着它一起同步）。隐藏目录和排除规则命中的目录（`node_modules` 等）不进去
+//! - 用户目录下已知工具的数据目录：`~/.codex`、`~/.agents` 对应现有的开关；`~/.claude` 等作为普通项目提议
+//!
+//! 每一项给出建议的 `ProjectItem`（目标端目录名不和已有项目、其他提议重复）和按排除规则算的大小。
+//! 已经在同步的（和已有项目是同一个目录、在已有项目里面、或者把已有项目包在里面）会标出来。
+
+use crate::config::{AgentSyncConfig, GitMode, ProjectItem};
+use crate::estimate;
+use crate::excludes::ExcludeMatcher;
+use crate::vars;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// 带这些东西的文件夹算一个项目
+const MARKERS: &[&str] = &[".git", "AGENTS.md", "CLAUDE.md", ".codex", ".claude", ".cursor"];
+
+/// 依赖 / 构建产物目录：排除规则里没写也不往里找（里面的 git 仓库是依赖，不是你的项目）
+const SKIP_DIRS: &[&str] = &["node_modules", "vendor", "target", "__pycache__"];
+
+/// 用户目录下一个已知工具的数据目录
+struct AgentData {
+  dir: &'static str,
+  tool: &'static str,
+  /// 对应的内置开关
+  flag: Option<&'static str>,
+  note: Option<&'static str>,
+  /// 登录信息文件：加进配置时一起加成排除规则，不然下次同步就把登录凭据推到目标端了
+  secrets: &'static [&'static str],
+}
+
+const AGENT_DATA: &[AgentData] = &[
+  AgentData {
+    dir: ".codex",
+    tool: "Codex",
+    flag: Some("syncCodex"),
+    note: Some("用“同步 Codex”开关同步（只同步 config.toml 和 sessions/，不带登录信息）"),
+    secrets: &[],
+  },
+  AgentData { dir: ".agents", tool: ".agents", flag: Some("syncAgents"), note: None, secrets: &[] },
+  AgentData {
+    dir: ".claude",
+    tool: "Claude Code",
+    flag: None,
+    note: Some("里面有登录信息（.credentials.json），加进来时会同时加排除规则 `.credentials.json`，到目标端重新登录"),
+    secrets: &[".credentials.json"],
+  },
+  AgentData {
+    dir: ".gemini",
+    tool: "Gemini CLI",
+    flag: None,
+    note: Some("里面可能有登录信息（oauth_creds.json），加进来时会同时加排除规则 `oauth_creds.json`"),
+    secrets: &["oauth_creds.json"],
+  },
+  AgentData { dir: ".cursor", tool: "Cursor", flag: None, note: None, secrets: &[] },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiscoveredKind {
+  Project,
+  AgentData,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredItem {
+  pub kind: DiscoveredKind,
+  /// 数据目录属于哪个工具（项目为空）
+  pub tool: Option<String>,
+  /// 命中的标记，例如 `.git`、`AGENTS.md`
+  pub markers: Vec<String>,
+  /// 建议加进配置的项目（`builtinFlag` 不为空时不用加项目，打开开关就行）
+  pub proposal: ProjectItem,
+  /// 对应的内置开关：`syncCodex` / `syncAgents`
+  pub builtin_flag: Option<String>,
+  /// 按排除规则算的大小
+  pub bytes: u64,
+  pub files: u64,
+  /// 文件太多，大小只数了一部分
+  pub size_truncated: bool,
+  /// 已经在同步了：已有项目的 id，或者 `flags.syncCodex` 这类开关
+  pub covered_by: Option<String>,
+  pub note: Option<String>,
+  /// 加进配置时要一起加的排除规则（登录信息文件）
+  pub excludes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoverResult {
+  /// 实际扫描了的根目录（展开变量后，存在的）
+  pub roots: Vec<String>,
+  pub items: Vec<DiscoveredItem>,
+  /// 根目录展开失败、读不了某个目录等（不影响其他结果）
+  pub errors: Vec<String>,
+}
+
+fn markers_of(dir: &Path) -> Vec<String> {
+  MARKERS
+    .iter()
+    .filter(|m| dir.join(m).exists())
+    .map(|m| m.to_string())
+    .collect()
+}
+
+fn is_hidden(name: &str) -> bool {
+  name.starts_with('.')
+}
+
+/// 在一个根目录下找项目：找到就不再往里找；隐藏目录、排除规则命中的目录不进去。
+/// 用户目录本身不算项目（它下面的 `.codex`、`.claude` 是工具数据，单独列）。
+fn scan_root(
+  root: &Path,
+  home: &Path,
+  max_depth: u32,
+  matcher: &ExcludeMatcher,
+  out: &mut Vec<(PathBuf, Vec<String>)>,
+) {
+  let mut stack = vec![(root.to_path_buf(), 0u32)];
+  while let Some((dir, depth)) = stack.pop() {
+    let markers = markers_of(&dir);
+    if !markers.is_empty() && canonical(&dir) != home {
+      out.push((dir, markers));
+      continue;
+    }
+    if depth >= max_depth {
+      continue;
+    }
+    let Ok(entries) = fs::read_dir(&dir) else {
+      continue;
+    };
+    for entry in entries.map_while(Result::ok) {
+      let name = entry.file_name().to_string_lossy().into_owned();
+      let path = entry.path();
+      let rel = path.strip_prefix(root).unwrap_or(&path);
+      // file_type 不跟符号链接：链接过去的目录不进去，避免绕圈
+      if is_hidden(&name)
+        || SKIP_DIRS.contains(&name.as_str())
+        || matcher.is_excluded(rel)
+        || !entry.file_type().is_ok_and(|t| t.is_dir())
+      {
+        continue;
+      }
+      stack.push((path, depth + 1));
+    }
+  }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+  fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn file_name(path: &Path) -> String {
+  path
+    .file_name()
+    .map(|n| n.to_string_lossy().into_owned())
+    .unwrap_or_else(|| "project".to_string())
+}
+
+/// 目标端目录名：先用文件夹名；重复了用 `上一级-文件夹名`，还重复就加 `-2`、`-3`。
+fn pick_remote_name(path: &Path, preferred: &str, used: &mut BTreeSet<String>) -> String {
+  let mut candidates = vec![preferred.to_string()];
+  if let Some(parent) = path.parent().and_then(|p| p.file_name()) {
+    candidates.push(format!("{}-{}", parent.to_string_lossy().trim_start_matches('.'), preferred));
+  }
+  let name = candidates
+    .into_iter()
+    .find(|c| !used.contains(&c.to_lowercase()))
+    .unwrap_or_else(|| {
+      (2..)
+        .map(|n| format!("{}-{}", preferred, n))
+        .find(|c| !used.contains(&c.to_lowercase()))
+        .unwrap_or_default()
+    });
+  // 目标端可能不区分大小写：按小写比较
+  used.insert(name.to_lowercase());
+  name
+}
+
+/// 扫描一遍，返回建议加进来的项目。`roots` / `max_depth` 为空时用配置里的 `discover`。
+pub fn discover(cfg: &AgentSyncConfig, roots: Option<&[String]>, max_depth: Option<u32>) -> Result<DiscoverResult, String> {
+  let home = dirs::home_dir().ok_or_else(|| "找不到本机用户目录（home directory）".to_string())?;
+  let matcher = ExcludeMatcher::new(&cfg.excludes)?;
+  let max_depth = max_depth.unwrap_or(cfg.discover.max_depth);
+  let mut result = DiscoverResult::default();
+
+  // 已有项目：按真实路径比较（配置里可能写了变量）
+  let existing: Vec<(PathBuf, &str)> = cfg
+    .projects
+    .iter()
+    .filter_map(|p| {
+      let path = vars::expand_local(&p.local_path, &cfg.variables).ok()?;
+      Some((canonical(Path::new(path.trim())), p.id.as_str()))
+    })
+    .collect();
+  // 两个方向都算：在已有项目里面，或者把已有项目包在里面（都加的话同一批文件会同步两遍）
+  let covering = |path: &Path| -> Option<String> {
+    let real = canonical(path);
+    existing
+      .iter()
+      .find(|(dir, _)| real.starts_with(dir) || dir.starts_with(&real))
+      .map(|(_, id)| id.to_string())
+  };
+  let mut used: BTreeSet<String> = cfg.projects.iter().map(|p| p.remote_dir_name.to_lowercase()).collect();
+
+  // 1) 根目录下的项目
+  let mut found: Vec<(PathBuf, Vec<String>)> = Vec::new();
+  for raw in roots.unwrap_or(&cfg.discover.roots) {
+    let root = match vars::expand_local(raw, &cfg.variables) {
+      Ok(r) => PathBuf::from(r.trim()),
+      Err(e) => {
+        result.errors.push(format!("扫描目录 {} 展开失败：{}", raw, e));
+        continue;
+      }
+    };
+    if !root.is_dir() {
+      continue;
+    }
+    result.roots.push(root.display().to_string());
+    scan_root(&root, &canonical(&home), max_depth, &matcher, &mut found);
+  }
+  found.sort();
+  found.dedup_by(|a, b| canonical(&a.0) == canonical(&b.0));
+
+  for (dir, markers) in found {
+    let name = file_name(&dir);
+    let covered_by = covering(&dir);
+    let remote_dir_name = match covered_by {
+      Some(_) => name.clone(),
+      None => pick_remote_name(&dir, &name, &mut used),
+    };
+    let size = estimate::measure(&dir, &matcher, 0);
+    result.items.push(DiscoveredItem {
+      kind: DiscoveredKind::Project,
+      tool: None,
+      markers,
+      proposal: ProjectItem {
+        id: Uuid::new_v4().simple().to_string(),
+        name,
+        local_path: dir.display().to_string(),
+        remote_dir_name,
+        enabled: true,
+        git: GitMode::Off,
+      },
+      builtin_flag: None,
+      bytes: size.bytes,
+      files: size.files,
+      size_truncated: size.truncated,
+      covered_by,
+      note: None,
+      excludes: vec![],
+    });
+  }
+
+  // 2) 已知工具的数据目录
+  for AgentData { dir: dir_name, tool, flag, note, secrets } in AGENT_DATA {
+    let dir = home.join(dir_name);
+    if !dir.is_dir() {
+      continue;
+    }
+    let flag_on = match *flag {
+      Some("syncCodex") => cfg.flags.sync_codex,
+      Some("syncAgents") => cfg.flags.sync_agents,
+      _ => false,
+    };
+    let covered_by = if flag_on {
+      flag.map(|f| format!("flags.{}", f))
+    } else {
+      covering(&dir)
+    };
+    let short = dir_name.trim_start_matches('.');
+    let remote_dir_name = if flag.is_some() || covered_by.is_some() {
+      format!("agent-data/{}", short)
+    } else {
+      pick_remote_name(&dir, &format!("agent-data/{}", short), &mut used)
+    };
+    let size = estimate::measure(&dir, &matcher, 0);
+    result.items.push(DiscoveredItem {
+      kind: DiscoveredKind::AgentData,
+      tool: Some(tool.to_string()),
+      markers: vec![],
+      proposal: ProjectItem {
+        id: Uuid::new_v4().simple().to_string(),
+        name: tool.to_string(),
+        local_path: dir.display().to_string(),
+        remote_dir_name,
+        enabled: true,
+        git: GitMode::Off,
+      },
+      builtin_flag: flag.map(str::to_string),
+      bytes: size.bytes,
+      files: size.files,
+      size_truncated: size.truncated,
+      covered_by,
+      note: note.map(str::to_string),
+      excludes: secrets.iter().map(|s| s.to_string()).collect(),
+    });
+  }
+
+  Ok(result)
+}
+
+/// 把发现的、还没在同步的都加进配置（内置开关的打开开关），返回加了几项。不保存。
+/// 带登录信息的数据目录同时加上对应的排除规则（已经有的不重复加）。
+pub fn apply(cfg: &mut AgentSyncConfig, items: &[DiscoveredItem]) -> usize {
+  let mut added = 0;
+  for item in items.iter().filter(|i| i.covered_by.is_none()) {
+    match item.builtin_flag.as_deref() {
+      Some("syncCodex") => cfg.flags.sync_codex = true,
+      Some("syncAgents") => cfg.flags.sync_agents = true,
+      _ => cfg.projects.push(item.proposal.clone()),
+    }
+    for rule in &item.excludes {
+      if !cfg.excludes.iter().any(|e| e.trim() == rule) {
+        cfg.excludes.push(rule.clone());
+      }
+    }
+    added += 1;
+  }
+  added
+}
@@ -0,0 +1,274 @@
+//! 目标端改动（drift）：上次同步之后，有人直接在目标端改了文件。
+//!
+//! 判断方法（大白话）：
+//! - AgentSync 往目标端写文件时会带上本机的修改时间，所以目标端文件的修改时间不会晚于那次同步结束
+//! - 目标端某个文件的修改时间晚于“这一项上次成功同步的结束时间”，就说明是别人改的
+//! - 本机同名文件大小、修改时间都一样的不算（两边已经一致）
+//!
+//! 发现改动后按 `flags.onDrift`（或这次运行单独指定的）处理：照常覆盖并列出来 / 跳过这一项 /
+//! 先拉回本机 / 先另存到本机 `~/.agentsync/drift/<run_id>/`。
+
+use crate::config::{self, AgentSyncConfig, DriftAction};
+use crate::rclone::{self, PlannedItem, SyncProgress};
+use crate::runs::{self, DriftFile};
+use crate::vars;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use uuid::Uuid;
+
+/// 两边时钟 / SFTP 修改时间精度（秒）的误差，在这个范围内的不算改动。
+const SLACK_MS: i64 = 2_000;
+
+/// 一个同步项的改动检查结果。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftItemReport {
+  pub item_id: String,
+  pub label: String,
+  /// 上次成功同步的结束时间；从没同步成功过的项不检查
+  pub last_synced_at_ms: Option<u64>,
+  pub files: Vec<DriftFile>,
+  pub error: Option<String>,
+}
+
+/// 调 rclone 需要的东西（同步过程中一直不变）。
+pub(crate) struct Remote<'a> {
+  pub cfg: &'a AgentSyncConfig,
+  pub rclone: &'a Path,
+  pub rclone_conf: &'a Path,
+}
+
+/// 同步前检查的结论：`blocked` 有值时这一项不要同步，`note` 追加到这一项的结果说明里。
+pub(crate) struct Prepared {
+  pub files: Vec<DriftFile>,
+  pub blocked: Option<String>,
+  pub note: Option<String>,
+}
+
+/// 改动检查 / 拉回都在“根目录”下按相对路径进行：目录项就是它自己，单文件项是它所在的目录。
+fn roots(item: &PlannedItem) -> (PathBuf, String) {
+  if item.is_file {
+    let local = item.local.parent().map(Path::to_path_buf).unwrap_or_default();
+    let remote = item.remote_dest.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+    (local, remote.to_string())
+  } else {
+    (item.local.clone(), item.remote_dest.clone())
+  }
+}
+
+fn mtime_ms(path: &Path) -> Option<i64> {
+  let t = fs::metadata(path).ok()?.modified().ok()?;
+  Some(t.duration_since(UNIX_EPOCH).ok()?.as_millis() as i64)
+}
+
+fn same_as_local(local: &Path, size: i64, remote_ms: i64) -> bool {
+  let Ok(meta) = fs::metadata(local) else {
+    return false;
+  };
+  meta.is_file()
+    && meta.len() as i64 == size
+    && mtime_ms(local).is_some_and(|ms| (ms - remote_ms).abs() <= SLACK_MS)
+}
+
+/// 列出目标端这一项里修改时间晚于 `since_ms` 的文件（遵守排除规则）。目标端还没有这一项时返回空。
+pub(crate) fn find(remote: &Remote, item: &PlannedItem, since_ms: u64) -> Result<Vec<DriftFile>, String> {
+  let mut extra: Vec<String> = Vec::new();
+  if !item.is_file {
+    extra.push("-R".to_string());
+    extra.push("--files-only".to_string());
+    extra.extend(rclone::exclude_args(remote.cfg, item));
+  }
+  let extra: Vec<&str> = extra.iter().map(String::as_str).collect();
+  let entries = match rclone::rclone_lsjson(remote.rclone, remote.rclone_conf, &item.remote_dest, &extra) {
+    Ok(v) => v,
+    // 第一次同步前目标端还没有这个目录 / 文件
+    Err(e) if e.contains("not found") => return Ok(vec![]),
+    Err(e) => return Err(e),
+  };
+
+  let (local_root, _) = roots(item);
+  let mut out = Vec::new();
+  for e in entries.into_iter().filter(|e| !e.is_dir) {
+    let Some(mod_time) = e.mod_time else {
+      continue;
+    };
+    let Ok(t) = chrono::DateTime::parse_from_rfc3339(&mod_time) else {
+      continue;
+    };
+    let remote_ms = t.timestamp_millis();
+    if remote_ms <= since_ms as i64 + SLACK_MS {
+      continue;
+    }
+    let local = local_root.join(&e.path);
+    if same_as_local(&local, e.size, remote_ms) {
+      continue;
+    }
+    out.push(DriftFile {
+      path: e.path,
+      size: e.size,
+      mod_time,
+      local_exists: local.is_file(),
+    });
+  }
+  out.sort_by(|a, b| a.path.cmp(&b.path));
+  Ok(out)
+}
+
+/// 把目标端改过的文件复制到本机 `dest`（`--files-from-raw`，只复制列出来的文件）。
+fn copy_back(
+  remote: &Remote,
+  item: &PlannedItem,
+  files: &[DriftFile],
+  dest: &Path,
+  backup_dir: Option<&Path>,
+  log: &mut fs::File,
+  progress: &mut dyn SyncProgress,
+) -> Result<(), String> {
+  let list_path = std::env::temp_dir().join(format!("agentsync-drift-{}.txt", Uuid::new_v4().simple()));
+  let list: String = files.iter().map(|f| format!("{}\n", f.path)).collect();
+  fs::write(&list_path, list).map_err(|e| format!("写入临时文件失败：{}（{}）", list_path.display(), e))?;
+
+  let (_, remote_root) = roots(item);
+  let mut args = rclone::make_common_args(remote.cfg, remote.rclone_conf);
+  args.push("copy".to_string());
+  args.push(format!("remote:{}", remote_root));
+  args.push(dest.display().to_string());
+  args.push("--files-from-raw".to_string());
+  args.push(list_path.display().to_string());
+  if let Some(dir) = backup_dir {
+    args.push("--backup-dir".to_string());
+    args.push(dir.display().to_string());
+  }
+
+  let label = format!("{}（拉回目标端改动）", item.label);
+  let r = rclone::run_one_stream(remote.rclone, log, &item.id, &label, &args, progress);
+  let _ = fs::remove_file(&list_path);
+  if r.ok {
+    Ok(())
+  } else {
+    Err(r.message)
+  }
+}
+
+/// 同步这一项之前调用：找改动，按 `action` 处理。检查本身失败时只写日志，照常同步。
+pub(crate) fn prepare(
+  remote: &Remote,
+  item: &PlannedItem,
+  run_id: &str,
+  since_ms: Option<u64>,
+  action: DriftAction,
+  log: &mut fs::File,
+  progress: &mut dyn SyncProgress,
+) -> Prepared {
+  let mut prepared = Prepared {
+    files: vec![],
+    blocked: None,
+    note: None,
+  };
+  let Some(since_ms) = since_ms.filter(|_| action != DriftAction::Off) else {
+    return prepared;
+  };
+  let files = match find(remote, item, since_ms) {
+    Ok(f) => f,
+    Err(e) => {
+      let _ = runs::append_log_line(log, &format!("drift {}：检查失败，照常同步（{}）", item.id, e));
+      return prepared;
+    }
+  };
+  if files.is_empty() {
+    return prepared;
+  }
+
+  let n = files.len();
+  let _ = runs::append_log_line(log, &format!("---- drift {}：目标端有 {} 个文件在上次同步后被改过 ----", item.id, n));
+  for f in &files {
+    let _ = runs::append_log_line(log, &format!("drift: {}（{}）", f.path, f.mod_time));
+  }
+
+  let save_dir = match config::drift_dir() {
+    Ok(d) => d.join(run_id).join(&item.backup_sub),
+    Err(e) => {
+      prepared.blocked = Some(e);
+      prepared.files = files;
+      return prepared;
+    }
+  };
+  let (local_root, _) = roots(item);
+
+  match action {
+    DriftAction::Off => {}
+    DriftAction::Warn => {
+      prepared.note = Some(format!("目标端有 {} 个文件在上次同步后被改过，已换成本机版本（目标端的版本挪到了备份目录）", n));
+    }
+    DriftAction::Skip => {
+      prepared.blocked = Some(format!(
+        "目标端有 {} 个文件在上次同步后被改过，已跳过本项（可以先拉回本机或另存后再同步）",
+        n
+      ));
+    }
+    DriftAction::Pull => {
+      match copy_back(remote, item, &files, &local_root, Some(&save_dir), log, progress) {
+        Ok(()) => {
+          prepared.note = Some(format!(
+            "已先把目标端改过的 {} 个文件拉回本机（本机被覆盖的版本在 {}）",
+            n,
+            save_dir.display()
+          ));
+        }
+        Err(e) => prepared.blocked = Some(format!("拉回目标端改动失败，已跳过本项：{}", e)),
+      }
+    }
+    DriftAction::Backup => {
+      match copy_back(remote, item, &files, &save_dir, None, log, progress) {
+        Ok(()) => {
+          prepared.note = Some(format!("目标端改过的 {} 个文件已另存到 {}", n, save_dir.display()));
+        }
+        Err(e) => prepared.blocked = Some(format!("另存目标端改动失败，已跳过本项：{}", e)),
+      }
+    }
+  }
+  prepared.files = files;
+  prepared
+}
+
+/// 只检查不同步：列出每一项上次同步后目标端被改过的文件，界面据此让用户选“拉回 / 跳过 / 另存”。
+pub fn check(cfg: &AgentSyncConfig, only: Option<&[String]>) -> Result<Vec<DriftItemReport>, String> {
+  let cfg = &vars::expanded(cfg)?;
+  rclone::validate_basic(cfg)?;
+  let rclone_path = rclone::resolve_rclone(cfg)?;
+  let last_ok = runs::last_ok_item_times()?;
+  let plan = rclone::plan_items(cfg, only)?;
+  let rclone_conf = rclone::write_temp_rclone_config(cfg)?;
+  let remote = Remote {
+    cfg,
+    rclone: &rclone_path,
+    rclone_conf: &rclone_conf,
+  };
+
+  let reports = plan
+    .iter()
+    .filter(|item| item.skip_message.is_none())
+    .map(|item| {
+      let since = last_ok.get(&item.id).copied();
+      let (files, error) = match since {
+        Some(ms) => match find(&remote, item, ms) {
+          Ok(f) => (f, None),
+          Err(e) => (vec![], Some(e)),
+        },
+        None => (vec![], None),
+      };
+      DriftItemReport {
+        item_id: item.id.clone(),
+        label: item.label.clone(),
+        last_synced_at_ms: since,
+        files,
+        error,
+      }
+    })
+    .collect();
+
+  drop(rclone_conf);
+  Ok(reports)
+}
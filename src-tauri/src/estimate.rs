@@ -0,0 +1,258 @@
+//! 同步前估算：每个同步项按排除规则在本机数一遍，看这次大概要推多少东西。
+//!
+//! 大白话：
+//! - 每项给出文件数、大小、最大的几个子目录（一眼看出是不是忘了排除 `target/`）、大文件
+//! - 超过 `estimate` 里的阈值就提醒（单项太大、加起来太大、有大文件）
+//! - 算的是本机要同步的总量：已经同步过、没变的文件 rclone 不会再传，所以这是第一次同步（或目标端被清空时）的上限
+//! - 只数本机，不连目标电脑
+
+use crate::config::AgentSyncConfig;
+use crate::excludes::ExcludeMatcher;
+use crate::rclone;
+use crate::vars;
+use crate::webhooks::human_bytes;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// 一个同步项最多数这么多个文件，超过就停（结果标 `truncated`，实际只会更多）
+const MAX_FILES: u64 = 500_000;
+/// 每项最多列几个大文件 / 几个子目录
+const MAX_HUGE_FILES: usize = 20;
+const MAX_BIGGEST_DIRS: usize = 5;
+
+const MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HugeFile {
+  /// 相对同步项根目录
+  pub path: String,
+  pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirUsage {
+  /// 同步项根目录下第一层的子目录
+  pub path: String,
+  pub files: u64,
+  pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemEstimate {
+  pub item_id: String,
+  pub label: String,
+  pub local: String,
+  pub files: u64,
+  pub bytes: u64,
+  /// 文件太多没数完
+  pub truncated: bool,
+  /// 最大的几个子目录（大的在前）
+  pub biggest_dirs: Vec<DirUsage>,
+  /// 超过 `hugeFileMb` 的文件（大的在前）
+  pub huge_files: Vec<HugeFile>,
+  /// 本机找不到、这次会跳过
+  pub skip_message: Option<String>,
+  pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncEstimate {
+  pub items: Vec<ItemEstimate>,
+  pub total_files: u64,
+  pub total_bytes: u64,
+  /// 所有提醒（单项的带上项目名），为空表示没什么要注意的
+  pub warnings: Vec<String>,
+}
+
+/// 数一个目录（或单个文件）的结果。
+#[derive(Default)]
+pub(crate) struct DirStats {
+  pub files: u64,
+  pub bytes: u64,
+  pub truncated: bool,
+  /// 不小于 `huge_bytes` 的文件（相对路径, 大小）
+  pub huge: Vec<(String, u64)>,
+  /// 第一层子目录 -> (文件数, 大小)
+  pub top_dirs: BTreeMap<String, (u64, u64)>,
+}
+
+/// 按排除规则数一个目录里会同步多少文件、多少字节（不跟符号链接）。`huge_bytes` 为 0 时不收集大文件。
+pub(crate) fn measure(root: &Path, matcher: &ExcludeMatcher, huge_bytes: u64) -> DirStats {
+  let mut stats = DirStats::default();
+  if let Ok(meta) = fs::metadata(root) {
+    if meta.is_file() {
+      stats.files = 1;
+      stats.bytes = meta.len();
+      return stats;
+    }
+  }
+
+  let mut stack = vec![root.to_path_buf()];
+  while let Some(dir) = stack.pop() {
+    let Ok(entries) = fs::read_dir(&dir) else {
+      continue;
+    };
+    for entry in entries.map_while(Result::ok) {
+      let path = entry.path();
+      let rel = path.strip_prefix(root).unwrap_or(&path);
+      if matcher.is_excluded(rel) {
+        continue;
+      }
+      let Ok(meta) = entry.metadata() else {
+        continue;
+      };
+      if meta.is_dir() {
+        stack.push(path);
+        continue;
+      }
+      if !meta.is_file() {
+        continue;
+      }
+      let len = meta.len();
+      stats.files += 1;
+      stats.bytes += len;
+      let mut parts = rel.components();
+      if let (Some(first), Some(_)) = (parts.next(), parts.next()) {
+        let e = stats
+          .top_dirs
+          .entry(first.as_os_str().to_string_lossy().into_owned())
+          .or_default();
+        e.0 += 1;
+        e.1 += len;
+      }
+      if huge_bytes > 0 && len >= huge_bytes {
+        stats.huge.push((rel.to_string_lossy().replace('\\', "/"), len));
+      }
+      if stats.files >= MAX_FILES {
+        stats.truncated = true;
+        return stats;
+      }
+    }
+  }
+  stats
+}
+
+fn estimate_item(
+  item: &rclone::PlannedItem,
+  matcher: &ExcludeMatcher,
+  cfg: &AgentSyncConfig,
+) -> ItemEstimate {
+  let limits = &cfg.estimate;
+  let mut out = ItemEstimate {
+    item_id: item.id.clone(),
+    label: item.label.clone(),
+    local: item.local.display().to_string(),
+    files: 0,
+    bytes: 0,
+    truncated: false,
+    biggest_dirs: vec![],
+    huge_files: vec![],
+    skip_message: item.skip_message.clone(),
+    warnings: vec![],
+  };
+  if item.skip_message.is_some() {
+    return out;
+  }
+  if !item.local.exists() {
+    out.warnings.push(format!("本机找不到：{}（同步时这一项会失败）", item.local.display()));
+    return out;
+  }
+
+  let empty = ExcludeMatcher::empty();
+  let stats = measure(
+    &item.local,
+    if item.use_excludes { matcher } else { &empty },
+    limits.huge_file_mb.saturating_mul(MB),
+  );
+  out.files = stats.files;
+  out.bytes = stats.bytes;
+  out.truncated = stats.truncated;
+
+  let mut dirs: Vec<DirUsage> = stats
+    .top_dirs
+    .into_iter()
+    .map(|(path, (files, bytes))| DirUsage { path, files, bytes })
+    .collect();
+  dirs.sort_by_key(|d| std::cmp::Reverse(d.bytes));
+  dirs.truncate(MAX_BIGGEST_DIRS);
+  out.biggest_dirs = dirs;
+
+  let mut huge = stats.huge;
+  huge.sort_by_key(|(_, bytes)| std::cmp::Reverse(*bytes));
+  let huge_count = huge.len();
+  out.huge_files = huge
+    .into_iter()
+    .take(MAX_HUGE_FILES)
+    .map(|(path, bytes)| HugeFile { path, bytes })
+    .collect();
+
+  let at_least = if out.truncated { "至少 " } else { "" };
+  if limits.warn_item_mb > 0 && out.bytes > limits.warn_item_mb.saturating_mul(MB) {
+    let mut message = format!(
+      "有 {}{}（{} 个文件），超过提醒线 {} MB",
+      at_least,
+      human_bytes(out.bytes),
+      out.files,
+      limits.warn_item_mb
+    );
+    if let Some(d) = out.biggest_dirs.first() {
+      message.push_str(&format!(
+        "；最大的目录是 {}/（{}），是不是忘了加排除规则？",
+        d.path,
+        human_bytes(d.bytes)
+      ));
+    }
+    out.warnings.push(message);
+  }
+  if let Some(f) = out.huge_files.first() {
+    out.warnings.push(format!(
+      "有 {} 个大文件（不小于 {} MB），最大的是 {}（{}）",
+      huge_count,
+      limits.huge_file_mb,
+      f.path,
+      human_bytes(f.bytes)
+    ));
+  }
+  if out.truncated {
+    out.warnings.push(format!("文件超过 {} 个，只数了一部分", MAX_FILES));
+  }
+  out
+}
+
+/// 估算这次同步（`only` 为空时是全部启用项）要推多少东西。
+pub fn estimate(cfg: &AgentSyncConfig, only: Option<&[String]>) -> Result<SyncEstimate, String> {
+  let cfg = &vars::expanded(cfg)?;
+  let matcher = ExcludeMatcher::new(&cfg.excludes)?;
+  let items: Vec<ItemEstimate> = rclone::plan_items(cfg, only)?
+    .iter()
+    .map(|item| estimate_item(item, &matcher, cfg))
+    .collect();
+
+  let total_files = items.iter().map(|i| i.files).sum();
+  let total_bytes: u64 = items.iter().map(|i| i.bytes).sum();
+  let mut warnings: Vec<String> = items
+    .iter()
+    .flat_map(|i| i.warnings.iter().map(move |w| format!("{}：{}", i.label, w)))
+    .collect();
+  let warn_total = cfg.estimate.warn_total_mb;
+  if warn_total > 0 && total_bytes > warn_total.saturating_mul(MB) {
+    warnings.push(format!(
+      "这次一共要同步 {}，超过提醒线 {} MB（已经同步过、没变的文件不会再传）",
+      human_bytes(total_bytes),
+      warn_total
+    ));
+  }
+
+  Ok(SyncEstimate {
+    items,
+    total_files,
+    total_bytes,
+    warnings,
+  })
+}
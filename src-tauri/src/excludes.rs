@@ -0,0 +1,78 @@
+//! 排除规则的本地匹配：按 rclone `--exclude` 的写法判断一个本机路径会不会被同步。
+//!
+//! rclone 的规则（大白话）：
+//! - 以 `/` 开头的规则只从同步项根目录开始匹配
+//! - 其他规则可以匹配任意一层目录，比如 `node_modules/**` 也会排除 `a/b/node_modules/x`
+//! - `dir/**` 命中时，`dir` 这个目录本身也当作被排除（监听时目录事件也要过滤掉）
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::Path;
+
+pub struct ExcludeMatcher {
+  files: GlobSet,
+  dirs: GlobSet,
+}
+
+fn to_glob(pattern: &str) -> String {
+  match pattern.strip_prefix('/') {
+    Some(anchored) => anchored.to_string(),
+    None => format!("**/{}", pattern),
+  }
+}
+
+fn build_glob(pattern: &str) -> Result<globset::Glob, String> {
+  GlobBuilder::new(&to_glob(pattern))
+    .literal_separator(true)
+    .build()
+    .map_err(|e| format!("排除规则无效：{}（{}）", pattern, e))
+}
+
+impl ExcludeMatcher {
+  pub fn new(patterns: &[String]) -> Result<Self, String> {
+    let mut files = GlobSetBuilder::new();
+    let mut dirs = GlobSetBuilder::new();
+    for raw in patterns {
+      let pattern = raw.trim();
+      if pattern.is_empty() {
+        continue;
+      }
+      files.add(build_glob(pattern)?);
+      if let Some(dir) = pattern.strip_suffix("/**") {
+        if !dir.is_empty() {
+          dirs.add(build_glob(dir)?);
+        }
+      }
+    }
+    Ok(ExcludeMatcher {
+      files: files.build().map_err(|e| format!("排除规则无效（{}）", e))?,
+      dirs: dirs.build().map_err(|e| format!("排除规则无效（{}）", e))?,
+    })
+  }
+
+  /// 不做任何排除（例如 Codex / .agents 这类同步项本来就不带排除规则）。
+  pub fn empty() -> Self {
+    ExcludeMatcher {
+      files: GlobSet::empty(),
+      dirs: GlobSet::empty(),
+    }
+  }
+
+  /// `rel` 是相对同步项根目录的路径。
+  pub fn is_excluded(&self, rel: &Path) -> bool {
+    let parts: Vec<String> = rel
+      .components()
+      .map(|c| c.as_os_str().to_string_lossy().to_string())
+      .collect();
+    if parts.is_empty() {
+      return false;
+    }
+
+    let full = parts.join("/");
+    if self.files.is_match(&full) {
+      return true;
+    }
+
+    // 任意一层父目录（包括自己）命中 `dir/**` 都算排除
+    (1..=parts.len()).any(|n| self.dirs.is_match(parts[..n].join("/")))
+  }
+}
@@ -0,0 +1,354 @@
+//! git 仓库的同步。
+//!
+//! 大白话：
+//! - 每个项目同步时都顺手读一下本机的分支、HEAD、有没有未提交的改动，记进这一项的结果（不是 git 仓库 / 没装 git 就不记）
+//! - 项目的 `git` 设为 `bundle` 时：工作区照常用 rclone 同步（`.git` 强制排除），
+//!   `.git` 用 `git bundle` 打成一个文件推过去（不用一个个传几千个小对象文件），
+//!   在目标端 fetch 进项目目录里的仓库，分支 / HEAD 对齐本机，再 `git reset`（不带 `--hard`，只让暂存区跟上，不动文件）。
+//!   这样目标端 `git status` 看到的改动和本机一样
+//! - 只打目标端还没有的提交：先问目标端各分支 / 标签指到哪，本机也有的提交不进包；目标端还没有仓库时才打全部历史，
+//!   一个新提交都没有就不打包，只对齐分支 / HEAD
+//! - 设为 `fetch` 时反过来：工作区照常同步，目标端仓库里本机没有的提交打成 bundle 取回来，
+//!   目标端的分支放到本机 `refs/remotes/agentsync/<分支>`（不动本机分支和工作区，要不要合并自己决定）
+//! - 目标端需要装 git、登录 shell 是 sh 兼容的（macOS / Linux）；目标端只有的分支不会被删掉
+
+use crate::drift::Remote;
+use crate::hooks;
+use crate::rclone::{self, PlannedItem, SyncProgress};
+use crate::runs::{self, GitState};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// 目标端临时放 bundle 的目录（相对目标端用户目录，fetch 完就删）
+const REMOTE_TMP_DIR: &str = ".agentsync-tmp";
+/// `fetch` 模式下目标端的分支放在本机的这个位置
+const FETCH_NS: &str = "refs/remotes/agentsync/";
+/// 写日志时的阶段名
+const STAGE: &str = "git";
+
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+  let out = Command::new("git").arg("-C").arg(dir).args(args).output().ok()?;
+  out
+    .status
+    .success()
+    .then(|| String::from_utf8_lossy(&out.stdout).trim_end().to_string())
+}
+
+/// 读本机仓库的状态；不是 git 仓库、或者本机没装 git 时为空。
+pub(crate) fn local_state(dir: &Path) -> Option<GitState> {
+  if !dir.join(".git").exists() {
+    return None;
+  }
+  let status = git(dir, &["status", "--porcelain"])?;
+  let changed_files = status.lines().filter(|l| !l.trim().is_empty()).count() as u32;
+  Some(GitState {
+    branch: git(dir, &["symbolic-ref", "--short", "-q", "HEAD"]).filter(|s| !s.is_empty()),
+    head: git(dir, &["rev-parse", "--verify", "-q", "HEAD"]).filter(|s| !s.is_empty()),
+    dirty: changed_files > 0,
+    changed_files,
+    bundle_bytes: None,
+    remote_updated: false,
+    fetched_branches: None,
+  })
+}
+
+/// 一句话说明仓库状态，例如 `main@1a2b3c4（3 个文件未提交）`。
+pub(crate) fn describe(state: &GitState) -> String {
+  let head = state.head.as_deref().map(|h| &h[..h.len().min(7)]).unwrap_or("（还没有提交）");
+  let mut text = match &state.branch {
+    Some(b) => format!("{}@{}", b, head),
+    None => format!("HEAD 游离@{}", head),
+  };
+  if state.dirty {
+    text.push_str(&format!("（{} 个文件未提交）", state.changed_files));
+  }
+  text
+}
+
+/// 同步项 id 里可能有 `:` 之类的字符，文件名里只留字母数字和 `-`。
+fn safe_name(id: &str) -> String {
+  id.chars()
+    .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+    .collect()
+}
+
+fn run_git(dir: &Path, args: &[String]) -> Result<String, String> {
+  let out = Command::new("git")
+    .arg("-C")
+    .arg(dir)
+    .args(args)
+    .output()
+    .map_err(|e| format!("运行 git 失败（{}）", e))?;
+  if !out.status.success() {
+    return Err(String::from_utf8_lossy(&out.stderr).trim().to_string());
+  }
+  Ok(String::from_utf8_lossy(&out.stdout).trim_end().to_string())
+}
+
+/// 本机仓库里有没有这个对象
+fn has_object(dir: &Path, sha: &str) -> bool {
+  git(dir, &["cat-file", "-e", sha]).is_some()
+}
+
+/// 一行一个 `<提交> <引用名>`，解析成 (提交, 引用名)
+fn parse_refs(text: &str) -> Vec<(String, String)> {
+  text
+    .lines()
+    .filter_map(|l| l.trim().split_once(' '))
+    .filter(|(sha, name)| !sha.is_empty() && !name.is_empty())
+    .map(|(sha, name)| (sha.to_string(), name.to_string()))
+    .collect()
+}
+
+/// 本机的分支和标签（不含 HEAD）
+fn local_refs(dir: &Path) -> Vec<(String, String)> {
+  git(dir, &["for-each-ref", "--format=%(objectname) %(refname)", "refs/heads", "refs/tags"])
+    .map(|t| parse_refs(&t))
+    .unwrap_or_default()
+}
+
+/// 在目标端跑一段 sh 脚本，拿标准输出（退出码 127 当作没装 git）。
+fn remote_output(remote: &Remote, script: &str) -> Result<String, String> {
+  let out = hooks::ssh_command(remote.cfg, script)
+    .output()
+    .map_err(|e| format!("运行 ssh 失败（{}）", e))?;
+  match out.status.code() {
+    Some(0) => Ok(String::from_utf8_lossy(&out.stdout).to_string()),
+    Some(127) => Err("目标端没有安装 git".to_string()),
+    _ => Err(String::from_utf8_lossy(&out.stderr).trim().to_string()),
+  }
+}
+
+/// 目标端仓库的分支、标签和 HEAD（HEAD 记成引用名 `HEAD`）；目标端项目目录不存在、或里面还没有仓库时为空。
+fn remote_refs(remote: &Remote, remote_dir: &str) -> Result<Option<Vec<(String, String)>>, String> {
+  let script = format!(
+    "{{ cd {dir} 2>/dev/null && [ -d .git ]; }} || {{ echo none; exit 0; }}; \
+     command -v git >/dev/null 2>&1 || exit 127; \
+     echo repo; git for-each-ref --format='%(objectname) %(refname)' refs/heads refs/tags; \
+     echo \"$(git rev-parse -q --verify HEAD) HEAD\"",
+    dir = hooks::sh_quote(remote_dir),
+  );
+  let text = remote_output(remote, &script).map_err(|e| format!("读目标端仓库失败：{}", e))?;
+  let mut lines = text.lines();
+  match lines.next().map(str::trim) {
+    Some("repo") => Ok(Some(parse_refs(&lines.collect::<Vec<_>>().join("\n")))),
+    _ => Ok(None),
+  }
+}
+
+/// `bundle` 模式：把 `.git` 打包推到目标端，fetch 进目标端项目目录的仓库，分支 / HEAD 和本机对齐。
+/// 只打目标端还没有的提交（目标端还没有仓库时打全部）。
+/// 成功时把 bundle 大小、目标端已对齐记进 `state`。工作区要先同步好再调。
+pub(crate) fn push_bundle(
+  remote: &Remote,
+  item: &PlannedItem,
+  run_id: &str,
+  state: &mut GitState,
+  log: &mut fs::File,
+  progress: &mut dyn SyncProgress,
+) -> Result<(), String> {
+  let head = state
+    .head
+    .clone()
+    .ok_or_else(|| "仓库还没有任何提交，没有可以推的历史".to_string())?;
+
+  // 1) 目标端现在有什么：各引用指到的提交里本机也有的，不用再打进包
+  let theirs = remote_refs(remote, &item.remote_dest)?;
+  let mut known: Vec<String> = theirs
+    .iter()
+    .flatten()
+    .map(|(sha, _)| sha.clone())
+    .filter(|sha| has_object(&item.local, sha))
+    .collect();
+  known.sort();
+  known.dedup();
+  let mut revs = vec!["--all".to_string()];
+  if !known.is_empty() {
+    revs.push("--not".to_string());
+    revs.extend(known);
+  }
+  let mut count_args = vec!["rev-list".to_string(), "--count".to_string()];
+  count_args.extend(revs.iter().cloned());
+  let new_commits: u64 = run_git(&item.local, &count_args)
+    .map_err(|e| format!("数新提交失败：{}", e))?
+    .trim()
+    .parse()
+    .unwrap_or(0);
+
+  // 2) 有新提交才打包（git 不肯打空包）、推到目标端的临时目录
+  let name = format!("{}-{}.bundle", run_id, safe_name(&item.id));
+  let remote_bundle = format!("{}/{}", REMOTE_TMP_DIR, name);
+  let mut in_bundle: Vec<String> = Vec::new();
+  let mut bytes = 0;
+  if new_commits > 0 {
+    let local_bundle = std::env::temp_dir().join(format!("agentsync-{}", name));
+    let mut args = vec!["bundle".to_string(), "create".to_string(), local_bundle.display().to_string()];
+    args.extend(revs);
+    if let Err(e) = run_git(&item.local, &args) {
+      let _ = fs::remove_file(&local_bundle);
+      return Err(format!("打包 .git 失败：{}", e));
+    }
+    // 包里带了哪些引用：只 fetch 这些，其余的下面直接改指向
+    let list = ["bundle".to_string(), "list-heads".to_string(), local_bundle.display().to_string()];
+    let heads = run_git(&item.local, &list).unwrap_or_default();
+    in_bundle = parse_refs(&heads).into_iter().map(|(_, name)| name).collect();
+    bytes = fs::metadata(&local_bundle).map(|m| m.len()).unwrap_or(0);
+    let scope = if theirs.is_some() { "增量" } else { "全部历史" };
+    let _ = runs::append_log_line(
+      log,
+      &format!("git {}：bundle {} 字节，{} 个新提交（{}，{}）", item.id, bytes, new_commits, scope, describe(state)),
+    );
+
+    let mut args = rclone::make_common_args(remote.cfg, remote.rclone_conf);
+    args.push("copyto".to_string());
+    args.push(local_bundle.display().to_string());
+    args.push(format!("remote:{}", remote_bundle));
+    let label = format!("{}（推送 .git bundle）", item.label);
+    let r = rclone::run_one_stream(remote.rclone, log, &item.id, &label, &args, progress);
+    let _ = fs::remove_file(&local_bundle);
+    if !r.ok {
+      return Err(format!("上传 .git bundle 失败：{}", r.message));
+    }
+  } else {
+    let _ = runs::append_log_line(log, &format!("git {}：目标端已有全部提交，不用打包（{}）", item.id, describe(state)));
+  }
+
+  // 3) 目标端：没有仓库就建一个，fetch 包里的引用；包里没带的（目标端已有那个提交）直接改指向；
+  //    HEAD 指到和本机一样的分支 / 提交，暂存区跟上 HEAD
+  let fetch = if in_bundle.is_empty() {
+    String::new()
+  } else {
+    let refspecs: Vec<String> = in_bundle
+      .iter()
+      .map(|r| match r.as_str() {
+        "HEAD" => "'+HEAD:refs/agentsync/head'".to_string(),
+        _ => hooks::sh_quote(&format!("+{}:{}", r, r)),
+      })
+      .collect();
+    format!(
+      "git fetch -q --force --update-head-ok \"$B\" {}; rc=$?; rm -f \"$B\"; [ $rc -eq 0 ] || exit $rc; ",
+      refspecs.join(" ")
+    )
+  };
+  let old: Vec<(String, String)> = theirs.unwrap_or_default();
+  let moves: Vec<String> = local_refs(&item.local)
+    .into_iter()
+    .filter(|(sha, name)| !in_bundle.contains(name) && !old.iter().any(|(s, n)| n == name && s == sha))
+    // 提交不在目标端（例如指向老提交的新附注标签）就先不动，等下次有新提交时跟着包过去
+    .map(|(sha, name)| format!("{{ ! git cat-file -e {sha} 2>/dev/null || git update-ref {} {sha}; }} && ", hooks::sh_quote(&name)))
+    .collect();
+  let set_head = match &state.branch {
+    Some(branch) => format!("git symbolic-ref HEAD {}", hooks::sh_quote(&format!("refs/heads/{}", branch))),
+    None => format!("git update-ref --no-deref HEAD {}", head),
+  };
+  let script = format!(
+    "( B=\"$HOME\"/{bundle}; \
+     if ! command -v git >/dev/null 2>&1; then rm -f \"$B\"; echo 'git: command not found' >&2; exit 127; fi; \
+     {{ [ -d .git ] || git init -q; }} && \
+     {fetch}{moves}{set_head} && git reset -q && test \"$(git rev-parse HEAD)\" = {head} )",
+    bundle = hooks::sh_quote(&remote_bundle),
+    fetch = fetch,
+    moves = moves.concat(),
+    set_head = set_head,
+    head = head,
+  );
+  let h = hooks::run_remote(remote.cfg, STAGE, &script, &item.remote_dest, log, progress);
+  if !h.ok {
+    return Err(match h.exit_code {
+      Some(127) => "目标端没有安装 git（工作区已同步，历史没推过去）".to_string(),
+      _ => format!("目标端更新仓库失败：{}", h.message),
+    });
+  }
+  state.bundle_bytes = Some(bytes);
+  state.remote_updated = true;
+  Ok(())
+}
+
+/// `fetch` 模式：目标端仓库里本机没有的提交打成 bundle 取回来，目标端的分支放到本机 `refs/remotes/agentsync/<分支>`。
+/// 本机分支、HEAD、工作区都不动；目标端已经删掉的分支，本机这边对应的也删掉。
+pub(crate) fn fetch_bundle(
+  remote: &Remote,
+  item: &PlannedItem,
+  run_id: &str,
+  state: &mut GitState,
+  log: &mut fs::File,
+  progress: &mut dyn SyncProgress,
+) -> Result<(), String> {
+  let theirs = remote_refs(remote, &item.remote_dest)?
+    .ok_or_else(|| "目标端项目目录里还没有 git 仓库，没有可以取回的提交".to_string())?;
+  let branches: Vec<(String, String)> = theirs
+    .into_iter()
+    .filter_map(|(sha, name)| name.strip_prefix("refs/heads/").map(|b| (sha, b.to_string())))
+    .collect();
+
+  // 1) 目标端打包：本机已有的提交（目标端也认得的）不进包；一个新提交都没有就不打
+  let name = format!("{}-{}.bundle", run_id, safe_name(&item.id));
+  let remote_bundle = format!("{}/{}", REMOTE_TMP_DIR, name);
+  let mut mine: Vec<String> = local_refs(&item.local).into_iter().map(|(sha, _)| sha).collect();
+  mine.extend(state.head.clone());
+  mine.sort();
+  mine.dedup();
+  let missing: Vec<&(String, String)> = branches.iter().filter(|(sha, _)| !has_object(&item.local, sha)).collect();
+  let mut bytes = 0;
+  if !missing.is_empty() {
+    let script = format!(
+      "cd {dir} && {{ command -v git >/dev/null 2>&1 || exit 127; }} && \
+       B=\"$HOME\"/{bundle}; mkdir -p \"$HOME\"/{tmp} && N=; \
+       for s in {mine}; do git cat-file -e \"$s\" 2>/dev/null && N=\"$N ^$s\"; done; \
+       git bundle create -q \"$B\" --branches $N",
+      dir = hooks::sh_quote(&item.remote_dest),
+      bundle = hooks::sh_quote(&remote_bundle),
+      tmp = REMOTE_TMP_DIR,
+      mine = if mine.is_empty() { "''".to_string() } else { mine.join(" ") },
+    );
+    remote_output(remote, &script).map_err(|e| format!("目标端打包 .git 失败：{}", e))?;
+
+    let local_bundle = std::env::temp_dir().join(format!("agentsync-{}", name));
+    let mut args = rclone::make_common_args(remote.cfg, remote.rclone_conf);
+    args.push("moveto".to_string());
+    args.push(format!("remote:{}", remote_bundle));
+    args.push(local_bundle.display().to_string());
+    let label = format!("{}（取回 .git bundle）", item.label);
+    let r = rclone::run_one_stream(remote.rclone, log, &item.id, &label, &args, progress);
+    if !r.ok {
+      let _ = fs::remove_file(&local_bundle);
+      return Err(format!("下载 .git bundle 失败：{}", r.message));
+    }
+    bytes = fs::metadata(&local_bundle).map(|m| m.len()).unwrap_or(0);
+    let fetched = run_git(
+      &item.local,
+      &[
+        "fetch".to_string(),
+        "-q".to_string(),
+        local_bundle.display().to_string(),
+        format!("+refs/heads/*:{}*", FETCH_NS),
+      ],
+    );
+    let _ = fs::remove_file(&local_bundle);
+    fetched.map_err(|e| format!("取回的 bundle fetch 失败：{}", e))?;
+  }
+
+  // 2) 包里没带的分支（提交本机已有）直接改指向；目标端没有了的分支删掉
+  for (sha, branch) in &branches {
+    run_git(&item.local, &["update-ref".to_string(), format!("{}{}", FETCH_NS, branch), sha.clone()])
+      .map_err(|e| format!("更新 {}{} 失败：{}", FETCH_NS, branch, e))?;
+  }
+  let ns = FETCH_NS.trim_end_matches('/');
+  for (_, name) in git(&item.local, &["for-each-ref", "--format=%(objectname) %(refname)", ns])
+    .map(|t| parse_refs(&t))
+    .unwrap_or_default()
+  {
+    let branch = name.strip_prefix(FETCH_NS).unwrap_or(&name);
+    if !branches.iter().any(|(_, b)| b == branch) {
+      let _ = git(&item.local, &["update-ref", "-d", &name]);
+    }
+  }
+  let _ = runs::append_log_line(
+    log,
+    &format!("git {}：取回目标端 {} 个分支到 {}*，bundle {} 字节", item.id, branches.len(), FETCH_NS, bytes),
+  );
+  state.bundle_bytes = Some(bytes);
+  state.fetched_branches = Some(branches.len() as u32);
+  Ok(())
+}
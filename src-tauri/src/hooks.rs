@@ -0,0 +1,146 @@
+//! 同步前后命令（hooks）：配置在 `config.hooks` 里，由 `run_sync_with_id` 在对应时机调用。
+//!
+//! 规则（大白话）：
+//! - 本机命令用系统 shell 跑（macOS / Linux：`sh -c`；Windows：`cmd /C`）
+//! - 目标端命令通过 ssh 跑，用的是连接设置里的 Host / User / 私钥，先 `cd` 到目标端的项目目录
+//! - 命令输出逐行写进本次运行的日志（带 `[阶段]` 前缀），结果记进 `HookResult`
+//! - 命令里可以用环境变量 `AGENTSYNC_RUN_ID` / `AGENTSYNC_ITEM_ID` / `AGENTSYNC_OK`（仅本机命令）
+
+use crate::config::AgentSyncConfig;
+use crate::known_hosts;
+use crate::rclone::{self, SyncProgress};
+use crate::runs::{self, HookResult};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn run(
+  cmd: Command,
+  stage: &str,
+  command: &str,
+  log: &mut fs::File,
+  progress: &mut dyn SyncProgress,
+) -> HookResult {
+  let _ = runs::append_log_line(log, &format!("---- hook {}: {} ----", stage, command));
+
+  let mut last_line: Option<String> = None;
+  let exit_code = rclone::stream_command(cmd, &format!("{} 命令", stage), |line| {
+    let trimmed = line.trim_end();
+    if !trimmed.is_empty() {
+      last_line = Some(trimmed.to_string());
+      let text = format!("[{}] {}", stage, trimmed);
+      let _ = runs::append_log_line(log, &text);
+      progress.on_line(&text);
+    }
+  });
+
+  let result = match exit_code {
+    Ok(0) => HookResult {
+      stage: stage.to_string(),
+      command: command.to_string(),
+      ok: true,
+      exit_code: Some(0),
+      message: "完成".to_string(),
+    },
+    Ok(code) => HookResult {
+      stage: stage.to_string(),
+      command: command.to_string(),
+      ok: false,
+      exit_code: Some(code),
+      message: format!(
+        "命令失败（exit code {}）{}",
+        code,
+        last_line
+          .as_deref()
+          .map(|s| format!("：{}", s))
+          .unwrap_or_default()
+      ),
+    },
+    Err(e) => HookResult {
+      stage: stage.to_string(),
+      command: command.to_string(),
+      ok: false,
+      exit_code: None,
+      message: e,
+    },
+  };
+  let _ = runs::append_log_line(log, &format!("hook {}: {}", stage, result.message));
+  result
+}
+
+/// 在本机跑一条命令；`cwd` 为空时在用户目录下跑。
+pub fn run_local(
+  stage: &str,
+  command: &str,
+  cwd: Option<&Path>,
+  env: &[(&str, &str)],
+  log: &mut fs::File,
+  progress: &mut dyn SyncProgress,
+) -> HookResult {
+  let mut cmd = if cfg!(windows) {
+    let mut c = Command::new("cmd");
+    c.args(["/C", command]);
+    c
+  } else {
+    let mut c = Command::new("sh");
+    c.args(["-c", command]);
+    c
+  };
+  let home = dirs::home_dir();
+  if let Some(dir) = cwd.or(home.as_deref()) {
+    cmd.current_dir(dir);
+  }
+  for (k, v) in env {
+    cmd.env(k, v);
+  }
+  run(cmd, stage, command, log, progress)
+}
+
+/// 通过 ssh 在目标端跑一条命令；`remote_dir` 是相对目标端用户目录的路径（和 rclone 一致）。
+pub fn run_remote(
+  cfg: &AgentSyncConfig,
+  stage: &str,
+  command: &str,
+  remote_dir: &str,
+  log: &mut fs::File,
+  progress: &mut dyn SyncProgress,
+) -> HookResult {
+  let remote_command = if remote_dir.is_empty() {
+    command.to_string()
+  } else {
+    format!("cd {} && {}", sh_quote(remote_dir), command)
+  };
+  run(ssh_command(cfg, &remote_command), stage, command, log, progress)
+}
+
+/// 拼进目标端 shell 命令的参数用单引号包起来：目录名里的 `$(...)`、反引号、`\` 都不会被执行 / 转义。
+pub(crate) fn sh_quote(s: &str) -> String {
+  format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// 用连接设置里的 Host / User / 私钥拼一条 ssh 命令（不交互，连不上 15 秒就放弃，指纹对不上就拒绝）。
+pub(crate) fn ssh_command(cfg: &AgentSyncConfig, remote_command: &str) -> Command {
+  let mut cmd = Command::new(if cfg!(windows) { "ssh.exe" } else { "ssh" });
+  cmd.args([
+    "-i",
+    cfg.connection.key_path.as_str(),
+    "-p",
+    &cfg.connection.port.to_string(),
+    "-o",
+    "BatchMode=yes",
+    "-o",
+    "StrictHostKeyChecking=yes",
+    "-o",
+    "ConnectTimeout=15",
+  ]);
+  // 和 rclone 用同一份记下的指纹（见 known_hosts），对不上就拒绝连接
+  if let Ok(path) = known_hosts::known_hosts_file() {
+    cmd.arg("-o").arg(format!(
+      "UserKnownHostsFile=\"{}\"",
+      path.display().to_string().replace('\\', "/")
+    ));
+  }
+  cmd.arg(format!("{}@{}", cfg.connection.user.trim(), cfg.connection.host.trim()));
+  cmd.arg(remote_command);
+  cmd
+}
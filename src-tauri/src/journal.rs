@@ -0,0 +1,266 @@
+//! 运行日志（journal）：同步跑到哪了，随时落盘，防止崩溃 / 重启后什么都不知道。
+//!
+//! 规则（大白话）：
+//! - 每次同步开始时写 `~/.agentsync/runs/<run_id>.json`，每跑完一项更新一次
+//! - 正常结束（历史已写入）就删掉；还留着的说明进程半路没了
+//! - 守护进程启动时检查：进程确实不在了 -> 清掉临时 rclone 配置，在历史里补一条“中断”
+//!   （按 pid + 进程启动时间认：重启后 pid 常被别的进程占用，光看 pid 会一直以为还在跑）
+//! - 补记过的 journal 保留下来，用户可以“续跑”没完成的项（`resume`）
+
+use crate::config;
+use crate::manifest;
+use crate::rclone::RunOptions;
+use crate::runs::{self, RunStatus, SyncItemResult, SyncRunSummary};
+use crate::sync_manager;
+use crate::webhooks;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunJournal {
+  pub run_id: String,
+  /// 跑这次同步的进程（守护进程 / 命令行 `--local`）
+  pub pid: u32,
+  /// 这个进程的启动时间（见 `process_identity`）；老的 journal 没有
+  #[serde(default)]
+  pub process_identity: Option<String>,
+  pub started_at_ms: u64,
+  pub updated_at_ms: u64,
+  #[serde(default)]
+  pub trigger: Option<String>,
+  /// 本次计划要跑的同步项（按顺序）
+  pub planned_item_ids: Vec<String>,
+  /// 正在跑的项；为空表示处在两项之间
+  #[serde(default)]
+  pub current_item_id: Option<String>,
+  #[serde(default)]
+  pub done: Vec<SyncItemResult>,
+  /// 临时 rclone 配置（里面有连接信息），中断后要清掉
+  pub rclone_conf: String,
+  pub log_path: String,
+  pub backup_root: String,
+  /// 同步到哪台电脑（补记历史时用）
+  #[serde(default)]
+  pub target: Option<String>,
+  /// 已经在历史里补记为“中断”，等待续跑
+  #[serde(default)]
+  pub recovered: bool,
+}
+
+impl RunJournal {
+  /// 还没跑完的项（半路中断的那一项也算）。
+  pub fn unfinished_item_ids(&self) -> Vec<String> {
+    let done: BTreeSet<&str> = self.done.iter().map(|r| r.item_id.as_str()).collect();
+    self
+      .planned_item_ids
+      .iter()
+      .filter(|id| !done.contains(id.as_str()))
+      .cloned()
+      .collect()
+  }
+}
+
+fn now_ms() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_millis() as u64
+}
+
+fn journal_path(run_id: &str) -> Result<PathBuf, String> {
+  Ok(config::runs_dir()?.join(format!("{}.json", run_id)))
+}
+
+/// 先写临时文件再改名，断电时也不会留下半个 JSON。
+pub fn write(journal: &mut RunJournal) -> Result<(), String> {
+  config::ensure_dir(&config::runs_dir()?)?;
+  journal.updated_at_ms = now_ms();
+  let path = journal_path(&journal.run_id)?;
+  let tmp = path.with_extension("json.tmp");
+  let text =
+    serde_json::to_string_pretty(journal).map_err(|e| format!("序列化运行日志失败（{}）", e))?;
+  fs::write(&tmp, text).map_err(|e| format!("写入运行日志失败：{}（{}）", tmp.display(), e))?;
+  fs::rename(&tmp, &path).map_err(|e| format!("写入运行日志失败：{}（{}）", path.display(), e))
+}
+
+pub fn remove(run_id: &str) {
+  if let Ok(path) = journal_path(run_id) {
+    let _ = fs::remove_file(path);
+  }
+}
+
+pub fn load(run_id: &str) -> Result<RunJournal, String> {
+  let path = journal_path(run_id)?;
+  let text = fs::read_to_string(&path)
+    .map_err(|_| format!("找不到这次运行的记录（可能已经续跑过或正常结束）：{}", run_id))?;
+  serde_json::from_str(&text).map_err(|e| format!("运行日志格式无效：{}（{}）", path.display(), e))
+}
+
+fn list_all() -> Vec<RunJournal> {
+  let Ok(dir) = config::runs_dir() else {
+    return vec![];
+  };
+  let Ok(entries) = fs::read_dir(&dir) else {
+    return vec![];
+  };
+  entries
+    .flatten()
+    .map(|e| e.path())
+    .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+    .filter_map(|p| fs::read_to_string(p).ok())
+    .filter_map(|text| serde_json::from_str(&text).ok())
+    .collect()
+}
+
+/// 进程的“身份”：pid 加上启动时间（Linux 另带开机 id），pid 被重用时就对不上了。
+/// 进程不在了返回 `Ok(None)`；查不了（没有 ps / powershell）返回 `Err`，调用方退回只看 pid。
+fn process_identity(pid: u32) -> Result<Option<String>, String> {
+  if cfg!(target_os = "linux") {
+    let Ok(stat) = fs::read_to_string(format!("/proc/{}/stat", pid)) else {
+      return Ok(None);
+    };
+    let boot_id = fs::read_to_string("/proc/sys/kernel/random/boot_id").unwrap_or_default();
+    // 进程名里可能有空格和括号：从最后一个 ')' 之后数，第 22 个字段是启动时间
+    let started = stat
+      .rsplit_once(')')
+      .and_then(|(_, rest)| rest.split_whitespace().nth(19))
+      .ok_or_else(|| format!("/proc/{}/stat 格式无法识别", pid))?;
+    return Ok(Some(format!("{}:{}", boot_id.trim(), started)));
+  }
+  let out = if cfg!(windows) {
+    let script = format!(
+      "(Get-Process -Id {} -ErrorAction SilentlyContinue).StartTime.ToFileTimeUtc()",
+      pid
+    );
+    Command::new("powershell")
+      .args(["-NoProfile", "-Command", &script])
+      .stderr(Stdio::null())
+      .output()
+  } else {
+    Command::new("ps")
+      .args(["-o", "lstart=", "-p", &pid.to_string()])
+      .stderr(Stdio::null())
+      .output()
+  }
+  .map_err(|e| format!("查询进程启动时间失败（{}）", e))?;
+  let text = String::from_utf8_lossy(&out.stdout).trim().to_string();
+  Ok((!text.is_empty()).then_some(text))
+}
+
+/// 本进程的身份（跑同步时写进 journal），只查一次。
+pub fn self_identity() -> Option<String> {
+  static SELF: Lazy<Option<String>> = Lazy::new(|| process_identity(std::process::id()).ok().flatten());
+  SELF.clone()
+}
+
+fn pid_exists(pid: u32) -> bool {
+  if cfg!(windows) {
+    Command::new("tasklist")
+      .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+      .stderr(Stdio::null())
+      .output()
+      .map(|o| String::from_utf8_lossy(&o.stdout).contains(&pid.to_string()))
+      .unwrap_or(false)
+  } else {
+    Command::new("kill")
+      .args(["-0", &pid.to_string()])
+      .stderr(Stdio::null())
+      .status()
+      .map(|s| s.success())
+      .unwrap_or(false)
+  }
+}
+
+/// 跑这次同步的进程还在不在。
+fn run_alive(j: &RunJournal) -> bool {
+  match process_identity(j.pid) {
+    Ok(None) => false,
+    // 老的 journal 没记启动时间，只能按 pid 算
+    Ok(Some(id)) => j.process_identity.as_deref().is_none_or(|recorded| recorded == id),
+    Err(_) => pid_exists(j.pid),
+  }
+}
+
+/// 守护进程启动时调用：把半路中断的运行补记到历史里。返回补记的条数。
+pub fn recover_interrupted() -> Result<usize, String> {
+  let known: BTreeSet<String> = runs::list_runs()?.into_iter().map(|r| r.run_id).collect();
+  let mut recovered = 0;
+
+  for mut j in list_all() {
+    if j.recovered || run_alive(&j) {
+      continue;
+    }
+    if known.contains(&j.run_id) {
+      // 历史已经写进去了，只是没来得及删 journal
+      remove(&j.run_id);
+      continue;
+    }
+
+    let _ = fs::remove_file(&j.rclone_conf);
+
+    let unfinished = j.unfinished_item_ids();
+    let note = match &j.current_item_id {
+      Some(id) => format!("同步在处理 {} 时中断（进程退出或关机），还有 {} 项没完成，可以续跑", id, unfinished.len()),
+      None => format!("同步中断（进程退出或关机），还有 {} 项没完成，可以续跑", unfinished.len()),
+    };
+    let summary = SyncRunSummary {
+      run_id: j.run_id.clone(),
+      started_at_ms: j.started_at_ms,
+      ended_at_ms: j.updated_at_ms,
+      ok: false,
+      backup_root: j.backup_root.clone(),
+      log_path: j.log_path.clone(),
+      items: j.done.clone(),
+      trigger: j.trigger.clone(),
+      status: RunStatus::Interrupted,
+      note: Some(note),
+      hooks: vec![],
+      // 中断前跑完的项已经记进清单
+      manifest_path: manifest::existing_path(&j.run_id),
+      target: j.target.clone(),
+      log_missing: false,
+    };
+    runs::append_run(&summary)?;
+    if let Ok(cfg) = config::load_or_default() {
+      webhooks::send_run_background(&cfg, &summary);
+    }
+
+    j.recovered = true;
+    j.current_item_id = None;
+    if unfinished.is_empty() {
+      remove(&j.run_id);
+    } else {
+      write(&mut j)?;
+    }
+    recovered += 1;
+  }
+  Ok(recovered)
+}
+
+/// 续跑一次被中断的运行：只跑当时没完成的项，返回新的 run_id。
+pub fn resume(run_id: &str) -> Result<String, String> {
+  let j = load(run_id)?;
+  if !j.recovered {
+    return Err(format!("这次运行还没有被判定为中断，不能续跑：{}", run_id));
+  }
+  let unfinished = j.unfinished_item_ids();
+  if unfinished.is_empty() {
+    remove(run_id);
+    return Err(format!("这次运行没有需要续跑的项：{}", run_id));
+  }
+
+  let opts = RunOptions {
+    only: Some(unfinished),
+    trigger: Some(format!("{}{}", runs::TRIGGER_RESUME_PREFIX, run_id)),
+    on_drift: None,
+  };
+  let new_run_id = sync_manager::spawn_run(config::load_or_default()?, opts)?;
+  remove(run_id);
+  Ok(new_run_id)
+}
@@ -0,0 +1,289 @@
+//! 目标电脑的身份指纹（SSH 主机密钥）：第一次连接时记下来，以后每次连接都核对。
+//!
+//! 大白话：
+//! - 记在 `~/.agentsync/known_hosts`（OpenSSH 的格式），每个目标按 `host`（22 端口）或 `[host]:port` 一组
+//! - rclone 用 `known_hosts_file`、ssh 用 `UserKnownHostsFile` + `StrictHostKeyChecking=yes`，指纹对不上就拒绝连接
+//! - 测试连接时把指纹给用户看，确认后再记下（`trust`）；不会自动记下：没确认过就同步、检查、列备份，
+//!   一律直接失败（定时、监听、守护进程没人在场核对，自动记下等于谁冒充都认）
+//! - 指纹变了（重装系统，或者有人在中间冒充）不会自动更新：同步直接失败，要用户核对后重新确认
+//! - 目标电脑的每种密钥（ed25519 / ecdsa / rsa）都记下：rclone 不一定挑哪一种
+//!
+//! 我们推过去的配置里带着各种登录信息，局域网里被冒充就全漏了，所以宁可失败也不自动接受新指纹。
+
+use crate::config::{self, AgentSyncConfig};
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// rclone 报这两句说明目标电脑出示的指纹和记下的对不上
+const RCLONE_MISMATCH: &[&str] = &["knownhosts: key mismatch", "knownhosts: key is unknown"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HostKeyStatus {
+  /// 还没记下过
+  New,
+  /// 和记下的一致
+  Trusted,
+  /// 和记下的不一样
+  Changed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostKeyInfo {
+  /// `host` 或 `[host]:port`
+  pub host: String,
+  /// 例如 `ED25519`
+  pub key_type: String,
+  /// 目标电脑现在出示的指纹 `SHA256:...`
+  pub fingerprint: String,
+  pub status: HostKeyStatus,
+  /// 记下的指纹（`changed` 时和 `fingerprint` 不一样）
+  pub pinned_fingerprint: Option<String>,
+}
+
+/// ssh-keyscan 拿到的一把密钥
+struct ScannedKey {
+  /// 例如 `ssh-ed25519`
+  algo: String,
+  blob: String,
+  key_type: String,
+  fingerprint: String,
+}
+
+pub fn known_hosts_file() -> Result<PathBuf, String> {
+  Ok(config::agentsync_dir()?.join("known_hosts"))
+}
+
+/// known_hosts 里的主机写法：22 端口写 `host`，别的写 `[host]:port`。
+pub(crate) fn host_spec(host: &str, port: u16) -> String {
+  if port == 22 {
+    host.to_string()
+  } else {
+    format!("[{}]:{}", host, port)
+  }
+}
+
+/// 记下的这个主机的密钥：（算法, 内容）
+fn pinned_keys(spec: &str) -> Result<Vec<(String, String)>, String> {
+  let path = known_hosts_file()?;
+  let text = match fs::read_to_string(&path) {
+    Ok(t) => t,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+    Err(e) => return Err(format!("读取 {} 失败（{}）", path.display(), e)),
+  };
+  Ok(
+    text
+      .lines()
+      .filter_map(|l| {
+        let mut parts = l.split_whitespace();
+        let hosts = parts.next()?;
+        let algo = parts.next()?;
+        let blob = parts.next()?;
+        hosts
+          .split(',')
+          .any(|h| h == spec)
+          .then(|| (algo.to_string(), blob.to_string()))
+      })
+      .collect(),
+  )
+}
+
+/// 用 `ssh-keygen -lf -` 算指纹，每行一个：（指纹, 类型）
+fn fingerprints(keys: &[(String, String)]) -> Result<Vec<(String, String)>, String> {
+  let mut child = Command::new("ssh-keygen")
+    .args(["-l", "-f", "-"])
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(|e| format!("执行 ssh-keygen 失败（{}）。请确认已安装 OpenSSH 客户端。", e))?;
+  if let Some(mut stdin) = child.stdin.take() {
+    for (algo, blob) in keys {
+      let _ = writeln!(stdin, "{} {}", algo, blob);
+    }
+  }
+  let out = child
+    .wait_with_output()
+    .map_err(|e| format!("执行 ssh-keygen 失败（{}）", e))?;
+  // 256 SHA256:xxxx no comment (ED25519)
+  let list: Vec<(String, String)> = String::from_utf8_lossy(&out.stdout)
+    .lines()
+    .filter_map(|l| {
+      let fp = l.split_whitespace().find(|w| w.starts_with("SHA256:"))?;
+      let key_type = l.trim().rsplit_once('(').map(|(_, t)| t.trim_end_matches(')')).unwrap_or("");
+      Some((fp.to_string(), key_type.to_string()))
+    })
+    .collect();
+  if list.len() != keys.len() {
+    return Err(format!("算不出指纹：{}", String::from_utf8_lossy(&out.stderr).trim()));
+  }
+  Ok(list)
+}
+
+/// 用 `ssh-keyscan` 拿目标电脑的所有主机密钥，ed25519 排最前。
+fn scan(host: &str, port: u16) -> Result<Vec<ScannedKey>, String> {
+  let out = Command::new("ssh-keyscan")
+    .args(["-T", "5", "-p", &port.to_string(), host])
+    .output()
+    .map_err(|e| format!("执行 ssh-keyscan 失败（{}）。请确认已安装 OpenSSH 客户端。", e))?;
+  let mut keys: Vec<(String, String)> = String::from_utf8_lossy(&out.stdout)
+    .lines()
+    .filter(|l| !l.starts_with('#'))
+    .filter_map(|l| {
+      let mut parts = l.split_whitespace().skip(1);
+      Some((parts.next()?.to_string(), parts.next()?.to_string()))
+    })
+    .collect();
+  if keys.is_empty() {
+    // stderr 里 `#` 开头的是 ssh-keyscan 打印的对方版本，不是错误
+    let err = String::from_utf8_lossy(&out.stderr);
+    let err = err
+      .lines()
+      .find(|l| !l.starts_with('#') && !l.trim().is_empty())
+      .unwrap_or("目标端没有回主机密钥");
+    return Err(format!("没拿到主机密钥：{}", err.trim()));
+  }
+  let rank = |algo: &str| match algo {
+    "ssh-ed25519" => 0,
+    a if a.starts_with("ecdsa-") => 1,
+    "ssh-rsa" => 2,
+    _ => 3,
+  };
+  keys.sort_by_key(|(algo, _)| rank(algo));
+  keys.dedup();
+
+  let fps = fingerprints(&keys)?;
+  Ok(
+    keys
+      .into_iter()
+      .zip(fps)
+      .map(|((algo, blob), (fingerprint, key_type))| ScannedKey {
+        algo,
+        blob,
+        key_type,
+        fingerprint,
+      })
+      .collect(),
+  )
+}
+
+fn status_of(scanned: &[ScannedKey], pinned: &[(String, String)]) -> HostKeyStatus {
+  if pinned.is_empty() {
+    return HostKeyStatus::New;
+  }
+  // 同一种算法的密钥不一样就是变了；一把都对不上也算变了
+  let differs = scanned
+    .iter()
+    .any(|k| pinned.iter().any(|(a, b)| *a == k.algo && *b != k.blob));
+  let matches = scanned
+    .iter()
+    .any(|k| pinned.iter().any(|(a, b)| *a == k.algo && *b == k.blob));
+  if differs || !matches {
+    HostKeyStatus::Changed
+  } else {
+    HostKeyStatus::Trusted
+  }
+}
+
+/// 看目标电脑现在的指纹，和记下的比一比（不改文件）。
+pub fn check(host: &str, port: u16) -> Result<HostKeyInfo, String> {
+  let host = host.trim();
+  let spec = host_spec(host, port);
+  let scanned = scan(host, port)?;
+  let pinned = pinned_keys(&spec)?;
+  let status = status_of(&scanned, &pinned);
+  let main = &scanned[0];
+
+  let pinned_fingerprint = if pinned.is_empty() {
+    None
+  } else {
+    // 优先给和现在同一种算法的那把
+    let key = pinned
+      .iter()
+      .find(|(a, _)| *a == main.algo)
+      .unwrap_or(&pinned[0])
+      .clone();
+    fingerprints(&[key])?.into_iter().next().map(|(fp, _)| fp)
+  };
+  Ok(HostKeyInfo {
+    host: spec,
+    key_type: main.key_type.clone(),
+    fingerprint: main.fingerprint.clone(),
+    status,
+    pinned_fingerprint,
+  })
+}
+
+/// 把这个主机记下的密钥换成 `keys`（先写临时文件再改名）。
+fn write_pinned(spec: &str, keys: &[ScannedKey]) -> Result<(), String> {
+  let dir = config::agentsync_dir()?;
+  fs::create_dir_all(&dir).map_err(|e| format!("创建目录失败：{}（{}）", dir.display(), e))?;
+  let path = known_hosts_file()?;
+  let old = fs::read_to_string(&path).unwrap_or_default();
+  let mut text: String = old
+    .lines()
+    .filter(|l| {
+      !l.split_whitespace()
+        .next()
+        .is_some_and(|hosts| hosts.split(',').any(|h| h == spec))
+    })
+    .map(|l| format!("{}\n", l))
+    .collect();
+  for k in keys {
+    text.push_str(&format!("{} {} {}\n", spec, k.algo, k.blob));
+  }
+
+  let tmp = dir.join("known_hosts.tmp");
+  let write = || -> std::io::Result<()> {
+    let mut f = fs::File::create(&tmp)?;
+    f.write_all(text.as_bytes())?;
+    f.sync_all()
+  };
+  if let Err(e) = write() {
+    let _ = fs::remove_file(&tmp);
+    return Err(format!("写入 {} 失败（{}）", tmp.display(), e));
+  }
+  fs::rename(&tmp, &path).map_err(|e| format!("写入 {} 失败（{}）", path.display(), e))
+}
+
+/// 用户核对过 `fingerprint` 之后记下来（指纹变了之后重新确认也走这里）。
+/// 会重新拿一次指纹，和用户确认的不一样就不记（确认的那一刻可能已经被换了）。
+pub fn trust(host: &str, port: u16, fingerprint: &str) -> Result<HostKeyInfo, String> {
+  let host = host.trim();
+  let scanned = scan(host, port)?;
+  let fingerprint = fingerprint.trim();
+  if !scanned.iter().any(|k| k.fingerprint == fingerprint) {
+    return Err(format!(
+      "目标电脑现在的指纹是 {} {}，和确认的 {} 不一样，没有记下（请重新测试连接再核对）",
+      scanned[0].key_type, scanned[0].fingerprint, fingerprint
+    ));
+  }
+  write_pinned(&host_spec(host, port), &scanned)?;
+  check(host, port)
+}
+
+/// 同步等要连目标电脑之前调：还没确认过指纹就报错（不自动记下）；确认过就什么都不做，由 rclone / ssh 核对。
+pub(crate) fn require_pinned(cfg: &AgentSyncConfig) -> Result<(), String> {
+  let spec = host_spec(cfg.connection.host.trim(), cfg.connection.port);
+  if pinned_keys(&spec)?.is_empty() {
+    return Err(UNCONFIRMED_MESSAGE.to_string());
+  }
+  Ok(())
+}
+
+/// rclone 的这行输出是不是在说指纹对不上。
+pub(crate) fn is_mismatch(line: &str) -> bool {
+  RCLONE_MISMATCH.iter().any(|m| line.contains(m))
+}
+
+/// 指纹对不上时给用户看的话。
+pub(crate) const MISMATCH_MESSAGE: &str =
+  "目标电脑的身份指纹和记下的不一样，已拒绝连接：可能重装过系统，也可能有人在中间冒充。请在连接设置里测试连接，核对指纹后重新确认";
+
+/// 还没确认过指纹时给用户看的话。
+pub(crate) const UNCONFIRMED_MESSAGE: &str =
+  "目标电脑的身份指纹还没确认，不会连接：请先在连接设置里测试连接，核对指纹后确认（命令行用 agentsync host-key 查看、agentsync host-key --trust <指纹> 确认）";
@@ -1,25 +1,84 @@
 //! AgentSync 的 Tauri 后端：负责配置读写、连接测试、调用 rclone 执行同步。
+//!
+//! `config` / `rclone` / `runs` / `ssh_keys` 对外公开，命令行版（src/bin/agentsync.rs）直接复用。
+//! 同步引擎（同步状态、监听模式、定时同步）跑在守护进程里（见 `daemon`），界面只负责转发请求。
+//! 界面进程另外负责托盘图标和同步结束的系统通知（见 `tray`），以及配置文件被别处改动时通知界面（见 `config_watch`）。
 
-mod config;
-mod rclone;
-mod runs;
+pub mod bundle;
+pub mod config;
+mod config_watch;
+pub mod daemon;
+pub mod diagnose;
+pub mod discover;
+pub mod drift;
+pub mod estimate;
+mod excludes;
+mod git;
+mod hooks;
+mod journal;
+pub mod known_hosts;
+mod lockfile;
+pub mod logs;
+pub mod manifest;
+pub mod preflight;
+pub mod rclone;
+pub mod runs;
+mod scheduler;
 mod share_server;
-mod ssh_keys;
-mod sync_manager;
+pub mod stats;
+pub mod ssh_keys;
+pub mod sync_manager;
+pub mod validate;
+pub mod vars;
+mod tray;
+mod watcher;
+pub mod webhooks;
 
-use crate::config::AgentSyncConfig;
-use crate::runs::SyncRunSummary;
+use crate::bundle::{ExportOptions, ExportResult, ImportOptions, ImportResult};
+use crate::config::{AgentSyncConfig, DriftAction};
+use crate::discover::DiscoverResult;
+use crate::drift::DriftItemReport;
+use crate::estimate::SyncEstimate;
+use crate::known_hosts::HostKeyInfo;
+use crate::logs::RetentionResult;
+use crate::manifest::{ManifestEntry, ManifestHit};
+use crate::rclone::{ConnectionTestResult, VerifyResult};
+use crate::runs::{CompactResult, RunPage, RunQuery, SyncRunSummary};
+use crate::scheduler::ScheduleStatus;
 use crate::share_server::ShareStartResult;
 use crate::ssh_keys::EnsureSshKeypairResult;
+use crate::stats::HistoryStats;
 use crate::sync_manager::SyncStatus;
+use crate::validate::ValidationReport;
+use crate::watcher::WatchStatus;
+use crate::webhooks::NotifyResult;
+use serde::de::DeserializeOwned;
 use serde::Serialize;
-use uuid::Uuid;
+use serde_json::{json, Value};
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct ConnectionTestResult {
-  pub ok: bool,
-  pub message: String,
+pub struct ConfigSaveResult {
+  pub revision: u64,
+  pub warning: Option<String>,
+}
+
+/// 界面进程不直接跑同步：请求转给守护进程（没在跑就用 `<本程序> --daemon` 拉起来）。
+fn daemon_call<T: DeserializeOwned>(method: &str, params: Value) -> Result<T, String> {
+  let exe = std::env::current_exe().map_err(|e| format!("找不到当前程序路径（{}）", e))?;
+  daemon::ensure_running(&exe, &["--daemon"])?;
+  let v = daemon::request(method, params)?;
+  serde_json::from_value(v).map_err(|e| format!("守护进程回复格式无效（{}）", e))
+}
+
+/// 守护进程只用磁盘上的配置跑：界面传来的和保存的不一样（有没保存的修改）就先不跑，免得跑的不是眼前看到的设置。
+fn ensure_saved(config: &AgentSyncConfig) -> Result<(), String> {
+  let on_disk = config::load_or_default()?;
+  let same = serde_json::to_value(config).ok() == serde_json::to_value(&on_disk).ok();
+  if !same {
+    return Err("配置有没保存的修改，请先保存再开始".to_string());
+  }
+  Ok(())
 }
 
 #[tauri::command]
@@ -27,9 +86,66 @@ fn config_get() -> Result<AgentSyncConfig, String> {
   config::load_or_default()
 }
 
+/// 保存配置，返回新的版本号；`config.revision` 和磁盘上的对不上（别处改过）时报冲突。
+/// 保存成功但守护进程没能按新配置开监听时，放在 `warning` 里（配置已经写进去了，不能当失败）。
 #[tauri::command]
-fn config_save(config: AgentSyncConfig) -> Result<(), String> {
-  config::save_config(&config)
+fn config_save(config: AgentSyncConfig) -> Result<ConfigSaveResult, String> {
+  let revision = config::save_config(&config)?;
+  // 让守护进程按新配置调整监听模式（定时同步每次都读磁盘配置，不用通知）
+  let reload = if config.watch.enabled {
+    daemon_call::<Value>("config_reload", Value::Null).map(|_| ())
+  } else if daemon::ping().is_ok() {
+    daemon::request("config_reload", Value::Null).map(|_| ())
+  } else {
+    Ok(())
+  };
+  Ok(ConfigSaveResult {
+    revision,
+    warning: reload
+      .err()
+      .map(|e| format!("配置已保存，但监听模式没能按新配置启动：{}", e)),
+  })
+}
+
+/// 检查配置（界面上还没保存的也行），一次列出所有问题和对应字段。
+#[tauri::command]
+fn config_validate(config: AgentSyncConfig) -> ValidationReport {
+  validate::validate(&config)
+}
+
+/// 在 `discover.roots`（或传入的 `roots`）下找可以同步的项目和工具数据目录，只返回建议，不改配置。
+#[tauri::command]
+async fn projects_discover(
+  config: AgentSyncConfig,
+  roots: Option<Vec<String>>,
+  max_depth: Option<u32>,
+) -> Result<DiscoverResult, String> {
+  tauri::async_runtime::spawn_blocking(move || discover::discover(&config, roots.as_deref(), max_depth))
+    .await
+    .map_err(|e| format!("扫描项目异常中断（{}）", e))?
+}
+
+/// 把配置（可选隐去密钥路径）、历史、日志导出成一个 `.tar.gz`。
+#[tauri::command]
+async fn bundle_export(path: String, options: Option<ExportOptions>) -> Result<ExportResult, String> {
+  tauri::async_runtime::spawn_blocking(move || {
+    bundle::export(std::path::Path::new(&path), &options.unwrap_or_default())
+  })
+  .await
+  .map_err(|e| format!("导出异常中断（{}）", e))?
+}
+
+/// 把导出的包合并进本机配置；项目路径按 `options.pathMap` 换成本机的。
+#[tauri::command]
+async fn bundle_import(path: String, options: Option<ImportOptions>) -> Result<ImportResult, String> {
+  let opts = options.unwrap_or_default();
+  let res = tauri::async_runtime::spawn_blocking(move || bundle::import(std::path::Path::new(&path), &opts))
+    .await
+    .map_err(|e| format!("导入异常中断（{}）", e))??;
+  if !res.dry_run && daemon::ping().is_ok() {
+    daemon::request("config_reload", Value::Null)?;
+  }
+  Ok(res)
 }
 
 #[tauri::command]
@@ -37,86 +153,171 @@ fn runs_list() -> Result<Vec<SyncRunSummary>, String> {
   runs::list_runs()
 }
 
+/// 分页 / 过滤查询历史（目标、状态、时间范围、同步项），顺带报告坏行。
+#[tauri::command]
+fn runs_query(query: RunQuery) -> Result<RunPage, String> {
+  runs::query_runs(&query)
+}
+
+/// 整理历史文件：去重、旧记录挪进归档、坏行挪到单独的文件。
+#[tauri::command]
+fn history_compact(keep_days: Option<u32>) -> Result<CompactResult, String> {
+  runs::compact_history(keep_days.unwrap_or(runs::DEFAULT_KEEP_DAYS))
+}
+
+/// 按保留策略清理运行日志（界面上还没保存的配置也能直接试）。
+#[tauri::command]
+async fn logs_cleanup(config: AgentSyncConfig) -> Result<RetentionResult, String> {
+  tauri::async_runtime::spawn_blocking(move || logs::enforce(&config.log_retention, None))
+    .await
+    .map_err(|e| format!("清理日志异常中断（{}）", e))?
+}
+
 #[tauri::command]
 fn run_log_read(run_id: String) -> Result<String, String> {
   runs::read_log(&run_id)
 }
 
+/// 历史统计：成功率、用时、字节趋势、很久没成功的项等；`days` 为空统计全部历史。
 #[tauri::command]
-fn connection_test(config: AgentSyncConfig) -> Result<ConnectionTestResult, String> {
-  let res = rclone::test_connection(&config)?;
-  Ok(ConnectionTestResult {
-    ok: res.ok,
-    message: res.message,
-  })
+fn history_stats(days: Option<u32>) -> Result<HistoryStats, String> {
+  stats::history_stats(days)
 }
 
+/// 某次运行传输 / 删除 / 备份了哪些文件。
 #[tauri::command]
-async fn sync_run(config: AgentSyncConfig) -> Result<SyncRunSummary, String> {
-  tauri::async_runtime::spawn_blocking(move || rclone::run_sync(&config))
-    .await
-    .map_err(|e| format!("同步任务异常中断（{}）", e))?
+fn run_manifest_read(run_id: String) -> Result<Vec<ManifestEntry>, String> {
+  manifest::load(&run_id)
 }
 
+/// 跨所有运行查某个文件什么时候被推送 / 删除过（新的在前）。
 #[tauri::command]
-async fn sync_start(config: AgentSyncConfig) -> Result<String, String> {
-  rclone::validate_for_run(&config)?;
-  let run_id = Uuid::new_v4().simple().to_string();
-  let started_at_ms = {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    SystemTime::now()
-      .duration_since(UNIX_EPOCH)
-      .unwrap_or_default()
-      .as_millis() as u64
-  };
-  let total = rclone::estimate_total_items(&config)?;
-  sync_manager::start_run(run_id.clone(), started_at_ms, total)?;
-
-  tauri::async_runtime::spawn_blocking({
-    let run_id = run_id.clone();
-    move || {
-      struct Progress;
-      impl rclone::SyncProgress for Progress {
-        fn on_item_start(&mut self, label: &str) {
-          let _ = sync_manager::set_current_label(label.to_string());
-        }
+fn manifest_find(query: String, item_id: Option<String>, limit: Option<usize>) -> Result<Vec<ManifestHit>, String> {
+  manifest::find(&query, item_id.as_deref(), limit)
+}
 
-        fn on_line(&mut self, line: &str) {
-          let _ = sync_manager::push_line(line.to_string());
-        }
+/// 测试连接，顺便做同步前检查（会在本机数一遍要同步的文件，可能要一会儿）。
+#[tauri::command]
+async fn connection_test(config: AgentSyncConfig) -> Result<ConnectionTestResult, String> {
+  tauri::async_runtime::spawn_blocking(move || rclone::test_connection(&config))
+    .await
+    .map_err(|e| format!("测试连接异常中断（{}）", e))?
+}
 
-        fn on_item_done(&mut self, result: &crate::runs::SyncItemResult) {
-          let _ = sync_manager::push_item_result(result.clone());
-        }
-      }
+/// 目标电脑现在的身份指纹，和记下的比一比（不改记录）。
+#[tauri::command]
+async fn host_key_check(config: AgentSyncConfig) -> Result<HostKeyInfo, String> {
+  tauri::async_runtime::spawn_blocking(move || known_hosts::check(&config.connection.host, config.connection.port))
+    .await
+    .map_err(|e| format!("读取主机指纹异常中断（{}）", e))?
+}
 
-      let mut progress = Progress;
-      let result = rclone::run_sync_with_id(&config, run_id.clone(), &mut progress);
+/// 用户核对过指纹后记下来（指纹变了之后重新确认也用这个）；现在的指纹和确认的不一样时报错、不记。
+#[tauri::command]
+async fn host_key_trust(config: AgentSyncConfig, fingerprint: String) -> Result<HostKeyInfo, String> {
+  tauri::async_runtime::spawn_blocking(move || {
+    known_hosts::trust(&config.connection.host, config.connection.port, &fingerprint)
+  })
+  .await
+  .map_err(|e| format!("记下主机指纹异常中断（{}）", e))?
+}
 
-      match result {
-        Ok(summary) => {
-          let _ = sync_manager::finish_ok(summary);
-        }
-        Err(e) => {
-          let ended_at_ms = {
-            use std::time::{SystemTime, UNIX_EPOCH};
-            SystemTime::now()
-              .duration_since(UNIX_EPOCH)
-              .unwrap_or_default()
-              .as_millis() as u64
-          };
-          let _ = sync_manager::finish_err(run_id, ended_at_ms, e);
-        }
+/// 跑一次同步并等到结束。和 `sync_start` 一样交给守护进程跑（同一时间只跑一个，能看进度、能取消），只是在这里等结果。
+#[tauri::command]
+async fn sync_run(config: AgentSyncConfig) -> Result<SyncRunSummary, String> {
+  tauri::async_runtime::spawn_blocking(move || {
+    ensure_saved(&config)?;
+    let run_id: String = daemon_call("sync_start", json!({ "trigger": runs::TRIGGER_MANUAL }))?;
+    loop {
+      std::thread::sleep(std::time::Duration::from_millis(500));
+      let st: SyncStatus = daemon_call("sync_status", Value::Null)?;
+      if st.run_id.as_deref() != Some(run_id.as_str()) {
+        return Err(format!("守护进程里已经找不到这次同步（{}），请到历史记录查看结果", run_id));
+      }
+      if !st.running {
+        return st
+          .summary
+          .ok_or_else(|| st.error.unwrap_or_else(|| "同步失败（原因未知）".to_string()));
       }
     }
-  });
+  })
+  .await
+  .map_err(|e| format!("同步任务异常中断（{}）", e))?
+}
 
-  Ok(run_id)
+#[tauri::command]
+fn sync_start(config: AgentSyncConfig, on_drift: Option<DriftAction>) -> Result<String, String> {
+  ensure_saved(&config)?;
+  daemon_call("sync_start", json!({ "trigger": runs::TRIGGER_MANUAL, "onDrift": on_drift }))
 }
 
 #[tauri::command]
 fn sync_status() -> Result<SyncStatus, String> {
-  sync_manager::get_status()
+  daemon_call("sync_status", Value::Null)
+}
+
+#[tauri::command]
+fn sync_cancel() -> Result<(), String> {
+  daemon_call("sync_cancel", Value::Null)
+}
+
+#[tauri::command]
+fn watch_start(config: AgentSyncConfig) -> Result<WatchStatus, String> {
+  ensure_saved(&config)?;
+  daemon_call("watch_start", Value::Null)
+}
+
+#[tauri::command]
+fn watch_stop() -> Result<(), String> {
+  daemon_call::<Value>("watch_stop", Value::Null).map(|_| ())
+}
+
+#[tauri::command]
+fn watch_status() -> Result<WatchStatus, String> {
+  daemon_call("watch_status", Value::Null)
+}
+
+#[tauri::command]
+fn schedule_status() -> Result<Vec<ScheduleStatus>, String> {
+  daemon_call("schedule_status", Value::Null)
+}
+
+/// 续跑一次被中断的运行（只跑当时没完成的项），返回新的 run_id。
+#[tauri::command]
+fn run_resume(run_id: String) -> Result<String, String> {
+  daemon_call("run_resume", json!({ "runId": run_id }))
+}
+
+/// 逐项比较本机和目标端（`rclone check`），只读；`item_ids` 为空时检查全部启用项。
+#[tauri::command]
+async fn sync_verify(config: AgentSyncConfig, item_ids: Option<Vec<String>>) -> Result<VerifyResult, String> {
+  tauri::async_runtime::spawn_blocking(move || rclone::verify(&config, item_ids.as_deref()))
+    .await
+    .map_err(|e| format!("校验任务异常中断（{}）", e))?
+}
+
+/// 同步前估算：每一项按排除规则在本机数文件和大小，超过阈值给出提醒（只读，不连目标电脑）。
+#[tauri::command]
+async fn sync_estimate(config: AgentSyncConfig, item_ids: Option<Vec<String>>) -> Result<SyncEstimate, String> {
+  tauri::async_runtime::spawn_blocking(move || estimate::estimate(&config, item_ids.as_deref()))
+    .await
+    .map_err(|e| format!("估算任务异常中断（{}）", e))?
+}
+
+/// 同步前先看看：每一项上次同步后目标端被改过的文件（只读）。
+#[tauri::command]
+async fn sync_drift_check(config: AgentSyncConfig, item_ids: Option<Vec<String>>) -> Result<Vec<DriftItemReport>, String> {
+  tauri::async_runtime::spawn_blocking(move || drift::check(&config, item_ids.as_deref()))
+    .await
+    .map_err(|e| format!("检查目标端改动异常中断（{}）", e))?
+}
+
+/// 给所有通知目标发一条测试消息（用界面上还没保存的配置）。
+#[tauri::command]
+async fn notify_test(config: AgentSyncConfig) -> Result<Vec<NotifyResult>, String> {
+  tauri::async_runtime::spawn_blocking(move || webhooks::send_test(&config))
+    .await
+    .map_err(|e| format!("发送测试通知失败（{}）", e))
 }
 
 #[tauri::command]
@@ -126,7 +327,10 @@ fn ssh_keypair_ensure(force: bool) -> Result<EnsureSshKeypairResult, String> {
 
 #[tauri::command]
 fn ssh_public_key_read(private_key_path: String) -> Result<String, String> {
-  ssh_keys::read_public_key(std::path::Path::new(&private_key_path))
+  // 路径里可能写了 `~`、`${HOME}`、自定义变量
+  let cfg = config::load_or_default()?;
+  let path = vars::expand_local(&private_key_path, &cfg.variables)?;
+  ssh_keys::read_public_key(std::path::Path::new(&path))
 }
 
 #[tauri::command]
@@ -134,19 +338,72 @@ fn share_start(content: String) -> Result<ShareStartResult, String> {
   share_server::share_start(content, None)
 }
 
+/// `<本程序> --daemon`：以守护进程身份运行（不打开窗口）。
+pub fn run_daemon() {
+  if let Err(e) = daemon::serve() {
+    eprintln!("{}", e);
+    std::process::exit(1);
+  }
+}
+
 pub fn run() {
   tauri::Builder::default()
     .plugin(tauri_plugin_opener::init())
     .plugin(tauri_plugin_dialog::init())
+    .plugin(tauri_plugin_notification::init())
+    .setup(|app| {
+      // 托盘图标 + 同步结束通知
+      tray::setup(app)?;
+
+      // 命令行 / 另一个窗口改了配置文件时通知界面（失败不影响打开界面）
+      let _ = config_watch::start(app.handle().clone());
+
+      // 打开界面时顺手拉起守护进程：监听模式 / 定时同步在那边生效（失败不影响打开界面）
+      std::thread::spawn(|| {
+        if let Ok(exe) = std::env::current_exe() {
+          let _ = daemon::ensure_running(&exe, &["--daemon"]);
+        }
+      });
+      Ok(())
+    })
+    .on_window_event(|window, event| {
+      // 关窗口只是藏到托盘里，真正退出走托盘菜单的“退出”
+      if let tauri::WindowEvent::CloseRequested { api, .. } = event {
+        let _ = window.hide();
+        api.prevent_close();
+      }
+    })
     .invoke_handler(tauri::generate_handler![
       config_get,
       config_save,
+      config_validate,
+      projects_discover,
       connection_test,
+      host_key_check,
+      host_key_trust,
       sync_run,
       sync_start,
       sync_status,
+      sync_cancel,
+      sync_verify,
+      sync_estimate,
+      sync_drift_check,
+      watch_start,
+      watch_stop,
+      watch_status,
+      schedule_status,
+      bundle_export,
+      bundle_import,
       runs_list,
+      runs_query,
+      history_compact,
+      logs_cleanup,
       run_log_read,
+      history_stats,
+      run_manifest_read,
+      manifest_find,
+      run_resume,
+      notify_test,
       ssh_keypair_ensure,
       ssh_public_key_read,
       share_start
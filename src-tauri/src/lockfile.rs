@@ -0,0 +1,47 @@
+//! 跨进程的文件锁：界面、守护进程、命令行会同时改 `~/.agentsync` 下的同一个文件（历史、配置）。
+//!
+//! 大白话：
+//! - 打开（没有就建）`<文件>.lock`，对它加系统的独占锁（unix 是 flock，Windows 是 LockFileEx）
+//! - 别人拿着就隔 50 毫秒再试，最多等 `WAIT`
+//! - 锁跟着文件句柄走：用完（drop）或进程崩了都由系统释放，不会留下“死锁文件”
+//! - 锁文件本身留着不删：删了的话，别的进程可能正锁着旧文件，新来的又建了个新文件，两边都以为拿到了锁
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const WAIT: Duration = Duration::from_secs(10);
+
+/// 拿着就是持有锁；drop 时关掉句柄，系统随之释放锁。
+pub(crate) struct FileLock {
+  _file: fs::File,
+}
+
+/// 给 `target` 加锁（锁文件是同目录下的 `<文件名>.lock`），拿不到就等，等太久报错。
+pub(crate) fn lock(target: &Path) -> Result<FileLock, String> {
+  let mut name = target.file_name().unwrap_or_default().to_os_string();
+  name.push(".lock");
+  let path = target.with_file_name(name);
+
+  let file = fs::OpenOptions::new()
+    .read(true)
+    .write(true)
+    .create(true)
+    .truncate(false)
+    .open(&path)
+    .map_err(|e| format!("创建锁文件失败：{}（{}）", path.display(), e))?;
+
+  let deadline = Instant::now() + WAIT;
+  loop {
+    match file.try_lock() {
+      Ok(()) => return Ok(FileLock { _file: file }),
+      Err(fs::TryLockError::WouldBlock) => {
+        if Instant::now() >= deadline {
+          return Err(format!("{} 正被别的 AgentSync 进程占用，请稍后重试", target.display()));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+      }
+      Err(fs::TryLockError::Error(e)) => return Err(format!("加锁失败：{}（{}）", path.display(), e)),
+    }
+  }
+}
@@ -0,0 +1,170 @@
+//! 运行日志的保留策略：监听模式下每次同步都有一份日志（还带每秒的进度行），不管的话会攒到几个 GB。
+//!
+//! 规则（大白话，按 `logRetention` 配置）：
+//! - 超过 `maxAgeDays` 天的删掉；按新旧排，第 `maxCount` 份之后的删掉；加起来超过 `maxTotalMb` 时从最旧的删起
+//! - 留下来的、超过 `compressAfterDays` 天的压缩成 `<run_id>.log.gz`（修改时间不变，读日志时自动解压）
+//! - 正在跑 / 等着续跑的同步（`~/.agentsync/runs/` 里有运行日志）的日志不动
+//! - 结果写回历史：压缩的改 `logPath`，删掉的标 `logMissing`（记录本身保留）
+
+use crate::config::{self, LogRetentionConfig};
+use crate::runs;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionResult {
+  pub compressed: usize,
+  pub deleted: usize,
+  /// 历史里改了 `logPath` / 标了 `logMissing` 的记录数
+  pub history_updated: usize,
+  pub bytes_before: u64,
+  pub bytes_after: u64,
+  /// 处理单个文件失败的说明（不影响其他文件）
+  pub errors: Vec<String>,
+}
+
+struct LogFile {
+  run_id: String,
+  path: PathBuf,
+  compressed: bool,
+  size: u64,
+  modified: SystemTime,
+}
+
+fn list_logs(dir: &Path) -> Vec<LogFile> {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return vec![];
+  };
+  let mut out: Vec<LogFile> = entries
+    .map_while(Result::ok)
+    .filter_map(|e| {
+      let name = e.file_name().to_string_lossy().into_owned();
+      let (run_id, compressed) = match name.strip_suffix(".log.gz") {
+        Some(id) => (id.to_string(), true),
+        None => (name.strip_suffix(".log")?.to_string(), false),
+      };
+      let meta = e.metadata().ok().filter(|m| m.is_file())?;
+      Some(LogFile {
+        run_id,
+        path: e.path(),
+        compressed,
+        size: meta.len(),
+        modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+      })
+    })
+    .collect();
+  // 新的在前
+  out.sort_by_key(|f| std::cmp::Reverse(f.modified));
+  out
+}
+
+/// 还在跑 / 等着续跑的同步：它们的日志还会继续写。
+fn active_run_ids() -> Vec<String> {
+  let Ok(dir) = config::runs_dir() else {
+    return vec![];
+  };
+  let Ok(entries) = fs::read_dir(&dir) else {
+    return vec![];
+  };
+  entries
+    .map_while(Result::ok)
+    .filter_map(|e| e.file_name().to_string_lossy().strip_suffix(".json").map(str::to_string))
+    .collect()
+}
+
+fn older_than(modified: SystemTime, days: u32) -> bool {
+  days > 0
+    && SystemTime::now()
+      .duration_since(modified)
+      .is_ok_and(|age| age > Duration::from_secs(days as u64 * 24 * 3600))
+}
+
+/// 压缩成 `.log.gz`（先写临时文件再改名），保留原来的修改时间，成功后删掉原文件。返回压缩后的大小。
+fn compress(f: &LogFile) -> Result<(PathBuf, u64), String> {
+  let gz = runs::compressed_log_path(&f.run_id)?;
+  let tmp = gz.with_extension("gz.tmp");
+  let write = || -> io::Result<u64> {
+    let mut src = fs::File::open(&f.path)?;
+    let mut enc = GzEncoder::new(fs::File::create(&tmp)?, Compression::default());
+    io::copy(&mut src, &mut enc)?;
+    let out = enc.finish()?;
+    out.set_modified(f.modified)?;
+    out.sync_all()?;
+    Ok(out.metadata()?.len())
+  };
+  let size = match write() {
+    Ok(n) => n,
+    Err(e) => {
+      let _ = fs::remove_file(&tmp);
+      return Err(format!("压缩日志失败：{}（{}）", f.path.display(), e));
+    }
+  };
+  fs::rename(&tmp, &gz).map_err(|e| format!("压缩日志失败：{}（{}）", gz.display(), e))?;
+  fs::remove_file(&f.path).map_err(|e| format!("删除已压缩的日志失败：{}（{}）", f.path.display(), e))?;
+  Ok((gz, size))
+}
+
+/// 按保留策略清理一次日志目录。`keep_run_id` 是刚结束、日志文件可能还开着的那次运行。
+pub fn enforce(policy: &LogRetentionConfig, keep_run_id: Option<&str>) -> Result<RetentionResult, String> {
+  let dir = config::logs_dir()?;
+  let active = active_run_ids();
+  let files: Vec<LogFile> = list_logs(&dir)
+    .into_iter()
+    .filter(|f| keep_run_id != Some(f.run_id.as_str()) && !active.contains(&f.run_id))
+    .collect();
+
+  let mut result = RetentionResult {
+    bytes_before: files.iter().map(|f| f.size).sum(),
+    ..Default::default()
+  };
+  let max_total = policy.max_total_mb.saturating_mul(1024 * 1024);
+  let mut changes = runs::LogChanges::new();
+  let mut kept_bytes: u64 = 0;
+  let mut kept_count: u32 = 0;
+
+  for f in &files {
+    let too_many = policy.max_count > 0 && kept_count >= policy.max_count;
+    let mut path = f.path.clone();
+    let mut size = f.size;
+    if !too_many && !older_than(f.modified, policy.max_age_days) {
+      // 先压缩再算总大小：压缩后通常只剩十分之一，能多留不少
+      if !f.compressed && older_than(f.modified, policy.compress_after_days) {
+        match compress(f) {
+          Ok((gz, n)) => {
+            result.compressed += 1;
+            changes.insert(f.run_id.clone(), Some(gz.display().to_string()));
+            path = gz;
+            size = n;
+          }
+          Err(e) => result.errors.push(e),
+        }
+      }
+      if max_total == 0 || kept_bytes + size <= max_total {
+        kept_count += 1;
+        kept_bytes += size;
+        continue;
+      }
+    }
+
+    match fs::remove_file(&path) {
+      Ok(()) => {
+        result.deleted += 1;
+        changes.insert(f.run_id.clone(), None);
+      }
+      Err(e) => {
+        result.errors.push(format!("删除日志失败：{}（{}）", path.display(), e));
+        kept_bytes += size;
+      }
+    }
+  }
+
+  result.bytes_after = kept_bytes;
+  result.history_updated = runs::apply_log_changes(&changes)?;
+  Ok(result)
+}
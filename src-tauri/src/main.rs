@@ -1,6 +1,11 @@
-// Prevents additional console window on Windows in release, DO NOT REMOVE!!
-#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
-
-fn main() {
-    tauri_app_lib::run()
-}
+// Prevents additional console window on Windows in release, DO NOT REMOVE!!
+#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+
+fn main() {
+    // 界面进程会用 `--daemon` 拉起自己作为后台守护进程
+    if std::env::args().nth(1).as_deref() == Some("--daemon") {
+        tauri_app_lib::run_daemon();
+        return;
+    }
+    tauri_app_lib::run()
+}
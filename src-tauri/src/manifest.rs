@@ -0,0 +1,170 @@
+//! 每次运行的文件清单（manifest）：这次到底传了哪些文件、删了哪些、备份到了哪。
+//!
+//! 规则（大白话）：
+//! - 每一项的 rclone 都带上 `--combined <临时文件>`，跑完把它整理成 `~/.agentsync/manifests/<run_id>.jsonl`
+//! - 一行一个文件，只记有变化的（新增 / 替换 / 删除 / 出错），没变的不记
+//! - 替换和删除的文件在目标端备份目录里有旧版本，`backupPath` 就是它的位置
+//! - 历史记录里的 `manifestPath` 指向这个文件；`find` 可以跨所有运行查某个文件
+
+use crate::config;
+use crate::runs;
+use globset::Glob;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ManifestAction {
+  /// 目标端原来没有，新传过去的
+  Added,
+  /// 目标端有旧版本，被替换了（旧版本进了备份目录）
+  Updated,
+  /// 本机已经没有，镜像删除把目标端的挪进了备份目录
+  Deleted,
+  /// rclone 处理这个文件时出错
+  Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+  pub item_id: String,
+  /// 相对该项根目录的路径（单文件项就是文件名）
+  pub path: String,
+  pub action: ManifestAction,
+  /// 目标端备份目录里的旧版本（`updated` / `deleted` 才有）
+  #[serde(default)]
+  pub backup_path: Option<String>,
+}
+
+/// 跨运行查询的一条结果。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestHit {
+  pub run_id: String,
+  pub started_at_ms: u64,
+  #[serde(flatten)]
+  pub entry: ManifestEntry,
+}
+
+fn manifest_path(run_id: &str) -> Result<PathBuf, String> {
+  Ok(config::manifests_dir()?.join(format!("{}.jsonl", run_id)))
+}
+
+/// 这次运行有清单文件时返回它的路径（写进 `SyncRunSummary.manifest_path`）。
+pub fn existing_path(run_id: &str) -> Option<String> {
+  manifest_path(run_id)
+    .ok()
+    .filter(|p| p.is_file())
+    .map(|p| p.display().to_string())
+}
+
+/// 给一项的 rclone 用的 `--combined` 输出位置（跑完由 `record` 收走并删除）。
+pub(crate) fn combined_temp_path() -> PathBuf {
+  std::env::temp_dir().join(format!("agentsync-combined-{}.txt", Uuid::new_v4().simple()))
+}
+
+/// 把一项的 `--combined` 输出整理进本次运行的清单。`backup_dir` 是这一项在目标端的备份目录。
+pub(crate) fn record(
+  run_id: &str,
+  item_id: &str,
+  combined: &Path,
+  mirror_delete: bool,
+  backup_dir: &str,
+) -> Result<usize, String> {
+  let text = fs::read_to_string(combined).unwrap_or_default();
+  let _ = fs::remove_file(combined);
+
+  // --combined：`+` 目标端没有、`*` 两边不同、`-` 只有目标端有、`!` 出错、`=` 一致
+  let entries: Vec<ManifestEntry> = text
+    .lines()
+    .filter_map(|line| line.split_once(' '))
+    .filter_map(|(mark, path)| {
+      let action = match mark {
+        "+" => ManifestAction::Added,
+        "*" => ManifestAction::Updated,
+        "-" if mirror_delete => ManifestAction::Deleted,
+        "!" => ManifestAction::Error,
+        _ => return None,
+      };
+      let backed_up = matches!(action, ManifestAction::Updated | ManifestAction::Deleted);
+      Some(ManifestEntry {
+        item_id: item_id.to_string(),
+        path: path.to_string(),
+        action,
+        backup_path: backed_up.then(|| format!("{}/{}", backup_dir.trim_end_matches('/'), path)),
+      })
+    })
+    .collect();
+  if entries.is_empty() {
+    return Ok(0);
+  }
+
+  config::ensure_dir(&config::manifests_dir()?)?;
+  let path = manifest_path(run_id)?;
+  let mut f = fs::OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(&path)
+    .map_err(|e| format!("写入文件清单失败：{}（{}）", path.display(), e))?;
+  for e in &entries {
+    let line = serde_json::to_string(e).map_err(|e| format!("序列化文件清单失败（{}）", e))?;
+    writeln!(f, "{}", line).map_err(|e| format!("写入文件清单失败（{}）", e))?;
+  }
+  Ok(entries.len())
+}
+
+/// 读某次运行的清单；这次没有任何文件变化时返回空。
+pub fn load(run_id: &str) -> Result<Vec<ManifestEntry>, String> {
+  let path = manifest_path(run_id)?;
+  if !path.exists() {
+    return Ok(vec![]);
+  }
+  let f = fs::File::open(&path).map_err(|e| format!("读取文件清单失败：{}（{}）", path.display(), e))?;
+  Ok(
+    BufReader::new(f)
+      .lines()
+      .map_while(Result::ok)
+      .filter_map(|line| serde_json::from_str(&line).ok())
+      .collect(),
+  )
+}
+
+/// 跨所有运行查文件（新的在前）：`query` 带 `*` / `?` 时按通配符匹配整个路径，否则按包含匹配。
+pub fn find(query: &str, item_id: Option<&str>, limit: Option<usize>) -> Result<Vec<ManifestHit>, String> {
+  let query = query.trim();
+  if query.is_empty() {
+    return Err("请填写要查的文件路径".to_string());
+  }
+  let glob = if query.contains(['*', '?', '[']) {
+    let g = Glob::new(query).map_err(|e| format!("通配符无效：{}（{}）", query, e))?;
+    Some(g.compile_matcher())
+  } else {
+    None
+  };
+  let matches = |path: &str| match &glob {
+    Some(m) => m.is_match(path),
+    None => path.contains(query),
+  };
+
+  let mut out = Vec::new();
+  for run in runs::list_runs()? {
+    for entry in load(&run.run_id)? {
+      if item_id.is_some_and(|id| id != entry.item_id) || !matches(&entry.path) {
+        continue;
+      }
+      out.push(ManifestHit {
+        run_id: run.run_id.clone(),
+        started_at_ms: run.started_at_ms,
+        entry,
+      });
+      if limit.is_some_and(|n| out.len() >= n) {
+        return Ok(out);
+      }
+    }
+  }
+  Ok(out)
+}
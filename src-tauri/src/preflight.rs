@@ -0,0 +1,417 @@
+//! 测试连接时顺便做的同步前检查：目标端空间够不够、是什么系统、分不分大小写、文件名能不能用。
+//!
+//! 大白话：
+//! - 每项检查单独给结果：`ok` 没问题 / `warn` 能同步但要注意 / `fail` 同步会出问题 / `skipped` 没法查或不用查
+//! - 剩余空间：先问 rclone（`rclone about`），不支持再 ssh 上去跑 `df`；和同步前估算（`estimate`）的总量比
+//! - 系统和用户目录：ssh 上去跑一条 sh / cmd / PowerShell 都能跑的 `echo`
+//! - 大小写：在目标端建两个只差大小写的空文件，看是不是变成了一个；不区分大小写时在本机找只差大小写的文件（同步过去会互相覆盖）
+//! - 文件名：目标端是 Windows 时，在本机找 Windows 上不能用的文件名（`<>:"|?*`、`CON`、结尾是点或空格等）
+//! - 本机文件按排除规则找（和同步一致）
+
+use crate::config::AgentSyncConfig;
+use crate::diagnose::{CheckStatus, ConnectionCheck};
+use crate::estimate;
+use crate::excludes::ExcludeMatcher;
+use crate::hooks;
+use crate::rclone;
+use crate::webhooks::human_bytes;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// 本机最多看这么多个文件，超过就停
+const MAX_FILES: u64 = 500_000;
+/// 每项检查最多列几个有问题的文件
+const MAX_EXAMPLES: usize = 10;
+
+/// sh 里 `%OS%` 原样输出、`$HOME` 展开；cmd 里正好反过来；PowerShell 只展开 `$HOME`
+const PROBE: &str = "echo \"%OS%|$HOME|%USERPROFILE%|$(uname -s)\"";
+
+const WINDOWS_RESERVED: &[&str] = &[
+  "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+  "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+struct RemoteEnv {
+  os: String,
+  windows: bool,
+  home: String,
+}
+
+fn looks_like_windows_path(s: &str) -> bool {
+  let b = s.as_bytes();
+  b.len() >= 3 && b[0].is_ascii_alphabetic() && b[1] == b':' && (b[2] == b'\\' || b[2] == b'/')
+}
+
+/// ssh 上去看目标端是什么系统、用户目录在哪。
+fn remote_env(cfg: &AgentSyncConfig) -> Result<RemoteEnv, String> {
+  let out = hooks::ssh_command(cfg, PROBE)
+    .output()
+    .map_err(|e| format!("运行 ssh 失败（{}）", e))?;
+  let stdout = String::from_utf8_lossy(&out.stdout);
+  // PowerShell 里没有 uname 会报错，但前面几段还是有的：有输出就用
+  let line = stdout.lines().find(|l| l.contains('|')).map(|l| l.trim().trim_matches('"'));
+  let Some(line) = line else {
+    let err = String::from_utf8_lossy(&out.stderr);
+    return Err(format!("ssh 上去跑命令失败：{}", err.trim()));
+  };
+  let parts: Vec<&str> = line.split('|').map(str::trim).collect();
+  let field = |i: usize| parts.get(i).copied().unwrap_or("");
+
+  if field(0) == "Windows_NT" {
+    return Ok(RemoteEnv {
+      os: "Windows".to_string(),
+      windows: true,
+      home: field(2).to_string(),
+    });
+  }
+  if looks_like_windows_path(field(1)) {
+    return Ok(RemoteEnv {
+      os: "Windows（PowerShell）".to_string(),
+      windows: true,
+      home: field(1).to_string(),
+    });
+  }
+  let os = match field(3) {
+    "Linux" => "Linux".to_string(),
+    "Darwin" => "macOS".to_string(),
+    "" => "类 Unix（uname 没有输出）".to_string(),
+    other => other.to_string(),
+  };
+  Ok(RemoteEnv {
+    os,
+    windows: false,
+    home: field(1).to_string(),
+  })
+}
+
+/// 目标端 `root` 所在磁盘的剩余字节数：先 `rclone about`，不行再 ssh 跑 `df`（只有类 Unix 能用）。
+fn remote_free_bytes(
+  cfg: &AgentSyncConfig,
+  rclone_path: &Path,
+  rclone_conf: &Path,
+  root: &str,
+  env: Option<&RemoteEnv>,
+) -> Result<u64, String> {
+  let mut args = rclone::make_common_args(cfg, rclone_conf);
+  args.extend(["about".to_string(), format!("remote:{}", root), "--json".to_string()]);
+  let out = rclone::rclone_output(rclone_path, &args)?;
+  if out.status.success() {
+    let free = serde_json::from_slice::<serde_json::Value>(&out.stdout)
+      .ok()
+      .and_then(|v| v.get("free").and_then(|f| f.as_u64()));
+    if let Some(free) = free {
+      return Ok(free);
+    }
+  }
+  let about_err = String::from_utf8_lossy(&out.stderr).trim().to_string();
+
+  let unix = env.is_some_and(|e| !e.windows);
+  if !unix {
+    return Err(format!("rclone about 查不到（{}），目标端也不能跑 df", about_err));
+  }
+  let command = if root.is_empty() {
+    "df -Pk .".to_string()
+  } else {
+    format!("cd {} && df -Pk .", hooks::sh_quote(root))
+  };
+  let out = hooks::ssh_command(cfg, &command)
+    .output()
+    .map_err(|e| format!("运行 ssh 失败（{}）", e))?;
+  let text = String::from_utf8_lossy(&out.stdout);
+  // Filesystem 1024-blocks Used Available Capacity Mounted-on
+  text
+    .lines()
+    .nth(1)
+    .and_then(|l| l.split_whitespace().nth(3))
+    .and_then(|n| n.parse::<u64>().ok())
+    .map(|kb| kb * 1024)
+    .ok_or_else(|| {
+      format!(
+        "rclone about 查不到（{}），df 也没读出来：{}",
+        about_err,
+        String::from_utf8_lossy(&out.stderr).trim()
+      )
+    })
+}
+
+/// 在目标端 `root` 下建两个只差大小写的空文件，看是不是变成了一个。返回是否不区分大小写。
+fn remote_case_insensitive(
+  cfg: &AgentSyncConfig,
+  rclone_path: &Path,
+  rclone_conf: &Path,
+  root: &str,
+) -> Result<bool, String> {
+  let dir = rclone::join_remote(root, &format!(".agentsync-probe-{}", Uuid::new_v4().simple()));
+  let run = |cmd: &str, target: String| -> Result<std::process::Output, String> {
+    let mut args = rclone::make_common_args(cfg, rclone_conf);
+    args.extend([cmd.to_string(), format!("remote:{}", target)]);
+    let out = rclone::rclone_output(rclone_path, &args)?;
+    if out.status.success() {
+      Ok(out)
+    } else {
+      Err(format!("rclone {} 失败：{}", cmd, String::from_utf8_lossy(&out.stderr).trim()))
+    }
+  };
+
+  let result = (|| {
+    run("mkdir", dir.clone())?;
+    run("touch", format!("{}/CaseProbe", dir))?;
+    run("touch", format!("{}/caseprobe", dir))?;
+    let out = run("lsf", dir.clone())?;
+    let names = String::from_utf8_lossy(&out.stdout)
+      .lines()
+      .filter(|l| !l.trim().is_empty())
+      .count();
+    Ok(names < 2)
+  })();
+  let _ = run("purge", dir);
+  result
+}
+
+fn windows_illegal(name: &str) -> bool {
+  if name.chars().any(|c| c < ' ' || "<>:\"|?*\\".contains(c)) {
+    return true;
+  }
+  if name.ends_with('.') || name.ends_with(' ') {
+    return true;
+  }
+  let stem = name.split('.').next().unwrap_or("").trim_end().to_ascii_uppercase();
+  WINDOWS_RESERVED.contains(&stem.as_str())
+}
+
+#[derive(Default)]
+struct NameScan {
+  /// 只差大小写的（同一个目录里）
+  collisions: Vec<String>,
+  collision_count: usize,
+  /// Windows 上不能用的文件名
+  illegal: Vec<String>,
+  illegal_count: usize,
+  truncated: bool,
+}
+
+fn push_example(list: &mut Vec<String>, count: &mut usize, text: String) {
+  *count += 1;
+  if list.len() < MAX_EXAMPLES {
+    list.push(text);
+  }
+}
+
+/// 按排除规则把要同步的本机文件名看一遍。
+fn scan_names(cfg: &AgentSyncConfig) -> Result<NameScan, String> {
+  let matcher = ExcludeMatcher::new(&cfg.excludes)?;
+  let empty = ExcludeMatcher::empty();
+  let mut scan = NameScan::default();
+  let mut files = 0u64;
+
+  for item in rclone::plan_items(cfg, None)? {
+    if item.skip_message.is_some() || !item.local.exists() {
+      continue;
+    }
+    if item.is_file {
+      let name = item.local.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+      if windows_illegal(&name) {
+        push_example(&mut scan.illegal, &mut scan.illegal_count, format!("{}：{}", item.label, name));
+      }
+      continue;
+    }
+    let matcher = if item.use_excludes { &matcher } else { &empty };
+    let mut stack: Vec<PathBuf> = vec![item.local.clone()];
+    while let Some(dir) = stack.pop() {
+      let Ok(entries) = fs::read_dir(&dir) else {
+        continue;
+      };
+      let mut seen: BTreeMap<String, String> = BTreeMap::new();
+      for entry in entries.map_while(Result::ok) {
+        let path = entry.path();
+        let rel = path.strip_prefix(&item.local).unwrap_or(&path);
+        if matcher.is_excluded(rel) {
+          continue;
+        }
+        let rel_text = rel.to_string_lossy().replace('\\', "/");
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(other) = seen.insert(name.to_lowercase(), rel_text.clone()) {
+          push_example(
+            &mut scan.collisions,
+            &mut scan.collision_count,
+            format!("{}：{} 和 {}", item.label, other, rel_text),
+          );
+        }
+        if windows_illegal(&name) {
+          push_example(&mut scan.illegal, &mut scan.illegal_count, format!("{}：{}", item.label, rel_text));
+        }
+        // file_type 不跟符号链接
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+          stack.push(path);
+        } else {
+          files += 1;
+          if files >= MAX_FILES {
+            scan.truncated = true;
+            return Ok(scan);
+          }
+        }
+      }
+    }
+  }
+  Ok(scan)
+}
+
+/// 连接和写入都通过之后跑的检查。`cfg` 是展开过变量的配置。
+pub(crate) fn run(cfg: &AgentSyncConfig, rclone_path: &Path, rclone_conf: &Path) -> Vec<ConnectionCheck> {
+  let mut checks = Vec::new();
+  let projects_root = rclone::normalize_remote_path(&cfg.remote.projects_root);
+
+  // 1) 系统和用户目录
+  let started = Instant::now();
+  let env = remote_env(cfg);
+  checks.push(match &env {
+    Ok(e) => ConnectionCheck::new(
+      "remoteOs",
+      "目标端系统",
+      CheckStatus::Ok,
+      format!("{}，用户目录 {}", e.os, if e.home.is_empty() { "（没读到）" } else { &e.home }),
+    ),
+    Err(e) => ConnectionCheck::new(
+      "remoteOs",
+      "目标端系统",
+      CheckStatus::Warn,
+      format!("没法通过 ssh 在目标端跑命令，和系统有关的检查跳过（git bundle、目标端命令也用不了）：{}", e),
+    ),
+  }
+  .timed(started));
+  let env = env.ok();
+
+  // 2) 剩余空间 vs 这次最多要同步多少
+  // 查的是 projects_root 所在的盘，只算放到它下面的项目；.codex / .agents 在目标端用户目录，不一定是同一块盘。
+  // 估算的是本机全量，目标端已有的不会再传，所以放不下也只是提醒，不拦着
+  let started = Instant::now();
+  let free = remote_free_bytes(cfg, rclone_path, rclone_conf, &projects_root, env.as_ref());
+  let project_ids: Vec<String> = cfg.projects.iter().filter(|p| p.enabled).map(|p| p.id.clone()).collect();
+  let total = estimate::estimate(cfg, Some(&project_ids)).map(|e| e.total_bytes);
+  checks.push(match (free, total) {
+    (Err(e), _) => ConnectionCheck::new("diskSpace", "剩余空间", CheckStatus::Skipped, format!("查不到剩余空间：{}", e)),
+    (Ok(free), Err(e)) => ConnectionCheck::new(
+      "diskSpace",
+      "剩余空间",
+      CheckStatus::Warn,
+      format!("目标端剩余 {}；估算这次要同步多少失败了：{}", human_bytes(free), e),
+    ),
+    (Ok(free), Ok(total)) if free < total => ConnectionCheck::new(
+      "diskSpace",
+      "剩余空间",
+      CheckStatus::Warn,
+      format!(
+        "目标端剩余 {}，项目全量是 {}，目标端没有的部分多的话会放不下",
+        human_bytes(free),
+        human_bytes(total)
+      ),
+    ),
+    (Ok(free), Ok(total)) if free < total + total / 5 => ConnectionCheck::new(
+      "diskSpace",
+      "剩余空间",
+      CheckStatus::Warn,
+      format!(
+        "目标端剩余 {}，这次最多要同步 {}，余量不多（备份的旧版本也要占空间）",
+        human_bytes(free),
+        human_bytes(total)
+      ),
+    ),
+    (Ok(free), Ok(total)) => ConnectionCheck::new(
+      "diskSpace",
+      "剩余空间",
+      CheckStatus::Ok,
+      format!("目标端剩余 {}，这次最多要同步 {}", human_bytes(free), human_bytes(total)),
+    ),
+  }
+  .timed(started));
+
+  // 3) 大小写；4) Windows 文件名。本机文件只在需要时看一遍，看的耗时算进用到它的那一步
+  let started = Instant::now();
+  let insensitive = remote_case_insensitive(cfg, rclone_path, rclone_conf, &projects_root);
+  let probe_elapsed = started.elapsed();
+  let windows = env.as_ref().map(|e| e.windows);
+  let started = Instant::now();
+  let scan = if matches!(insensitive, Ok(true)) || windows == Some(true) {
+    Some(scan_names(cfg))
+  } else {
+    None
+  };
+  let scan_elapsed = started.elapsed();
+  let case_elapsed = probe_elapsed + if matches!(insensitive, Ok(true)) { scan_elapsed } else { Duration::ZERO };
+  let names_elapsed = if windows == Some(true) { scan_elapsed } else { Duration::ZERO };
+  let truncated_note = |scan: &NameScan| {
+    if scan.truncated {
+      format!("（文件超过 {} 个，只看了一部分）", MAX_FILES)
+    } else {
+      String::new()
+    }
+  };
+
+  checks.push(match (&insensitive, &scan) {
+    (Err(e), _) => ConnectionCheck::new("caseSensitivity", "大小写", CheckStatus::Skipped, format!("没测出来：{}", e)),
+    (Ok(false), _) => ConnectionCheck::new("caseSensitivity", "大小写", CheckStatus::Ok, "目标端区分大小写"),
+    (Ok(true), Some(Err(e))) => ConnectionCheck::new(
+      "caseSensitivity",
+      "大小写",
+      CheckStatus::Warn,
+      format!("目标端不区分大小写；本机文件没看成：{}", e),
+    ),
+    (Ok(true), Some(Ok(s))) if s.collision_count > 0 => {
+      let mut c = ConnectionCheck::new(
+        "caseSensitivity",
+        "大小写",
+        CheckStatus::Warn,
+        format!(
+          "目标端不区分大小写，本机有 {} 组只差大小写的文件，同步过去会互相覆盖{}",
+          s.collision_count,
+          truncated_note(s)
+        ),
+      );
+      c.examples = s.collisions.clone();
+      c
+    }
+    (Ok(true), Some(Ok(s))) => ConnectionCheck::new(
+      "caseSensitivity",
+      "大小写",
+      CheckStatus::Ok,
+      format!("目标端不区分大小写；本机没有只差大小写的文件{}", truncated_note(s)),
+    ),
+    (Ok(true), None) => ConnectionCheck::new("caseSensitivity", "大小写", CheckStatus::Ok, "目标端不区分大小写"),
+  }
+  .took(case_elapsed));
+
+  checks.push(match (windows, &scan) {
+    (None, _) => ConnectionCheck::new("fileNames", "文件名", CheckStatus::Skipped, "不知道目标端是什么系统，没查"),
+    (Some(false), _) => ConnectionCheck::new("fileNames", "文件名", CheckStatus::Skipped, "目标端不是 Windows，不用查"),
+    (Some(true), Some(Err(e))) => {
+      ConnectionCheck::new("fileNames", "文件名", CheckStatus::Warn, format!("本机文件没看成：{}", e))
+    }
+    (Some(true), Some(Ok(s))) if s.illegal_count > 0 => {
+      let mut c = ConnectionCheck::new(
+        "fileNames",
+        "文件名",
+        CheckStatus::Warn,
+        format!(
+          "目标端是 Windows，本机有 {} 个文件名在 Windows 上不能用（含 <>:\"|?*\\、CON / NUL 之类、结尾是点或空格），这些文件会同步失败{}",
+          s.illegal_count,
+          truncated_note(s)
+        ),
+      );
+      c.examples = s.illegal.clone();
+      c
+    }
+    (Some(true), Some(Ok(s))) => ConnectionCheck::new(
+      "fileNames",
+      "文件名",
+      CheckStatus::Ok,
+      format!("目标端是 Windows，本机文件名都能用{}", truncated_note(s)),
+    ),
+    (Some(true), None) => ConnectionCheck::new("fileNames", "文件名", CheckStatus::Skipped, "没查"),
+  }
+  .took(names_elapsed));
+
+  checks
+}
@@ -1,19 +1,39 @@
 //! rclone 相关：生成临时配置、测试连接、执行同步命令。
 
-use crate::config::{AgentSyncConfig, ProjectItem};
-use crate::runs::{self, SyncItemResult, SyncRunSummary};
+use crate::config::{AgentSyncConfig, DriftAction, GitMode, PerformanceConfig, ProjectItem};
+use crate::diagnose::{self, CheckStatus, ConnectionCheck};
+use crate::drift;
+use crate::git;
+use crate::hooks;
+use crate::manifest;
+use crate::preflight;
+use crate::journal::{self, RunJournal};
+use crate::known_hosts::{self, HostKeyInfo};
+use crate::logs;
+use crate::validate::{self, Severity};
+use crate::vars;
+use crate::webhooks;
+use crate::runs::{self, HookResult, RunStatus, SyncItemResult, SyncRunSummary};
+use serde::{Deserialize, Serialize};
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::io::{BufRead, BufReader};
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ConnectionTestResult {
   pub ok: bool,
   pub message: String,
+  /// 每一步诊断和同步前检查的结果（按固定顺序，没做到的是 `skipped`）
+  pub checks: Vec<ConnectionCheck>,
+  /// 目标电脑的身份指纹（没拿到时为空）；`status` 不是 `trusted` 时要用户确认
+  pub host_key: Option<HostKeyInfo>,
 }
 
 pub trait SyncProgress {
@@ -24,6 +44,53 @@ pub trait SyncProgress {
 
 impl SyncProgress for () {}
 
+/// 非项目类同步项的固定 id（项目用 `ProjectItem.id`）。
+pub const CODEX_SESSIONS_ID: &str = "codex:sessions";
+pub const CODEX_CONFIG_ID: &str = "codex:config";
+pub const AGENTS_ID: &str = "agents";
+
+/// 一个同步项在本机对应的路径（监听模式用来判断“哪个项变了”）。
+pub struct LocalItemRoot {
+  pub item_id: String,
+  pub label: String,
+  pub path: PathBuf,
+  pub is_file: bool,
+  pub use_excludes: bool,
+}
+
+/// 一次同步的附加选项：跑哪些项、由谁触发（写进历史记录）。
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+  /// None 表示全部同步项；否则只跑 id 在列表里的项。
+  pub only: Option<Vec<String>>,
+  pub trigger: Option<String>,
+  /// 这次运行遇到目标端改动时怎么处理；None 用配置里的 `flags.onDrift`
+  pub on_drift: Option<DriftAction>,
+}
+
+/// 取消请求（见 `request_cancel`）：正在跑的 rclone / 命令会被结束，后面的项不再跑。
+static CANCEL_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// 请求取消当前进程里正在跑的同步（托盘 / 界面 / 命令行的“取消”最终都走这里）。
+pub fn request_cancel() {
+  CANCEL_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// 开始新的一次同步前清掉上次留下的取消请求。要在标记“开始运行”的同时调（见 `sync_manager::start_run`）：
+/// 放到后台线程里清的话，线程还没跑起来时按的“取消”会被清掉。
+pub fn reset_cancel() {
+  CANCEL_REQUESTED.store(false, Ordering::SeqCst);
+}
+
+fn cancel_requested() -> bool {
+  CANCEL_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// `only` 为 None 表示全部同步项；否则只跑 id 在列表里的项。
+fn is_selected(only: Option<&[String]>, item_id: &str) -> bool {
+  only.map(|ids| ids.iter().any(|id| id == item_id)).unwrap_or(true)
+}
+
 fn now_ms() -> u64 {
   SystemTime::now()
     .duration_since(UNIX_EPOCH)
@@ -31,7 +98,7 @@ fn now_ms() -> u64 {
     .as_millis() as u64
 }
 
-fn normalize_remote_path(input: &str) -> String {
+pub(crate) fn normalize_remote_path(input: &str) -> String {
   let mut s = input.trim().replace('\\', "/");
   if s == "~" {
     return "".to_string();
@@ -42,7 +109,7 @@ fn normalize_remote_path(input: &str) -> String {
   s
 }
 
-fn join_remote(a: &str, b: &str) -> String {
+pub(crate) fn join_remote(a: &str, b: &str) -> String {
   let a = normalize_remote_path(a);
   let b = normalize_remote_path(b);
   if a.is_empty() {
@@ -54,13 +121,6 @@ fn join_remote(a: &str, b: &str) -> String {
   format!("{}/{}", a.trim_end_matches('/'), b.trim_start_matches('/'))
 }
 
-fn ensure_non_empty(label: &str, value: &str) -> Result<(), String> {
-  if value.trim().is_empty() {
-    return Err(format!("{}不能为空", label));
-  }
-  Ok(())
-}
-
 pub fn resolve_rclone(cfg: &AgentSyncConfig) -> Result<PathBuf, String> {
   if let Some(p) = cfg.rclone_path.as_ref() {
     if !p.trim().is_empty() && Path::new(p).is_file() {
@@ -80,71 +140,115 @@ pub fn resolve_rclone(cfg: &AgentSyncConfig) -> Result<PathBuf, String> {
   Err("没找到 rclone：请先安装 rclone 并加入 PATH，或在连接设置里选择 rclone 可执行文件。".to_string())
 }
 
-fn validate_basic(cfg: &AgentSyncConfig) -> Result<(), String> {
-  ensure_non_empty("Host", &cfg.connection.host)?;
-  ensure_non_empty("User", &cfg.connection.user)?;
-  ensure_non_empty("SSH 私钥路径", &cfg.connection.key_path)?;
-  ensure_non_empty("目标端项目根目录", &cfg.remote.projects_root)?;
-  ensure_non_empty("备份根目录", &cfg.remote.backup_root)?;
+/// 连目标端之前的检查：只看连接、目标端目录这些字段（项目配置有问题照样能测连接、列备份）。
+pub(crate) fn validate_basic(cfg: &AgentSyncConfig) -> Result<(), String> {
+  errors_only(validate::validate_connection(cfg))
+}
+
+/// 同步前的检查：整份配置里 `error` 级别的问题（完整列表见 `validate::validate`），有就全部列出来。
+pub fn validate_for_run(cfg: &AgentSyncConfig) -> Result<(), String> {
+  errors_only(validate::validate(cfg))
+}
 
-  if !Path::new(&cfg.connection.key_path).is_file() {
-    return Err(format!("SSH 私钥文件不存在：{}", cfg.connection.key_path));
+fn errors_only(report: validate::ValidationReport) -> Result<(), String> {
+  let errors: Vec<String> = report
+    .problems
+    .into_iter()
+    .filter(|p| p.severity == Severity::Error)
+    .map(|p| format!("{}（{}）", p.message, p.path))
+    .collect();
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(format!("配置有问题：{}", errors.join("；")))
   }
-  Ok(())
 }
 
-pub fn validate_for_run(cfg: &AgentSyncConfig) -> Result<(), String> {
-  validate_basic(cfg)
+/// 临时 rclone 配置（里面有连接信息）：离开作用域就删掉，中途 `?` 出错返回也不会留在磁盘上。
+pub(crate) struct TempRcloneConf(PathBuf);
+
+impl std::ops::Deref for TempRcloneConf {
+  type Target = Path;
+
+  fn deref(&self) -> &Path {
+    &self.0
+  }
+}
+
+impl Drop for TempRcloneConf {
+  fn drop(&mut self) {
+    let _ = fs::remove_file(&self.0);
+  }
 }
 
-fn write_temp_rclone_config(cfg: &AgentSyncConfig) -> Result<PathBuf, String> {
+/// 写临时 rclone 配置；目标电脑的指纹还没确认过就报错，不连（见 `known_hosts`）。
+pub(crate) fn write_temp_rclone_config(cfg: &AgentSyncConfig) -> Result<TempRcloneConf, String> {
+  known_hosts::require_pinned(cfg)?;
+  write_rclone_config_file(cfg)
+}
+
+/// 只写文件，不管指纹记没记下（测试连接用：让用户先看指纹再确认）。
+fn write_rclone_config_file(cfg: &AgentSyncConfig) -> Result<TempRcloneConf, String> {
   let id = Uuid::new_v4().simple().to_string();
   let path = env::temp_dir().join(format!("agentsync-rclone-{}.conf", id));
 
   let key_file = cfg.connection.key_path.replace('\\', "/");
+  let known_hosts_file = known_hosts::known_hosts_file()?.display().to_string().replace('\\', "/");
   let content = format!(
     "[remote]\n\
 type = sftp\n\
 host = {host}\n\
 user = {user}\n\
 port = {port}\n\
-key_file = {key_file}\n",
+key_file = {key_file}\n\
+known_hosts_file = {known_hosts_file}\n",
     host = cfg.connection.host.trim(),
     user = cfg.connection.user.trim(),
     port = cfg.connection.port,
-    key_file = key_file
+    key_file = key_file,
+    known_hosts_file = known_hosts_file
   );
 
   fs::write(&path, content).map_err(|e| format!("写入临时 rclone 配置失败：{}（{}）", path.display(), e))?;
-  Ok(path)
+  Ok(TempRcloneConf(path))
 }
 
-fn rclone_output(rclone: &Path, args: &[String]) -> Result<std::process::Output, String> {
+pub(crate) fn rclone_output(rclone: &Path, args: &[String]) -> Result<std::process::Output, String> {
   Command::new(rclone)
     .args(args)
     .output()
     .map_err(|e| format!("执行 rclone 失败：{}（{}）", rclone.display(), e))
 }
 
-fn rclone_stream<F>(rclone: &Path, args: &[String], mut on_line: F) -> Result<i32, String>
+fn rclone_stream<F>(rclone: &Path, args: &[String], on_line: F) -> Result<i32, String>
 where
   F: FnMut(&str),
 {
-  let mut child = Command::new(rclone)
-    .args(args)
+  let mut cmd = Command::new(rclone);
+  cmd.args(args);
+  stream_command(cmd, &format!("rclone：{}", rclone.display()), on_line)
+}
+
+/// 跑一个外部命令，stdout / stderr 按行回调，返回退出码（rclone 和同步前后命令共用）。
+pub(crate) fn stream_command<F>(mut cmd: Command, name: &str, mut on_line: F) -> Result<i32, String>
+where
+  F: FnMut(&str),
+{
+  let mut child = cmd
+    .stdin(Stdio::null())
     .stdout(Stdio::piped())
     .stderr(Stdio::piped())
     .spawn()
-    .map_err(|e| format!("执行 rclone 失败：{}（{}）", rclone.display(), e))?;
+    .map_err(|e| format!("执行 {} 失败（{}）", name, e))?;
 
   let stdout = child
     .stdout
     .take()
-    .ok_or_else(|| "无法获取 rclone stdout".to_string())?;
+    .ok_or_else(|| format!("无法获取 {} stdout", name))?;
   let stderr = child
     .stderr
     .take()
-    .ok_or_else(|| "无法获取 rclone stderr".to_string())?;
+    .ok_or_else(|| format!("无法获取 {} stderr", name))?;
 
   let (tx, rx) = mpsc::channel::<String>();
   let tx1 = tx.clone();
@@ -163,22 +267,123 @@ where
   });
   drop(tx);
 
-  for line in rx {
-    on_line(&line);
+  // 每 200ms 看一眼有没有人要取消；取消时结束子进程
+  let mut killed = false;
+  loop {
+    match rx.recv_timeout(Duration::from_millis(200)) {
+      Ok(line) => on_line(&line),
+      Err(mpsc::RecvTimeoutError::Timeout) => {
+        if cancel_requested() {
+          let _ = child.kill();
+          killed = true;
+          break;
+        }
+      }
+      Err(mpsc::RecvTimeoutError::Disconnected) => break,
+    }
   }
 
   let status = child
     .wait()
-    .map_err(|e| format!("等待 rclone 进程结束失败（{}）", e))?;
+    .map_err(|e| format!("等待 {} 进程结束失败（{}）", name, e))?;
 
-  let _ = h1.join();
-  let _ = h2.join();
+  // 被取消时不等读输出的线程：shell 命令的子进程可能还拿着管道，让它们自己结束
+  if !killed {
+    let _ = h1.join();
+    let _ = h2.join();
+  }
 
   Ok(status.code().unwrap_or(1))
 }
 
-fn make_common_args(rclone_conf: &Path) -> Vec<String> {
-  vec![
+fn performance_args(p: &PerformanceConfig) -> Vec<String> {
+  let mut args = Vec::new();
+  let bwlimit = p.bwlimit.trim();
+  if !bwlimit.is_empty() {
+    args.push("--bwlimit".to_string());
+    args.push(bwlimit.to_string());
+  }
+  let counts = [
+    ("--transfers", p.transfers),
+    ("--checkers", p.checkers),
+    ("--sftp-concurrency", p.sftp_concurrency),
+  ];
+  for (flag, value) in counts {
+    if let Some(n) = value {
+      args.push(flag.to_string());
+      args.push(n.to_string());
+    }
+  }
+  args
+}
+
+/// 限速值：`off`，或数字加可选单位（B/K/M/G/T/P），上下行分开写成 `10M:1M`。
+fn is_valid_rate(rate: &str) -> bool {
+  if rate == "off" {
+    return true;
+  }
+  rate.split(':').all(|part| {
+    let num = part.trim_end_matches(|c: char| "BbKkMmGgTtPp".contains(c));
+    !num.is_empty() && num.len() + 1 >= part.len() && num.parse::<f64>().is_ok()
+  })
+}
+
+/// 时间段的起点：`HH:MM`，或 `Mon-HH:MM`（星期用英文缩写）。
+fn is_valid_bwlimit_time(at: &str) -> bool {
+  let time = match at.split_once('-') {
+    Some((day, time)) => {
+      if !["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"].contains(&day) {
+        return false;
+      }
+      time
+    }
+    None => at,
+  };
+  let Some((h, m)) = time.split_once(':') else {
+    return false;
+  };
+  matches!((h.parse::<u32>(), m.parse::<u32>()), (Ok(h), Ok(m)) if h < 24 && m < 60)
+}
+
+pub(crate) fn validate_performance(p: &PerformanceConfig) -> Result<(), String> {
+  let bwlimit = p.bwlimit.trim();
+  let entries: Vec<&str> = bwlimit.split_whitespace().collect();
+  match entries.as_slice() {
+    [] => {}
+    [rate] if !rate.contains(',') => {
+      if !is_valid_rate(rate) {
+        return Err(format!("限速格式无效：{}（例如 10M、off、08:00,2M 18:00,off）", bwlimit));
+      }
+    }
+    slots => {
+      for slot in slots {
+        let valid = slot
+          .split_once(',')
+          .is_some_and(|(at, rate)| is_valid_bwlimit_time(at) && is_valid_rate(rate));
+        if !valid {
+          return Err(format!("限速时间段格式无效：{}（应为 HH:MM,速率 或 Mon-HH:MM,速率）", slot));
+        }
+      }
+    }
+  }
+
+  let counts = [
+    ("同时传输文件数（transfers）", p.transfers),
+    ("同时比较文件数（checkers）", p.checkers),
+    ("SFTP 并发请求数（sftp concurrency）", p.sftp_concurrency),
+  ];
+  for (label, value) in counts {
+    if let Some(n) = value {
+      if n == 0 || n > 1024 {
+        return Err(format!("{}应在 1 到 1024 之间：{}", label, n));
+      }
+    }
+  }
+  Ok(())
+}
+
+pub(crate) fn make_common_args(cfg: &AgentSyncConfig, rclone_conf: &Path) -> Vec<String> {
+  let mut args = vec![
     "--config".to_string(),
     rclone_conf.display().to_string(),
     "--log-level".to_string(),
@@ -186,62 +391,38 @@ fn make_common_args(rclone_conf: &Path) -> Vec<String> {
     "--stats".to_string(),
     "1s".to_string(),
     "--stats-one-line".to_string(),
-  ]
+  ];
+  args.extend(performance_args(&cfg.performance));
+  args
 }
 
 pub fn test_connection(cfg: &AgentSyncConfig) -> Result<ConnectionTestResult, String> {
+  let cfg = &vars::expanded(cfg)?;
   validate_basic(cfg)?;
   let rclone = resolve_rclone(cfg)?;
-  let rclone_conf = write_temp_rclone_config(cfg)?;
-
-  let mut messages: Vec<String> = Vec::new();
-  let result = (|| -> Result<(), String> {
-    // 1) 仅测试能否连上（lsd 会触发连接）
-    let mut args = make_common_args(&rclone_conf);
-    args.extend(["lsd".to_string(), "remote:".to_string()]);
-    let out = rclone_output(&rclone, &args)?;
-    if !out.status.success() {
-      let err = String::from_utf8_lossy(&out.stderr).to_string();
-      return Err(format!("连接失败：{}", err.trim()));
-    }
-    messages.push("连接成功：能连上目标电脑。".to_string());
-
-    // 2) 测试目标目录可写（mkdir 会触发写入权限检查）
-    let projects_root = normalize_remote_path(&cfg.remote.projects_root);
-    let backup_root = normalize_remote_path(&cfg.remote.backup_root);
-
-    let mut mk1 = make_common_args(&rclone_conf);
-    mk1.extend(["mkdir".to_string(), format!("remote:{}", projects_root)]);
-    let out1 = rclone_output(&rclone, &mk1)?;
-    if !out1.status.success() {
-      let err = String::from_utf8_lossy(&out1.stderr).to_string();
-      return Err(format!("目标端目录不可写（projects root）：{}", err.trim()));
-    }
-
-    let mut mk2 = make_common_args(&rclone_conf);
-    mk2.extend(["mkdir".to_string(), format!("remote:{}", backup_root)]);
-    let out2 = rclone_output(&rclone, &mk2)?;
-    if !out2.status.success() {
-      let err = String::from_utf8_lossy(&out2.stderr).to_string();
-      return Err(format!("目标端目录不可写（backup root）：{}", err.trim()));
-    }
-
-    messages.push("写入权限正常：目标端目录可创建/可写。".to_string());
-    Ok(())
-  })();
+  // 指纹没确认过时在 hostKey 那一步停下，让用户先看指纹
+  let rclone_conf = write_rclone_config_file(cfg)?;
 
-  let _ = fs::remove_file(&rclone_conf);
-
-  match result {
-    Ok(()) => Ok(ConnectionTestResult {
-      ok: true,
-      message: messages.join("\n"),
-    }),
-    Err(e) => Ok(ConnectionTestResult {
-      ok: false,
-      message: e,
-    }),
+  // 先一步步查网络、指纹、登录、SFTP、写入；都通过了再做同步前检查
+  let (mut checks, host_key) = diagnose::run(cfg, &rclone, &rclone_conf);
+  if checks.iter().all(|c| c.status != CheckStatus::Fail) {
+    checks.extend(preflight::run(cfg, &rclone, &rclone_conf));
   }
+  drop(rclone_conf);
+
+  let checks = diagnose::fill_skipped(checks);
+  let ok = checks.iter().all(|c| c.status != CheckStatus::Fail);
+  let message = checks
+    .iter()
+    .map(|c| format!("{}：{}", c.label, c.message))
+    .collect::<Vec<_>>()
+    .join("\n");
+  Ok(ConnectionTestResult {
+    ok,
+    message,
+    checks,
+    host_key,
+  })
 }
 
 fn local_home_dir() -> Result<PathBuf, String> {
@@ -258,61 +439,182 @@ fn agents_dir() -> Result<PathBuf, String> {
   Ok(local_home_dir()?.join(".agents"))
 }
 
-pub fn estimate_total_items(cfg: &AgentSyncConfig) -> Result<u32, String> {
-  let mut total: u32 = cfg.projects.iter().filter(|p| p.enabled).count() as u32;
+/// 一次同步里的一个同步项（按执行顺序排好；项目在前，然后 Codex、.agents）。
+pub(crate) struct PlannedItem {
+  pub id: String,
+  pub label: String,
+  pub local: PathBuf,
+  pub remote_dest: String,
+  /// 相对本次运行备份目录（backup_root/<run_id>）的子路径
+  pub backup_sub: String,
+  pub is_file: bool,
+  pub use_excludes: bool,
+  /// 项目的 `.git` 怎么同步（其他同步项都是 `Off`）
+  pub git: GitMode,
+  /// 本机找不到对应目录 / 文件时直接跳过，这里是跳过的说明
+  pub skip_message: Option<String>,
+}
+
+pub(crate) fn plan_items(cfg: &AgentSyncConfig, only: Option<&[String]>) -> Result<Vec<PlannedItem>, String> {
+  let projects_root = normalize_remote_path(&cfg.remote.projects_root);
+  let mut out = Vec::new();
+
+  // 1) 项目（启用的）
+  for p in cfg
+    .projects
+    .iter()
+    .filter(|p| p.enabled && is_selected(only, &p.id))
+  {
+    out.push(PlannedItem {
+      id: p.id.clone(),
+      label: project_label(p),
+      local: PathBuf::from(&p.local_path),
+      remote_dest: join_remote(&projects_root, &p.remote_dir_name),
+      backup_sub: join_remote("projects", &p.remote_dir_name),
+      is_file: false,
+      use_excludes: true,
+      git: p.git,
+      skip_message: None,
+    });
+  }
 
+  // 2) Codex：只同步 config.toml + sessions/
   if cfg.flags.sync_codex {
     let (local_config, local_sessions) = codex_paths()?;
-    if local_sessions.is_dir() {
-      total = total.saturating_add(1);
+    // 只跑部分同步项时（例如监听模式），没选中的项不出现在结果里
+    if is_selected(only, CODEX_SESSIONS_ID) {
+      out.push(PlannedItem {
+        id: CODEX_SESSIONS_ID.to_string(),
+        label: "Codex: sessions".to_string(),
+        skip_message: (!local_sessions.is_dir())
+          .then(|| "本机未找到 .codex/sessions，已跳过".to_string()),
+        local: local_sessions,
+        remote_dest: ".codex/sessions".to_string(),
+        backup_sub: "codex/sessions".to_string(),
+        is_file: false,
+        use_excludes: false,
+        git: GitMode::Off,
+      });
     }
-    if local_config.is_file() {
-      total = total.saturating_add(1);
+    if is_selected(only, CODEX_CONFIG_ID) {
+      out.push(PlannedItem {
+        id: CODEX_CONFIG_ID.to_string(),
+        label: "Codex: config.toml".to_string(),
+        skip_message: (!local_config.is_file())
+          .then(|| "本机未找到 .codex/config.toml，已跳过".to_string()),
+        local: local_config,
+        remote_dest: ".codex/config.toml".to_string(),
+        backup_sub: "codex/config".to_string(),
+        is_file: true,
+        use_excludes: false,
+        git: GitMode::Off,
+      });
     }
   }
 
-  if cfg.flags.sync_agents {
+  // 3) .agents
+  if cfg.flags.sync_agents && is_selected(only, AGENTS_ID) {
     let local_agents = agents_dir()?;
-    if local_agents.is_dir() {
-      total = total.saturating_add(1);
-    }
+    out.push(PlannedItem {
+      id: AGENTS_ID.to_string(),
+      label: ".agents".to_string(),
+      skip_message: (!local_agents.is_dir()).then(|| "本机未找到 ~/.agents，已跳过".to_string()),
+      local: local_agents,
+      remote_dest: ".agents".to_string(),
+      backup_sub: "agents".to_string(),
+      is_file: false,
+      use_excludes: false,
+      git: GitMode::Off,
+    });
   }
 
-  Ok(total)
+  Ok(out)
+}
+
+pub fn estimate_total_items(cfg: &AgentSyncConfig, only: Option<&[String]>) -> Result<u32, String> {
+  let cfg = &vars::expanded(cfg)?;
+  let plan = plan_items(cfg, only)?;
+  Ok(plan.iter().filter(|i| i.skip_message.is_none()).count() as u32)
+}
+
+pub fn local_item_roots(cfg: &AgentSyncConfig) -> Result<Vec<LocalItemRoot>, String> {
+  let cfg = &vars::expanded(cfg)?;
+  Ok(
+    plan_items(cfg, None)?
+      .into_iter()
+      .map(|i| LocalItemRoot {
+        item_id: i.id,
+        label: i.label,
+        path: i.local,
+        is_file: i.is_file,
+        use_excludes: i.use_excludes,
+      })
+      .collect(),
+  )
+}
+
+/// 这一项要带的 `--exclude`：配置里的排除规则（用的话），git 仓库按 bundle / fetch 同步时再强制排除 `.git`
+/// （`.git` 单独打包传）。同步、检查、核对用同一份，不然没传的 `.git` 会被当成缺失 / 改动。
+pub(crate) fn exclude_args(cfg: &AgentSyncConfig, item: &PlannedItem) -> Vec<String> {
+  let mut patterns: Vec<&str> = Vec::new();
+  if item.use_excludes {
+    patterns.extend(cfg.excludes.iter().map(|e| e.trim()).filter(|e| !e.is_empty()));
+  }
+  if item.git != GitMode::Off {
+    patterns.extend(["/.git", "/.git/**"]);
+  }
+  patterns
+    .into_iter()
+    .flat_map(|p| ["--exclude".to_string(), p.to_string()])
+    .collect()
 }
 
 fn build_project_item_args(
   cfg: &AgentSyncConfig,
   rclone_conf: &Path,
   rclone_cmd: &str,
-  local_path: &str,
-  remote_dest: &str,
+  item: &PlannedItem,
   backup_dir: &str,
-  use_excludes: bool,
 ) -> Vec<String> {
-  let mut args = make_common_args(rclone_conf);
+  let mut args = make_common_args(cfg, rclone_conf);
   args.push(rclone_cmd.to_string());
-  args.push(local_path.to_string());
-  args.push(format!("remote:{}", remote_dest));
+  args.push(item.local.display().to_string());
+  args.push(format!("remote:{}", item.remote_dest));
   args.push("--backup-dir".to_string());
   args.push(format!("remote:{}", backup_dir));
-
-  if use_excludes {
-    for ex in &cfg.excludes {
-      if ex.trim().is_empty() {
-        continue;
-      }
-      args.push("--exclude".to_string());
-      args.push(ex.trim().to_string());
-    }
+  if cfg.flags.checksum {
+    args.push("--checksum".to_string());
   }
-
+  args.extend(exclude_args(cfg, item));
   args
 }
 
-fn run_one_stream(
+/// 从 rclone 的进度行里取出“已传输字节数”。
+/// `--stats-one-line` 的格式是 `... INFO  :    1.234 MiB / 5.678 MiB, 21%, 1.2 MiB/s, ETA 3s`，
+/// 取 ` / ` 左边的数字和单位。
+fn parse_stats_bytes(line: &str) -> Option<u64> {
+  let (left, right) = line.split_once(" / ")?;
+  if !right.contains("ETA") {
+    return None;
+  }
+  let mut parts = left.split_whitespace().rev();
+  let unit = parts.next()?;
+  let num: f64 = parts.next()?.parse().ok()?;
+  let mult: f64 = match unit {
+    "B" | "Byte" | "Bytes" => 1.0,
+    "KiB" => 1024.0,
+    "MiB" => 1024.0 * 1024.0,
+    "GiB" => 1024.0 * 1024.0 * 1024.0,
+    "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+    _ => return None,
+  };
+  Some((num * mult) as u64)
+}
+
+pub(crate) fn run_one_stream(
   rclone: &Path,
   log: &mut fs::File,
+  item_id: &str,
   label: &str,
   args: &[String],
   progress: &mut dyn SyncProgress,
@@ -322,9 +624,15 @@ fn run_one_stream(
   let _ = runs::append_log_line(log, &format!("cmd: rclone {}", args.join(" ")));
 
   let mut last_line: Option<String> = None;
+  let mut bytes = 0;
+  let mut host_key_mismatch = false;
   let exit_code = rclone_stream(rclone, args, |line| {
     let trimmed = line.trim_end();
     if !trimmed.is_empty() {
+      if let Some(b) = parse_stats_bytes(trimmed) {
+        bytes = b;
+      }
+      host_key_mismatch |= known_hosts::is_mismatch(trimmed);
       last_line = Some(trimmed.to_string());
       let _ = runs::append_log_line(log, trimmed);
       progress.on_line(trimmed);
@@ -333,11 +641,27 @@ fn run_one_stream(
 
   match exit_code {
     Ok(code) if code == 0 => SyncItemResult {
+      item_id: item_id.to_string(),
       label: label.to_string(),
       ok: true,
       message: "完成".to_string(),
+      bytes,
+      hooks: vec![],
+      drift: vec![],
+      git: None,
+    },
+    Ok(_) if host_key_mismatch => SyncItemResult {
+      item_id: item_id.to_string(),
+      label: label.to_string(),
+      ok: false,
+      message: known_hosts::MISMATCH_MESSAGE.to_string(),
+      bytes,
+      hooks: vec![],
+      drift: vec![],
+      git: None,
     },
     Ok(code) => SyncItemResult {
+      item_id: item_id.to_string(),
       label: label.to_string(),
       ok: false,
       message: format!(
@@ -348,11 +672,20 @@ fn run_one_stream(
           .map(|s| format!("：{}", s))
           .unwrap_or_default()
       ),
+      bytes,
+      hooks: vec![],
+      drift: vec![],
+      git: None,
     },
     Err(e) => SyncItemResult {
+      item_id: item_id.to_string(),
       label: label.to_string(),
       ok: false,
       message: e,
+      bytes: 0,
+      hooks: vec![],
+      drift: vec![],
+      git: None,
     },
   }
 }
@@ -361,17 +694,15 @@ fn project_label(p: &ProjectItem) -> String {
   format!("项目: {}", p.name)
 }
 
-pub fn run_sync(cfg: &AgentSyncConfig) -> Result<SyncRunSummary, String> {
-  let run_id = Uuid::new_v4().simple().to_string();
-  run_sync_with_id(cfg, run_id, &mut ())
-}
-
 pub fn run_sync_with_id(
   cfg: &AgentSyncConfig,
   run_id: String,
+  opts: &RunOptions,
   progress: &mut dyn SyncProgress,
 ) -> Result<SyncRunSummary, String> {
-  validate_basic(cfg)?;
+  let only = opts.only.as_deref();
+  let cfg = &vars::expanded(cfg)?;
+  validate_for_run(cfg)?;
   let rclone = resolve_rclone(cfg)?;
   let rclone_conf = write_temp_rclone_config(cfg)?;
   let started_at_ms = now_ms();
@@ -383,116 +714,242 @@ pub fn run_sync_with_id(
     .map_err(|e| format!("创建日志文件失败：{}（{}）", log_path.display(), e))?;
   runs::write_log_header(&log, &run_id)?;
 
-  let projects_root = normalize_remote_path(&cfg.remote.projects_root);
   let backup_root = normalize_remote_path(&cfg.remote.backup_root);
   let run_backup_root = join_remote(&backup_root, &run_id);
+  let plan = plan_items(cfg, only)?;
+
+  // 每跑完一项就把进度落盘；崩溃 / 重启后靠它补记历史、续跑没完成的项
+  let mut journal = RunJournal {
+    run_id: run_id.clone(),
+    pid: std::process::id(),
+    process_identity: journal::self_identity(),
+    started_at_ms,
+    updated_at_ms: started_at_ms,
+    trigger: opts.trigger.clone(),
+    planned_item_ids: plan.iter().map(|i| i.id.clone()).collect(),
+    current_item_id: None,
+    done: vec![],
+    rclone_conf: rclone_conf.display().to_string(),
+    log_path: log_path.display().to_string(),
+    backup_root: run_backup_root.clone(),
+    target: Some(cfg.connection.target_text()),
+    recovered: false,
+  };
+  journal::write(&mut journal)?;
+
+  // 目标端改动检查：以每一项上次成功同步的结束时间为准
+  let drift_action = opts.on_drift.unwrap_or(cfg.flags.on_drift);
+  let last_ok = if drift_action == DriftAction::Off {
+    Default::default()
+  } else {
+    runs::last_ok_item_times().unwrap_or_default()
+  };
+  let remote = drift::Remote {
+    cfg,
+    rclone: &rclone,
+    rclone_conf: &rclone_conf,
+  };
 
   let mut items: Vec<SyncItemResult> = Vec::new();
+  let mut run_hooks: Vec<HookResult> = Vec::new();
+  let run_env = [("AGENTSYNC_RUN_ID", run_id.as_str())];
 
-  // 1) 项目（启用的）
-  for p in cfg.projects.iter().filter(|p| p.enabled) {
-    let label = project_label(p);
-    progress.on_item_start(&label);
-    let remote_dest = join_remote(&projects_root, &p.remote_dir_name);
-    let backup_dir = join_remote(&run_backup_root, &join_remote("projects", &p.remote_dir_name));
-
-    let cmd = if cfg.flags.mirror_delete { "sync" } else { "copy" };
-    let args = build_project_item_args(
-      cfg,
-      &rclone_conf,
-      cmd,
-      &p.local_path,
-      &remote_dest,
-      &backup_dir,
-      true,
-    );
-
-    let r = run_one_stream(&rclone, &mut log, &label, &args, progress);
-    progress.on_item_done(&r);
-    items.push(r);
+  // 运行前命令失败：这次所有项都不同步
+  let pre_run = cfg.hooks.pre_run.trim();
+  if !pre_run.is_empty() {
+    run_hooks.push(hooks::run_local(runs::HOOK_PRE_RUN, pre_run, None, &run_env, &mut log, progress));
   }
+  let pre_run_failed = run_hooks.iter().any(|h| !h.ok);
 
-  // 2) Codex：只同步 config.toml + sessions/
-  if cfg.flags.sync_codex {
-    let (local_config, local_sessions) = codex_paths()?;
-
-    if local_sessions.is_dir() {
-      progress.on_item_start("Codex: sessions");
-      let backup_dir = join_remote(&run_backup_root, "codex/sessions");
-      let cmd = if cfg.flags.mirror_delete { "sync" } else { "copy" };
-      let args = build_project_item_args(
-        cfg,
-        &rclone_conf,
-        cmd,
-        &local_sessions.display().to_string(),
-        ".codex/sessions",
-        &backup_dir,
-        false,
-      );
-      let r = run_one_stream(&rclone, &mut log, "Codex: sessions", &args, progress);
-      progress.on_item_done(&r);
-      items.push(r);
+  for item in &plan {
+    let blocked = if cancel_requested() {
+      Some("已取消".to_string())
+    } else if pre_run_failed {
+      Some("运行前命令失败，已跳过".to_string())
     } else {
-      items.push(SyncItemResult {
-        label: "Codex: sessions".to_string(),
-        ok: true,
-        message: "本机未找到 .codex/sessions，已跳过".to_string(),
-      });
+      None
+    };
+    let skip_message = match (&item.skip_message, blocked) {
+      (Some(m), _) => Some((true, m.clone())),
+      (None, Some(m)) => Some((false, m)),
+      (None, None) => None,
+    };
+    if let Some((ok, message)) = skip_message {
+      let r = SyncItemResult {
+        item_id: item.id.clone(),
+        label: item.label.clone(),
+        ok,
+        message,
+        bytes: 0,
+        hooks: vec![],
+        drift: vec![],
+        git: None,
+      };
+      // 取消 / 运行前命令失败跳过的项算在总数里，也要报完成，进度才走得到头
+      // （本机找不到的项一开始就不算在总数里）
+      if item.skip_message.is_none() {
+        progress.on_item_done(&r);
+      }
+      items.push(r.clone());
+      journal.done.push(r);
+      continue;
     }
 
-    if local_config.is_file() {
-      progress.on_item_start("Codex: config.toml");
-      // copyto：把单个文件放到固定位置
-      let mut args = make_common_args(&rclone_conf);
-      args.push("copyto".to_string());
-      args.push(local_config.display().to_string());
-      args.push("remote:.codex/config.toml".to_string());
-      args.push("--backup-dir".to_string());
-      args.push(format!("remote:{}", join_remote(&run_backup_root, "codex/config")));
-      let r = run_one_stream(&rclone, &mut log, "Codex: config.toml", &args, progress);
-      progress.on_item_done(&r);
-      items.push(r);
+    journal.current_item_id = Some(item.id.clone());
+    let _ = journal::write(&mut journal);
+    progress.on_item_start(&item.label);
+
+    let item_hooks = cfg.hooks.items.get(&item.id).cloned().unwrap_or_default();
+    let item_env = [
+      ("AGENTSYNC_RUN_ID", run_id.as_str()),
+      ("AGENTSYNC_ITEM_ID", item.id.as_str()),
+    ];
+    let local_dir = if item.is_file { item.local.parent() } else { Some(item.local.as_path()) };
+    let remote_dir = if item.is_file {
+      item.remote_dest.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
     } else {
-      items.push(SyncItemResult {
-        label: "Codex: config.toml".to_string(),
-        ok: true,
-        message: "本机未找到 .codex/config.toml，已跳过".to_string(),
-      });
+      item.remote_dest.as_str()
+    };
+
+    let mut hook_results = Vec::new();
+    let pre_sync = item_hooks.pre_sync.trim();
+    if !pre_sync.is_empty() {
+      hook_results.push(hooks::run_local(runs::HOOK_PRE_SYNC, pre_sync, local_dir, &item_env, &mut log, progress));
     }
-  }
 
-  // 3) .agents
-  if cfg.flags.sync_agents {
-    let local_agents = agents_dir()?;
-    if local_agents.is_dir() {
-      progress.on_item_start(".agents");
-      let backup_dir = join_remote(&run_backup_root, "agents");
-      let cmd = if cfg.flags.mirror_delete { "sync" } else { "copy" };
-      let args = build_project_item_args(
-        cfg,
-        &rclone_conf,
-        cmd,
-        &local_agents.display().to_string(),
-        ".agents",
-        &backup_dir,
-        false,
-      );
-      let r = run_one_stream(&rclone, &mut log, ".agents", &args, progress);
-      progress.on_item_done(&r);
-      items.push(r);
+    let r = if hook_results.iter().any(|h| !h.ok) {
+      // 同步前命令失败：跳过这一项（比如 git stash 没成功，不要把半截状态同步过去）
+      SyncItemResult {
+        item_id: item.id.clone(),
+        label: item.label.clone(),
+        ok: false,
+        message: "同步前命令失败，已跳过本项".to_string(),
+        bytes: 0,
+        hooks: hook_results,
+        drift: vec![],
+        git: None,
+      }
     } else {
-      items.push(SyncItemResult {
-        label: ".agents".to_string(),
-        ok: true,
-        message: "本机未找到 ~/.agents，已跳过".to_string(),
-      });
-    }
+      // 目标端有人改过的文件：按设置覆盖 / 跳过 / 先拉回 / 先另存
+      let since = last_ok.get(&item.id).copied();
+      let drifted = drift::prepare(&remote, item, &run_id, since, drift_action, &mut log, progress);
+      if let Some(message) = drifted.blocked {
+        let r = SyncItemResult {
+          item_id: item.id.clone(),
+          label: item.label.clone(),
+          ok: false,
+          message,
+          bytes: 0,
+          hooks: hook_results,
+          drift: drifted.files,
+          git: None,
+        };
+        progress.on_item_done(&r);
+        items.push(r.clone());
+        journal.current_item_id = None;
+        journal.done.push(r);
+        let _ = journal::write(&mut journal);
+        continue;
+      }
+
+      let backup_dir = join_remote(&run_backup_root, &item.backup_sub);
+      let mut args = if item.is_file {
+        // copyto：把单个文件放到固定位置
+        let mut args = make_common_args(cfg, &rclone_conf);
+        args.push("copyto".to_string());
+        args.push(item.local.display().to_string());
+        args.push(format!("remote:{}", item.remote_dest));
+        args.push("--backup-dir".to_string());
+        args.push(format!("remote:{}", backup_dir));
+        if cfg.flags.checksum {
+          args.push("--checksum".to_string());
+        }
+        args
+      } else {
+        let cmd = if cfg.flags.mirror_delete { "sync" } else { "copy" };
+        build_project_item_args(cfg, &rclone_conf, cmd, item, &backup_dir)
+      };
+
+      // git 仓库：记下分支 / HEAD / 有没有未提交的改动；bundle / fetch 模式下 `.git` 单独推 / 取，不走 rclone
+      let mut git_state = if item.is_file { None } else { git::local_state(&item.local) };
+
+      // 文件清单：rclone 把每个文件的结果写到临时文件，跑完收进 manifests/<run_id>.jsonl
+      let combined = manifest::combined_temp_path();
+      args.push("--combined".to_string());
+      args.push(combined.display().to_string());
+
+      let mut r = run_one_stream(&rclone, &mut log, &item.id, &item.label, &args, progress);
+      if let Err(e) = manifest::record(&run_id, &item.id, &combined, cfg.flags.mirror_delete, &backup_dir) {
+        let _ = runs::append_log_line(&mut log, &format!("manifest {}：{}", item.id, e));
+      }
+      if cancel_requested() {
+        r.ok = false;
+        r.message = "已取消（本项可能只同步了一部分）".to_string();
+      }
+
+      if r.ok && item.git != GitMode::Off && !cancel_requested() {
+        match git_state.as_mut() {
+          Some(state) if item.git == GitMode::Fetch => {
+            match git::fetch_bundle(&remote, item, &run_id, state, &mut log, progress) {
+              Ok(()) => r.message = format!("{}；已取回目标端的 git 分支（refs/remotes/agentsync/）", r.message),
+              Err(e) => {
+                r.ok = false;
+                r.message = format!("工作区已同步，但目标端的 .git 没取回来：{}", e);
+              }
+            }
+          }
+          Some(state) => match git::push_bundle(&remote, item, &run_id, state, &mut log, progress) {
+            Ok(()) => r.message = format!("{}；git {} 已推到目标端", r.message, git::describe(state)),
+            Err(e) => {
+              r.ok = false;
+              r.message = format!("工作区已同步，但 .git 没推过去：{}", e);
+            }
+          },
+          None => r.message = format!("{}；不是 git 仓库（或本机没装 git），只同步了工作区", r.message),
+        }
+      }
+
+      // 同步后命令只在同步成功时跑（例如目标端重新装依赖）
+      let post_sync = item_hooks.post_sync_remote.trim();
+      if r.ok && !post_sync.is_empty() && !cancel_requested() {
+        let h = hooks::run_remote(cfg, runs::HOOK_POST_SYNC_REMOTE, post_sync, remote_dir, &mut log, progress);
+        if !h.ok {
+          r.ok = false;
+          r.message = format!("同步完成，但目标端同步后命令失败：{}", h.message);
+        }
+        hook_results.push(h);
+      }
+      if let Some(note) = drifted.note {
+        r.message = format!("{}；{}", r.message, note);
+      }
+      r.hooks = hook_results;
+      r.drift = drifted.files;
+      r.git = git_state;
+      r
+    };
+
+    progress.on_item_done(&r);
+    items.push(r.clone());
+
+    journal.current_item_id = None;
+    journal.done.push(r);
+    let _ = journal::write(&mut journal);
+  }
+
+  // 运行后命令：不管前面成败（包括被取消）都跑，用 AGENTSYNC_OK 告诉它结果
+  let cancelled = cancel_requested();
+  CANCEL_REQUESTED.store(false, Ordering::SeqCst);
+  let post_run = cfg.hooks.post_run.trim();
+  if !post_run.is_empty() {
+    let ok_text = if items.iter().all(|i| i.ok) && !pre_run_failed { "1" } else { "0" };
+    let env = [("AGENTSYNC_RUN_ID", run_id.as_str()), ("AGENTSYNC_OK", ok_text)];
+    run_hooks.push(hooks::run_local(runs::HOOK_POST_RUN, post_run, None, &env, &mut log, progress));
   }
 
   let ended_at_ms = now_ms();
-  let ok = items.iter().all(|i| i.ok);
+  let ok = !cancelled && items.iter().all(|i| i.ok) && run_hooks.iter().all(|h| h.ok);
 
-  let _ = fs::remove_file(&rclone_conf);
+  drop(rclone_conf);
 
   let summary = SyncRunSummary {
     run_id: run_id.clone(),
@@ -502,6 +959,13 @@ pub fn run_sync_with_id(
     backup_root: run_backup_root.clone(),
     log_path: log_path.display().to_string(),
     items: items.clone(),
+    trigger: opts.trigger.clone(),
+    status: if cancelled { RunStatus::Cancelled } else { RunStatus::Completed },
+    note: cancelled.then(|| "同步被手动取消".to_string()),
+    hooks: run_hooks,
+    manifest_path: manifest::existing_path(&run_id),
+    target: Some(cfg.connection.target_text()),
+    log_missing: false,
   };
 
   let _ = runs::append_log_line(&mut log, "---- summary ----");
@@ -509,5 +973,337 @@ pub fn run_sync_with_id(
   let _ = runs::append_log_line(&mut log, &format!("backup_root: {}", run_backup_root));
 
   runs::append_run(&summary)?;
+  journal::remove(&run_id);
+
+  // 对外通知（webhook / Slack / ntfy）：后台发，结果只写日志，不影响这次同步的成败
+  webhooks::send_run_background(cfg, &summary);
+
+  // 日志保留策略：这次的日志还开着，不动它
+  let _ = logs::enforce(&cfg.log_retention, Some(&run_id));
   Ok(summary)
 }
+
+/// 目标端备份目录里的一次运行（目录名就是当次的 run_id）。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupEntry {
+  pub run_id: String,
+  pub remote_path: String,
+  pub mod_time: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreResult {
+  pub run_id: String,
+  pub ok: bool,
+  pub items: Vec<SyncItemResult>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct LsJsonEntry {
+  pub path: String,
+  #[serde(default)]
+  pub size: i64,
+  #[serde(default)]
+  pub mod_time: Option<String>,
+  #[serde(default)]
+  pub is_dir: bool,
+}
+
+pub(crate) fn rclone_lsjson(rclone: &Path, rclone_conf: &Path, remote_path: &str, extra: &[&str]) -> Result<Vec<LsJsonEntry>, String> {
+  let mut args = vec![
+    "--config".to_string(),
+    rclone_conf.display().to_string(),
+    "lsjson".to_string(),
+    format!("remote:{}", remote_path),
+  ];
+  args.extend(extra.iter().map(|s| s.to_string()));
+  let out = rclone_output(rclone, &args)?;
+  if !out.status.success() {
+    let err = String::from_utf8_lossy(&out.stderr).to_string();
+    return Err(format!("读取目标端目录失败：{}（{}）", remote_path, err.trim()));
+  }
+  serde_json::from_slice::<Vec<LsJsonEntry>>(&out.stdout)
+    .map_err(|e| format!("解析 rclone lsjson 输出失败（{}）", e))
+}
+
+pub fn list_backups(cfg: &AgentSyncConfig) -> Result<Vec<BackupEntry>, String> {
+  let cfg = &vars::expanded(cfg)?;
+  validate_basic(cfg)?;
+  let rclone = resolve_rclone(cfg)?;
+  let rclone_conf = write_temp_rclone_config(cfg)?;
+  let backup_root = normalize_remote_path(&cfg.remote.backup_root);
+
+  let result = rclone_lsjson(&rclone, &rclone_conf, &backup_root, &["--dirs-only"]);
+  drop(rclone_conf);
+
+  let mut out: Vec<BackupEntry> = result?
+    .into_iter()
+    .filter(|e| e.is_dir)
+    .map(|e| BackupEntry {
+      remote_path: join_remote(&backup_root, &e.path),
+      run_id: e.path,
+      mod_time: e.mod_time,
+    })
+    .collect();
+  // ModTime 是 RFC3339 字符串，直接按字符串倒序就是“新的在前”
+  out.sort_by(|a, b| b.mod_time.cmp(&a.mod_time));
+  Ok(out)
+}
+
+/// 备份目录里的子路径 -> 目标端原位置（和 run_sync_with_id 里的 --backup-dir 布局一一对应）。
+struct BackupSlot {
+  item_id: String,
+  label: String,
+  backup_sub: String,
+  remote_dest: String,
+}
+
+fn backup_layout(cfg: &AgentSyncConfig) -> Vec<BackupSlot> {
+  let projects_root = normalize_remote_path(&cfg.remote.projects_root);
+  let mut out: Vec<BackupSlot> = cfg
+    .projects
+    .iter()
+    .map(|p| BackupSlot {
+      item_id: p.id.clone(),
+      label: project_label(p),
+      backup_sub: join_remote("projects", &p.remote_dir_name),
+      remote_dest: join_remote(&projects_root, &p.remote_dir_name),
+    })
+    .collect();
+
+  let fixed = [
+    (CODEX_SESSIONS_ID, "Codex: sessions", "codex/sessions", ".codex/sessions"),
+    // copyto 的备份文件直接落在 codex/config/config.toml
+    (CODEX_CONFIG_ID, "Codex: config.toml", "codex/config", ".codex"),
+    (AGENTS_ID, ".agents", "agents", ".agents"),
+  ];
+  for (item_id, label, backup_sub, remote_dest) in fixed {
+    out.push(BackupSlot {
+      item_id: item_id.to_string(),
+      label: label.to_string(),
+      backup_sub: backup_sub.to_string(),
+      remote_dest: remote_dest.to_string(),
+    });
+  }
+  out
+}
+
+/// 把某次运行备份下来的文件放回目标端原位置（只在目标端内部复制，不动本机）。
+pub fn restore_backup(
+  cfg: &AgentSyncConfig,
+  backup_run_id: &str,
+  only: Option<&[String]>,
+) -> Result<RestoreResult, String> {
+  let cfg = &vars::expanded(cfg)?;
+  validate_basic(cfg)?;
+  if backup_run_id.trim().is_empty() || backup_run_id.contains('/') || backup_run_id.contains('\\') {
+    return Err(format!("备份 run_id 无效：{}", backup_run_id));
+  }
+  let rclone = resolve_rclone(cfg)?;
+  let rclone_conf = write_temp_rclone_config(cfg)?;
+  let backup_root = join_remote(&normalize_remote_path(&cfg.remote.backup_root), backup_run_id);
+
+  let result = (|| -> Result<Vec<SyncItemResult>, String> {
+    let existing: Vec<String> = rclone_lsjson(
+      &rclone,
+      &rclone_conf,
+      &backup_root,
+      &["--dirs-only", "-R", "--max-depth", "2"],
+    )?
+    .into_iter()
+    .map(|e| e.path)
+    .collect();
+
+    let mut items = Vec::new();
+    for slot in backup_layout(cfg) {
+      if !is_selected(only, &slot.item_id) || !existing.iter().any(|p| p == &slot.backup_sub) {
+        continue;
+      }
+      let mut args = make_common_args(cfg, &rclone_conf);
+      args.push("copy".to_string());
+      args.push(format!("remote:{}", join_remote(&backup_root, &slot.backup_sub)));
+      args.push(format!("remote:{}", slot.remote_dest));
+      let out = rclone_output(&rclone, &args)?;
+      items.push(if out.status.success() {
+        SyncItemResult {
+          item_id: slot.item_id.clone(),
+          label: slot.label,
+          ok: true,
+          message: format!("已恢复到 {}", slot.remote_dest),
+          bytes: 0,
+          hooks: vec![],
+          drift: vec![],
+          git: None,
+        }
+      } else {
+        let err = String::from_utf8_lossy(&out.stderr).to_string();
+        SyncItemResult {
+          item_id: slot.item_id.clone(),
+          label: slot.label,
+          ok: false,
+          message: format!("恢复失败：{}", err.trim().lines().last().unwrap_or("")),
+          bytes: 0,
+          hooks: vec![],
+          drift: vec![],
+          git: None,
+        }
+      });
+    }
+    Ok(items)
+  })();
+
+  drop(rclone_conf);
+  let items = result?;
+  if items.is_empty() {
+    return Err(format!("备份 {} 里没有可恢复的同步项", backup_run_id));
+  }
+  Ok(RestoreResult {
+    run_id: backup_run_id.to_string(),
+    ok: items.iter().all(|i| i.ok),
+    items,
+  })
+}
+
+/// 一个同步项的校验结果（`rclone check`，文件路径相对该项的根目录）。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyItemResult {
+  pub item_id: String,
+  pub label: String,
+  pub ok: bool,
+  /// `hash`：按哈希比较；`size`：两边没有共同的哈希算法，只比较了大小
+  pub method: String,
+  pub matched: u64,
+  /// 本机有、目标端没有
+  pub missing: Vec<String>,
+  /// 目标端有、本机没有（镜像删除关闭时不算失败）
+  pub extra: Vec<String>,
+  /// 两边都有但内容不同
+  pub differ: Vec<String>,
+  /// 读取出错的文件
+  pub errors: Vec<String>,
+  pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyResult {
+  pub ok: bool,
+  pub items: Vec<VerifyItemResult>,
+}
+
+fn verify_item(cfg: &AgentSyncConfig, rclone: &Path, rclone_conf: &Path, item: &PlannedItem) -> VerifyItemResult {
+  let mut res = VerifyItemResult {
+    item_id: item.id.clone(),
+    label: item.label.clone(),
+    ok: false,
+    method: "hash".to_string(),
+    matched: 0,
+    missing: vec![],
+    extra: vec![],
+    differ: vec![],
+    errors: vec![],
+    message: String::new(),
+  };
+
+  let mut args = make_common_args(cfg, rclone_conf);
+  args.push("check".to_string());
+  if item.is_file {
+    // 单个文件：比较所在目录，只看这一个文件
+    let (Some(parent), Some(name)) = (item.local.parent(), item.local.file_name()) else {
+      res.message = format!("路径无效：{}", item.local.display());
+      return res;
+    };
+    let remote_dir = item.remote_dest.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+    args.push(parent.display().to_string());
+    args.push(format!("remote:{}", remote_dir));
+    args.push("--include".to_string());
+    args.push(format!("/{}", name.to_string_lossy()));
+  } else {
+    args.push(item.local.display().to_string());
+    args.push(format!("remote:{}", item.remote_dest));
+    args.extend(exclude_args(cfg, item));
+  }
+  args.push("--combined".to_string());
+  args.push("-".to_string());
+
+  let out = match rclone_output(rclone, &args) {
+    Ok(o) => o,
+    Err(e) => {
+      res.message = e;
+      return res;
+    }
+  };
+
+  // --combined 每行一个文件：`= 一致`、`- 目标端缺少`、`+ 目标端多出`、`* 不一致`、`! 出错`
+  for line in String::from_utf8_lossy(&out.stdout).lines() {
+    let Some((mark, path)) = line.split_once(' ') else {
+      continue;
+    };
+    let path = path.to_string();
+    match mark {
+      "=" => res.matched += 1,
+      "-" => res.missing.push(path),
+      "+" => res.extra.push(path),
+      "*" => res.differ.push(path),
+      "!" => res.errors.push(path),
+      _ => {}
+    }
+  }
+  let stderr = String::from_utf8_lossy(&out.stderr);
+  if stderr.contains("No common hash found") {
+    res.method = "size".to_string();
+  }
+
+  let extra_counts = cfg.flags.mirror_delete && !res.extra.is_empty();
+  res.ok = res.missing.is_empty() && res.differ.is_empty() && res.errors.is_empty() && !extra_counts;
+
+  let failed_without_report = !out.status.success()
+    && res.missing.is_empty()
+    && res.differ.is_empty()
+    && res.errors.is_empty()
+    && res.extra.is_empty();
+  res.message = if failed_without_report {
+    // 比如目标端目录还不存在、连不上
+    res.ok = false;
+    let err = stderr.trim().lines().last().unwrap_or("").to_string();
+    format!("校验失败：{}", err)
+  } else if res.ok {
+    format!("一致（{} 个文件）", res.matched)
+  } else {
+    format!(
+      "不一致：缺少 {}，多出 {}，内容不同 {}，出错 {}",
+      res.missing.len(),
+      res.extra.len(),
+      res.differ.len(),
+      res.errors.len()
+    )
+  };
+  res
+}
+
+/// 逐项用 `rclone check` 比较本机和目标端，报告缺少 / 多出 / 内容不同的文件（只读，不改任何文件）。
+pub fn verify(cfg: &AgentSyncConfig, only: Option<&[String]>) -> Result<VerifyResult, String> {
+  let cfg = &vars::expanded(cfg)?;
+  validate_basic(cfg)?;
+  let rclone = resolve_rclone(cfg)?;
+  let rclone_conf = write_temp_rclone_config(cfg)?;
+
+  let result = plan_items(cfg, only).map(|plan| {
+    plan
+      .iter()
+      .filter(|item| item.skip_message.is_none())
+      .map(|item| verify_item(cfg, &rclone, &rclone_conf, item))
+      .collect::<Vec<_>>()
+  });
+
+  drop(rclone_conf);
+  let items = result?;
+  Ok(VerifyResult {
+    ok: items.iter().all(|i| i.ok),
+    items,
+  })
+}
@@ -1,17 +1,113 @@
 //! 运行历史与日志：每次同步写一条记录，方便回看。
 
 use crate::config;
+use crate::lockfile;
+use chrono::TimeZone;
+use flate2::read::GzDecoder;
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SyncItemResult {
+  /// 同步项 id（项目 id / codex:sessions / codex:config / agents）；旧记录里没有
+  #[serde(default)]
+  pub item_id: String,
   pub label: String,
   pub ok: bool,
   pub message: String,
+  /// rclone 报告的传输字节数（取最后一条进度行；没有就是 0）
+  #[serde(default)]
+  pub bytes: u64,
+  /// 这一项的同步前 / 同步后命令的结果（没配置就为空）
+  #[serde(default)]
+  pub hooks: Vec<HookResult>,
+  /// 同步前发现的目标端改动（上次同步后在目标端被改过的文件）
+  #[serde(default)]
+  pub drift: Vec<DriftFile>,
+  /// 项目是 git 仓库时，同步那一刻本机的分支 / HEAD / 有没有未提交的改动
+  #[serde(default)]
+  pub git: Option<GitState>,
+}
+
+/// 一个 git 仓库的状态（同步时在本机读的）。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitState {
+  /// 当前分支；HEAD 游离（detached）时为空
+  pub branch: Option<String>,
+  /// HEAD 指向的提交；还没有任何提交时为空
+  pub head: Option<String>,
+  /// 有没有未提交的改动（包括没跟踪的新文件）
+  pub dirty: bool,
+  /// 有改动的文件数
+  pub changed_files: u32,
+  /// `bundle` 模式下推过去、`fetch` 模式下取回来的 bundle 大小（字节；没有新提交时是 0）
+  #[serde(default)]
+  pub bundle_bytes: Option<u64>,
+  /// `bundle` 模式下目标端的分支 / HEAD 已经和本机一致
+  #[serde(default)]
+  pub remote_updated: bool,
+  /// `fetch` 模式下取回了目标端几个分支（放在 `refs/remotes/agentsync/`）
+  #[serde(default)]
+  pub fetched_branches: Option<u32>,
+}
+
+/// 目标端在上次同步之后被改过的一个文件（路径相对该项的根目录；单文件项就是文件名）。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftFile {
+  pub path: String,
+  pub size: i64,
+  /// 目标端的修改时间（RFC3339）
+  pub mod_time: String,
+  /// 本机有没有同名文件：没有的话镜像删除会把它挪进备份目录
+  pub local_exists: bool,
+}
+
+/// 同步前后命令的阶段（写在 `HookResult.stage` 里）。
+pub const HOOK_PRE_RUN: &str = "preRun";
+pub const HOOK_POST_RUN: &str = "postRun";
+pub const HOOK_PRE_SYNC: &str = "preSync";
+pub const HOOK_POST_SYNC_REMOTE: &str = "postSyncRemote";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HookResult {
+  pub stage: String,
+  pub command: String,
+  pub ok: bool,
+  /// 没能启动（比如找不到 ssh）时为空
+  pub exit_code: Option<i32>,
+  pub message: String,
+}
+
+/// 触发来源（写在 `SyncRunSummary.trigger` 里；定时同步是 `schedule:<id>`）。
+pub const TRIGGER_MANUAL: &str = "manual";
+pub const TRIGGER_WATCH: &str = "watch";
+pub const TRIGGER_CLI: &str = "cli";
+pub const TRIGGER_SCHEDULE_PREFIX: &str = "schedule:";
+/// 续跑被中断的运行：`resume:<原 run_id>`
+pub const TRIGGER_RESUME_PREFIX: &str = "resume:";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RunStatus {
+  /// 真的跑过（成功与否看 `ok`）
+  #[default]
+  Completed,
+  /// 到点了但没跑成（比如已有同步在运行、或者当时 App 没开）
+  Missed,
+  /// 跑到一半进程没了（崩溃 / 关机），启动时根据运行日志补记
+  Interrupted,
+  /// 跑到一半被手动取消（托盘 / 界面 / `agentsync cancel`）
+  Cancelled,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,12 +120,35 @@ pub struct SyncRunSummary {
   pub backup_root: String,
   pub log_path: String,
   pub items: Vec<SyncItemResult>,
+  #[serde(default)]
+  pub trigger: Option<String>,
+  #[serde(default)]
+  pub status: RunStatus,
+  #[serde(default)]
+  pub note: Option<String>,
+  /// 整次同步的运行前 / 运行后命令的结果
+  #[serde(default)]
+  pub hooks: Vec<HookResult>,
+  /// 这次传输 / 删除 / 备份了哪些文件（`~/.agentsync/manifests/<run_id>.jsonl`；没有文件变化时为空）
+  #[serde(default)]
+  pub manifest_path: Option<String>,
+  /// 同步到哪台电脑：`user@host:port`（旧记录、错过的定时没有）
+  #[serde(default)]
+  pub target: Option<String>,
+  /// 日志已按保留策略删掉（`logPath` 不再能打开）
+  #[serde(default)]
+  pub log_missing: bool,
 }
 
 pub fn log_file_path(run_id: &str) -> Result<PathBuf, String> {
   Ok(config::logs_dir()?.join(format!("{}.log", run_id)))
 }
 
+/// 压缩后的日志：`<run_id>.log.gz`
+pub fn compressed_log_path(run_id: &str) -> Result<PathBuf, String> {
+  Ok(config::logs_dir()?.join(format!("{}.log.gz", run_id)))
+}
+
 pub fn ensure_logs_dir() -> Result<(), String> {
   config::ensure_dir(&config::logs_dir()?)
 }
@@ -40,6 +159,13 @@ pub fn write_log_header(mut file: &fs::File, run_id: &str) -> Result<(), String>
   Ok(())
 }
 
+/// 历史文件超过这个大小时，写入后自动整理一次（见 `compact_history`）。
+const AUTO_COMPACT_BYTES: u64 = 8 * 1024 * 1024;
+/// 自动整理时保留最近多少天的记录在 `history.jsonl` 里，更早的挪进归档。
+pub const DEFAULT_KEEP_DAYS: u32 = 180;
+/// 查询结果里最多列出多少条坏行（总数另算）。
+const MAX_CORRUPT_REPORTED: usize = 20;
+
 pub fn append_run(record: &SyncRunSummary) -> Result<(), String> {
   let dir = config::agentsync_dir()?;
   config::ensure_dir(&dir)?;
@@ -47,48 +173,373 @@ pub fn append_run(record: &SyncRunSummary) -> Result<(), String> {
 
   let line =
     serde_json::to_string(record).map_err(|e| format!("序列化历史记录失败（{}）", e))?;
+  // 和整理历史共用一把锁：整理是“读一遍再整个换掉”，中间追加的记录会丢
+  let _lock = lockfile::lock(&path)?;
   let mut f = fs::OpenOptions::new()
     .create(true)
     .append(true)
     .open(&path)
     .map_err(|e| format!("写入历史记录失败：{}（{}）", path.display(), e))?;
   writeln!(f, "{}", line).map_err(|e| format!("写入历史记录失败（{}）", e))?;
+  drop(f);
+
+  // 监听模式跑几个月后文件会很大：超过阈值就顺手整理（失败不影响这次写入）
+  if fs::metadata(&path).map(|m| m.len() > AUTO_COMPACT_BYTES).unwrap_or(false) {
+    let _ = compact_locked(&path, DEFAULT_KEEP_DAYS);
+  }
   Ok(())
 }
 
-pub fn list_runs() -> Result<Vec<SyncRunSummary>, String> {
-  let path = config::history_file()?;
-  if !path.exists() {
-    return Ok(vec![]);
+/// 记一条“到点但没跑成”的历史（定时同步用）。
+pub fn append_missed(trigger: &str, at_ms: u64, note: &str) -> Result<(), String> {
+  append_run(&SyncRunSummary {
+    run_id: Uuid::new_v4().simple().to_string(),
+    started_at_ms: at_ms,
+    ended_at_ms: at_ms,
+    ok: false,
+    backup_root: "".to_string(),
+    log_path: "".to_string(),
+    items: vec![],
+    trigger: Some(trigger.to_string()),
+    status: RunStatus::Missed,
+    note: Some(note.to_string()),
+    hooks: vec![],
+    manifest_path: None,
+    target: None,
+    log_missing: false,
+  })
+}
+
+/// 每个同步项最近一次成功同步的结束时间（毫秒）；从没成功过的项不在里面。
+pub fn last_ok_item_times() -> Result<BTreeMap<String, u64>, String> {
+  let mut out = BTreeMap::new();
+  for run in list_runs()? {
+    for item in run.items.iter().filter(|i| i.ok && !i.item_id.is_empty()) {
+      // list_runs 是新的在前，第一次见到的就是最近一次
+      out.entry(item.item_id.clone()).or_insert(run.ended_at_ms);
+    }
   }
+  Ok(out)
+}
+
+/// 历史文件里解析不了的一行。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CorruptLine {
+  pub file: String,
+  /// 从 1 开始
+  pub line_no: usize,
+  /// 原文（太长的截断）
+  pub text: String,
+  pub error: String,
+}
+
+/// 一个 JSONL 历史文件的内容：能解析的记录 + 坏行（顺序和文件里一致）。
+#[derive(Default)]
+struct HistoryFile {
+  runs: Vec<SyncRunSummary>,
+  corrupt: Vec<CorruptLine>,
+  /// 坏行原文（整理时原样挪走）
+  corrupt_raw: Vec<String>,
+}
 
-  let f = fs::File::open(&path).map_err(|e| format!("读取历史记录失败：{}（{}）", path.display(), e))?;
+fn read_history_file(path: &Path) -> Result<HistoryFile, String> {
+  let mut out = HistoryFile::default();
+  if !path.exists() {
+    return Ok(out);
+  }
+  let f = fs::File::open(path).map_err(|e| format!("读取历史记录失败：{}（{}）", path.display(), e))?;
   let reader = BufReader::new(f);
 
-  let mut out = Vec::new();
-  for line in reader.lines() {
-    let line = match line {
-      Ok(v) => v,
-      Err(_) => continue,
-    };
+  for (idx, line) in reader.split(b'\n').enumerate() {
+    // 读到一半出错不能当作读完了：压缩会按读到的内容重写文件，后面的记录就丢了
+    let bytes = line.map_err(|e| format!("读取历史记录失败：{} 第 {} 行（{}）", path.display(), idx + 1, e))?;
+    let line = String::from_utf8_lossy(&bytes);
     if line.trim().is_empty() {
       continue;
     }
-    if let Ok(r) = serde_json::from_str::<SyncRunSummary>(&line) {
-      out.push(r);
+    match serde_json::from_str::<SyncRunSummary>(&line) {
+      Ok(r) => out.runs.push(r),
+      Err(e) => {
+        out.corrupt.push(CorruptLine {
+          file: path.display().to_string(),
+          line_no: idx + 1,
+          text: line.chars().take(200).collect(),
+          error: e.to_string(),
+        });
+        out.corrupt_raw.push(line.into_owned());
+      }
     }
   }
+  Ok(out)
+}
+
+fn archive_files() -> Result<Vec<PathBuf>, String> {
+  let dir = config::history_archive_dir()?;
+  let Ok(entries) = fs::read_dir(&dir) else {
+    return Ok(vec![]);
+  };
+  let mut files: Vec<PathBuf> = entries
+    .map_while(Result::ok)
+    .map(|e| e.path())
+    .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+    .collect();
+  files.sort();
+  Ok(files)
+}
 
-  out.sort_by(|a, b| b.started_at_ms.cmp(&a.started_at_ms));
+/// 当前历史（不含归档），新的在前；坏行跳过（用 `query_runs` 能看到坏行报告）。
+pub fn list_runs() -> Result<Vec<SyncRunSummary>, String> {
+  let mut out = read_history_file(&config::history_file()?)?.runs;
+  out.sort_by_key(|r| Reverse(r.started_at_ms));
   Ok(out)
 }
 
+/// 历史查询条件；都不填就是“全部，新的在前”。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RunQuery {
+  pub offset: usize,
+  /// 为空表示不分页
+  pub limit: Option<usize>,
+  /// 目标 `user@host:port`，包含即可
+  pub target: Option<String>,
+  pub status: Option<RunStatus>,
+  /// 只要成功 / 只要失败
+  pub ok: Option<bool>,
+  /// 开始时间范围（毫秒，含两端）
+  pub from_ms: Option<u64>,
+  pub to_ms: Option<u64>,
+  /// 同步项的名字或 id，包含即可（不分大小写）
+  pub item: Option<String>,
+  /// 也查整理时挪进归档的旧记录
+  pub include_archived: bool,
+}
+
+impl RunQuery {
+  fn matches(&self, r: &SyncRunSummary) -> bool {
+    let item = self.item.as_deref().map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty());
+    self
+      .target
+      .as_deref()
+      .is_none_or(|t| r.target.as_deref().is_some_and(|rt| rt.contains(t.trim())))
+      && self.status.is_none_or(|s| s == r.status)
+      && self.ok.is_none_or(|ok| ok == r.ok)
+      && self.from_ms.is_none_or(|from| r.started_at_ms >= from)
+      && self.to_ms.is_none_or(|to| r.started_at_ms <= to)
+      && item.is_none_or(|q| {
+        r.items
+          .iter()
+          .any(|i| i.label.to_lowercase().contains(&q) || i.item_id.to_lowercase().contains(&q))
+      })
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunPage {
+  /// 符合条件的总条数（分页前）
+  pub total: usize,
+  pub offset: usize,
+  pub runs: Vec<SyncRunSummary>,
+  pub corrupt_count: usize,
+  /// 坏行（最多列 20 条）；可以用 `compact_history` 把它们挪到 `history.corrupt.jsonl`
+  pub corrupt: Vec<CorruptLine>,
+}
+
+/// 分页 / 过滤查询历史，新的在前；顺带报告解析不了的行。
+pub fn query_runs(q: &RunQuery) -> Result<RunPage, String> {
+  let mut files = vec![config::history_file()?];
+  if q.include_archived {
+    files.extend(archive_files()?);
+  }
+
+  let mut all = Vec::new();
+  let mut corrupt = Vec::new();
+  for f in &files {
+    let h = read_history_file(f)?;
+    all.extend(h.runs.into_iter().filter(|r| q.matches(r)));
+    corrupt.extend(h.corrupt);
+  }
+  all.sort_by_key(|r| Reverse(r.started_at_ms));
+
+  let total = all.len();
+  let runs: Vec<SyncRunSummary> = match q.limit {
+    Some(n) => all.into_iter().skip(q.offset).take(n).collect(),
+    None => all.into_iter().skip(q.offset).collect(),
+  };
+  let corrupt_count = corrupt.len();
+  corrupt.truncate(MAX_CORRUPT_REPORTED);
+  Ok(RunPage {
+    total,
+    offset: q.offset,
+    runs,
+    corrupt_count,
+    corrupt,
+  })
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompactResult {
+  /// 留在 history.jsonl 里的条数
+  pub kept: usize,
+  /// 挪进归档的条数
+  pub archived: usize,
+  /// 同一个 run_id 出现多次时只留最后一条
+  pub duplicates_removed: usize,
+  /// 挪到 history.corrupt.jsonl 的坏行
+  pub corrupt_moved: usize,
+  pub bytes_before: u64,
+  pub bytes_after: u64,
+}
+
+fn append_lines(path: &Path, lines: &[String]) -> Result<(), String> {
+  if lines.is_empty() {
+    return Ok(());
+  }
+  if let Some(dir) = path.parent() {
+    config::ensure_dir(dir)?;
+  }
+  let mut f = fs::OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(path)
+    .map_err(|e| format!("写入失败：{}（{}）", path.display(), e))?;
+  for line in lines {
+    writeln!(f, "{}", line).map_err(|e| format!("写入失败：{}（{}）", path.display(), e))?;
+  }
+  Ok(())
+}
+
+fn archive_month(ms: u64) -> String {
+  chrono::Local
+    .timestamp_millis_opt(ms as i64)
+    .single()
+    .map(|t| t.format("%Y-%m").to_string())
+    .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// 整理 history.jsonl：去掉重复的 run_id、按时间排好，`keep_days` 天前的记录按月挪进
+/// `history-archive/<YYYY-MM>.jsonl`，坏行原样挪到 `history.corrupt.jsonl`。
+pub fn compact_history(keep_days: u32) -> Result<CompactResult, String> {
+  let path = config::history_file()?;
+  let _lock = lockfile::lock(&path)?;
+  compact_locked(&path, keep_days)
+}
+
+/// 已经拿着历史锁时调。
+fn compact_locked(path: &Path, keep_days: u32) -> Result<CompactResult, String> {
+  let bytes_before = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+  let HistoryFile { runs, corrupt_raw, .. } = read_history_file(path)?;
+
+  // 同一个 run_id 只留最后写入的那条（例如中断补记之后又正常写入）
+  let total = runs.len();
+  let mut by_id: BTreeMap<String, SyncRunSummary> = BTreeMap::new();
+  for r in runs {
+    by_id.insert(r.run_id.clone(), r);
+  }
+  let duplicates_removed = total - by_id.len();
+
+  let cutoff = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_millis() as u64;
+  let cutoff = cutoff.saturating_sub(keep_days as u64 * 24 * 3600 * 1000);
+
+  let mut runs: Vec<SyncRunSummary> = by_id.into_values().collect();
+  runs.sort_by_key(|r| r.started_at_ms);
+  let (old, keep): (Vec<_>, Vec<_>) = runs.into_iter().partition(|r| r.started_at_ms < cutoff);
+
+  // 先写归档和坏行，再替换原文件：中途失败最多是归档里多一份，不会丢记录
+  let mut by_month: BTreeMap<String, Vec<String>> = BTreeMap::new();
+  for r in &old {
+    let line = serde_json::to_string(r).map_err(|e| format!("序列化历史记录失败（{}）", e))?;
+    by_month.entry(archive_month(r.started_at_ms)).or_default().push(line);
+  }
+  let archive_dir = config::history_archive_dir()?;
+  for (month, lines) in &by_month {
+    append_lines(&archive_dir.join(format!("{}.jsonl", month)), lines)?;
+  }
+  append_lines(&config::history_corrupt_file()?, &corrupt_raw)?;
+
+  let tmp = path.with_extension("jsonl.tmp");
+  let mut text = String::new();
+  for r in &keep {
+    text.push_str(&serde_json::to_string(r).map_err(|e| format!("序列化历史记录失败（{}）", e))?);
+    text.push('\n');
+  }
+  config::ensure_dir(&config::agentsync_dir()?)?;
+  fs::write(&tmp, &text).map_err(|e| format!("写入历史记录失败：{}（{}）", tmp.display(), e))?;
+  fs::rename(&tmp, path).map_err(|e| format!("写入历史记录失败：{}（{}）", path.display(), e))?;
+
+  Ok(CompactResult {
+    kept: keep.len(),
+    archived: old.len(),
+    duplicates_removed,
+    corrupt_moved: corrupt_raw.len(),
+    bytes_before,
+    bytes_after: text.len() as u64,
+  })
+}
+
+/// 日志清理后的变化：`Some(新路径)` 表示压缩了，`None` 表示删掉了。
+pub(crate) type LogChanges = BTreeMap<String, Option<String>>;
+
+/// 把日志清理的结果写回 history.jsonl（改 `logPath` / 标 `logMissing`），其余行原样保留。
+pub(crate) fn apply_log_changes(changes: &LogChanges) -> Result<usize, String> {
+  let path = config::history_file()?;
+  if changes.is_empty() || !path.exists() {
+    return Ok(0);
+  }
+  let _lock = lockfile::lock(&path)?;
+  let bytes = fs::read(&path).map_err(|e| format!("读取历史记录失败：{}（{}）", path.display(), e))?;
+  let mut text = String::new();
+  let mut updated = 0;
+  for line in String::from_utf8_lossy(&bytes).lines().filter(|l| !l.trim().is_empty()) {
+    let changed = serde_json::from_str::<SyncRunSummary>(line).ok().and_then(|mut r| {
+      let change = changes.get(&r.run_id)?;
+      match change {
+        Some(p) => r.log_path = p.clone(),
+        None => r.log_missing = true,
+      }
+      serde_json::to_string(&r).ok()
+    });
+    match changed {
+      Some(l) => {
+        updated += 1;
+        text.push_str(&l);
+      }
+      // 没变的、解析不了的行都原样写回
+      None => text.push_str(line),
+    }
+    text.push('\n');
+  }
+  if updated == 0 {
+    return Ok(0);
+  }
+  let tmp = path.with_extension("jsonl.tmp");
+  fs::write(&tmp, &text).map_err(|e| format!("写入历史记录失败：{}（{}）", tmp.display(), e))?;
+  fs::rename(&tmp, &path).map_err(|e| format!("写入历史记录失败：{}（{}）", path.display(), e))?;
+  Ok(updated)
+}
+
+/// 读某次运行的日志；已经压缩成 `.log.gz` 的自动解压。
 pub fn read_log(run_id: &str) -> Result<String, String> {
   let path = log_file_path(run_id)?;
-  if !path.exists() {
-    return Err(format!("找不到日志文件：{}", path.display()));
+  if path.exists() {
+    return fs::read_to_string(&path).map_err(|e| format!("读取日志失败：{}（{}）", path.display(), e));
+  }
+  let gz = compressed_log_path(run_id)?;
+  if !gz.exists() {
+    return Err(format!("找不到日志文件：{}（可能已按保留策略清理）", path.display()));
   }
-  fs::read_to_string(&path).map_err(|e| format!("读取日志失败：{}（{}）", path.display(), e))
+  let f = fs::File::open(&gz).map_err(|e| format!("读取日志失败：{}（{}）", gz.display(), e))?;
+  let mut text = String::new();
+  GzDecoder::new(f)
+    .read_to_string(&mut text)
+    .map_err(|e| format!("解压日志失败：{}（{}）", gz.display(), e))?;
+  Ok(text)
 }
 
 pub fn append_log_line(file: &mut fs::File, text: &str) -> Result<(), String> {
@@ -0,0 +1,342 @@
+//! 定时同步：按配置里的 `schedules` 到点自动跑一次同步。
+//!
+//! 规则（大白话）：
+//! - 每 30 秒检查一次；配置每次都从磁盘重新读，改了设置不用重启
+//! - 到点时走和“一键同步”同一条路径（sync_manager::spawn_run）
+//! - 到点时已有同步在跑：不排队，在历史里记一条“错过”
+//! - App 没开期间错过的时间点：合并记一条“错过”，并在打开后补跑最近的一次
+//! - 每个定时任务上次处理到哪个时间点（和当时的设置），记在 `~/.agentsync/schedule-state.json`
+//! - 停用期间不记；改了时间设置、或者重新启用，都从现在重新算，不把之前的时间点当成“错过”
+
+use crate::config::{self, AgentSyncConfig, ScheduleConfig};
+use crate::rclone::RunOptions;
+use crate::runs;
+use crate::sync_manager;
+use chrono::{Datelike, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const TICK: Duration = Duration::from_secs(30);
+const MINUTE_MS: u64 = 60 * 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleSpec {
+  /// 每隔固定时长（毫秒）
+  Every(u64),
+  /// 每周的某几天（周一 = 0）的固定时间（本机时区）
+  At { days: [bool; 7], hour: u32, minute: u32 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleStatus {
+  pub id: String,
+  pub name: String,
+  pub enabled: bool,
+  pub spec: String,
+  pub error: Option<String>,
+  /// 上次处理到的时间点（触发 / 记为错过 / 新建时的起点）
+  pub last_at_ms: Option<u64>,
+  pub next_at_ms: Option<u64>,
+}
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+fn now_ms() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_millis() as u64
+}
+
+fn parse_interval(text: &str) -> Result<u64, String> {
+  let text = text.trim();
+  let split = text
+    .find(|c: char| !c.is_ascii_digit())
+    .ok_or_else(|| format!("间隔缺少单位（m/h/d）：{}", text))?;
+  let (num, unit) = text.split_at(split);
+  let n: u64 = num
+    .parse()
+    .map_err(|_| format!("间隔数字无效：{}", text))?;
+  let unit_ms = match unit {
+    "m" | "min" | "mins" => MINUTE_MS,
+    "h" | "hr" | "hrs" => 60 * MINUTE_MS,
+    "d" | "day" | "days" => 24 * 60 * MINUTE_MS,
+    _ => return Err(format!("间隔单位无效（只支持 m/h/d）：{}", text)),
+  };
+  if n == 0 {
+    return Err("间隔不能为 0".to_string());
+  }
+  Ok(n * unit_ms)
+}
+
+fn parse_time(text: &str) -> Result<(u32, u32), String> {
+  let (h, m) = text
+    .split_once(':')
+    .ok_or_else(|| format!("时间格式应为 HH:MM：{}", text))?;
+  let hour: u32 = h.parse().map_err(|_| format!("时间格式应为 HH:MM：{}", text))?;
+  let minute: u32 = m.parse().map_err(|_| format!("时间格式应为 HH:MM：{}", text))?;
+  if hour > 23 || minute > 59 {
+    return Err(format!("时间超出范围：{}", text));
+  }
+  Ok((hour, minute))
+}
+
+fn parse_days(text: &str) -> Result<[bool; 7], String> {
+  match text {
+    "daily" | "everyday" => return Ok([true; 7]),
+    "weekdays" => return Ok([true, true, true, true, true, false, false]),
+    "weekends" => return Ok([false, false, false, false, false, true, true]),
+    _ => {}
+  }
+
+  let mut days = [false; 7];
+  for part in text.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+    let idx = match part {
+      "mon" => 0,
+      "tue" => 1,
+      "wed" => 2,
+      "thu" => 3,
+      "fri" => 4,
+      "sat" => 5,
+      "sun" => 6,
+      _ => return Err(format!("无法识别的星期：{}（可用 mon..sun / daily / weekdays / weekends）", part)),
+    };
+    days[idx] = true;
+  }
+  if !days.iter().any(|d| *d) {
+    return Err("至少要选一天".to_string());
+  }
+  Ok(days)
+}
+
+/// 支持：`every 30m` / `every 2h` / `every 1d` / `18:00` / `daily 18:00` / `weekdays 18:00` / `mon,wed 09:30`
+pub fn parse_spec(spec: &str) -> Result<ScheduleSpec, String> {
+  let lower = spec.trim().to_lowercase();
+  let parts: Vec<&str> = lower.split_whitespace().collect();
+  match parts.as_slice() {
+    ["every", interval] => Ok(ScheduleSpec::Every(parse_interval(interval)?)),
+    [time] => {
+      let (hour, minute) = parse_time(time)?;
+      Ok(ScheduleSpec::At {
+        days: [true; 7],
+        hour,
+        minute,
+      })
+    }
+    [days, time] => {
+      let days = parse_days(days)?;
+      let (hour, minute) = parse_time(time)?;
+      Ok(ScheduleSpec::At { days, hour, minute })
+    }
+    _ => Err(format!("无法识别的定时规则：{}", spec)),
+  }
+}
+
+fn local_date(ms: u64) -> Option<NaiveDate> {
+  Local
+    .timestamp_millis_opt(ms as i64)
+    .earliest()
+    .map(|d| d.date_naive())
+}
+
+fn local_at(date: NaiveDate, hour: u32, minute: u32) -> Option<u64> {
+  date
+    .and_hms_opt(hour, minute, 0)?
+    .and_local_timezone(Local)
+    .earliest()
+    .map(|d| d.timestamp_millis().max(0) as u64)
+}
+
+/// `(after, until]` 之间到点的时间点：第一个、最后一个、一共几个。
+/// 不一个个列出来：`every` 直接算，隔了很久（比如每分钟一次、关了半年）也不会攒出几十万个。
+fn due_slots(spec: &ScheduleSpec, after_ms: u64, until_ms: u64) -> Option<(u64, u64, u64)> {
+  match spec {
+    ScheduleSpec::Every(interval) => {
+      let count = until_ms.saturating_sub(after_ms) / (*interval).max(1);
+      (count > 0).then(|| (after_ms + interval, after_ms + count * interval, count))
+    }
+    ScheduleSpec::At { days, hour, minute } => {
+      let (mut date, end) = (local_date(after_ms)?, local_date(until_ms)?);
+      let mut found: Option<(u64, u64, u64)> = None;
+      while date <= end {
+        if days[date.weekday().num_days_from_monday() as usize] {
+          if let Some(t) = local_at(date, *hour, *minute).filter(|t| *t > after_ms && *t <= until_ms) {
+            found = Some(match found {
+              Some((first, _, count)) => (first, t, count + 1),
+              None => (t, t, 1),
+            });
+          }
+        }
+        date = date.succ_opt()?;
+      }
+      found
+    }
+  }
+}
+
+fn next_slot(spec: &ScheduleSpec, last_ms: Option<u64>, now: u64) -> Option<u64> {
+  match spec {
+    ScheduleSpec::Every(interval) => Some(last_ms.unwrap_or(now).saturating_add(*interval).max(now)),
+    ScheduleSpec::At { .. } => due_slots(spec, now, now + 8 * 24 * 60 * MINUTE_MS).map(|(first, _, _)| first),
+  }
+}
+
+/// 一个定时任务处理到哪了
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SlotState {
+  last_ms: u64,
+  /// 记下时的时间设置；和现在的不一样说明改过，要从现在重新算（老版本没记，为空）
+  #[serde(default)]
+  spec: Option<String>,
+}
+
+fn load_state() -> BTreeMap<String, SlotState> {
+  let raw: BTreeMap<String, serde_json::Value> = config::schedule_state_file()
+    .ok()
+    .and_then(|p| fs::read_to_string(p).ok())
+    .and_then(|text| serde_json::from_str(&text).ok())
+    .unwrap_or_default();
+  raw
+    .into_iter()
+    .filter_map(|(id, v)| {
+      // 老格式只记了时间点
+      let state = match v.as_u64() {
+        Some(last_ms) => SlotState { last_ms, spec: None },
+        None => serde_json::from_value(v).ok()?,
+      };
+      Some((id, state))
+    })
+    .collect()
+}
+
+fn save_state(state: &BTreeMap<String, SlotState>) -> Result<(), String> {
+  config::ensure_dir(&config::agentsync_dir()?)?;
+  let path = config::schedule_state_file()?;
+  let text =
+    serde_json::to_string_pretty(state).map_err(|e| format!("序列化定时状态失败（{}）", e))?;
+  fs::write(&path, text).map_err(|e| format!("写入定时状态失败：{}（{}）", path.display(), e))
+}
+
+fn trigger_for(s: &ScheduleConfig) -> String {
+  format!("{}{}", runs::TRIGGER_SCHEDULE_PREFIX, s.id)
+}
+
+fn fire(cfg: &AgentSyncConfig, s: &ScheduleConfig, slot_ms: u64) {
+  let trigger = trigger_for(s);
+  if sync_manager::is_running() {
+    let _ = runs::append_missed(&trigger, slot_ms, "到点时已有同步在运行，本次定时同步已跳过");
+    return;
+  }
+
+  let opts = RunOptions {
+    only: if s.item_ids.is_empty() {
+      None
+    } else {
+      Some(s.item_ids.clone())
+    },
+    trigger: Some(trigger.clone()),
+    on_drift: None,
+  };
+  if let Err(e) = sync_manager::spawn_run(cfg.clone(), opts) {
+    let _ = runs::append_missed(&trigger, slot_ms, &format!("定时同步没能启动：{}", e));
+  }
+}
+
+fn tick(now: u64) -> Result<(), String> {
+  let cfg = config::load_or_default()?;
+  let mut state = load_state();
+  let before = state.clone();
+
+  // 删掉的、停用的定时任务不保留状态：重新启用时从那一刻算起
+  state.retain(|id, _| cfg.schedules.iter().any(|s| &s.id == id && s.enabled));
+
+  for s in cfg.schedules.iter().filter(|s| s.enabled) {
+    let Ok(spec) = parse_spec(&s.spec) else {
+      continue;
+    };
+    let anchor = SlotState {
+      last_ms: now,
+      spec: Some(s.spec.trim().to_string()),
+    };
+    let last = match state.get(&s.id) {
+      // 新加的、刚启用的、改了时间设置的：从现在开始算，不补跑过去的时间点
+      None => {
+        state.insert(s.id.clone(), anchor);
+        continue;
+      }
+      Some(old) if old.spec.as_deref().is_some_and(|o| o != s.spec.trim()) => {
+        state.insert(s.id.clone(), anchor);
+        continue;
+      }
+      Some(old) => old.last_ms,
+    };
+
+    let Some((first, latest, count)) = due_slots(&spec, last, now) else {
+      continue;
+    };
+    if count > 1 {
+      let note = format!("App 未运行期间错过了 {} 次定时同步，已补跑最近一次", count - 1);
+      let _ = runs::append_missed(&trigger_for(s), first, &note);
+    }
+
+    fire(&cfg, s, latest);
+    state.insert(
+      s.id.clone(),
+      SlotState {
+        last_ms: latest,
+        spec: anchor.spec,
+      },
+    );
+  }
+
+  if state != before {
+    save_state(&state)?;
+  }
+  Ok(())
+}
+
+/// 启动定时检查线程（重复调用只会启动一次）。
+pub fn start() {
+  if STARTED.swap(true, Ordering::SeqCst) {
+    return;
+  }
+  std::thread::spawn(|| loop {
+    let _ = tick(now_ms());
+    std::thread::sleep(TICK);
+  });
+}
+
+pub fn status() -> Result<Vec<ScheduleStatus>, String> {
+  let cfg = config::load_or_default()?;
+  let state = load_state();
+  let now = now_ms();
+
+  Ok(
+    cfg
+      .schedules
+      .iter()
+      .map(|s| {
+        let parsed = parse_spec(&s.spec);
+        let last_at_ms = state.get(&s.id).map(|st| st.last_ms);
+        let next_at_ms = match (&parsed, s.enabled) {
+          (Ok(spec), true) => next_slot(spec, last_at_ms, now),
+          _ => None,
+        };
+        ScheduleStatus {
+          id: s.id.clone(),
+          name: s.name.clone(),
+          enabled: s.enabled,
+          spec: s.spec.clone(),
+          error: parsed.err(),
+          last_at_ms,
+          next_at_ms,
+        }
+      })
+      .collect(),
+  )
+}
@@ -0,0 +1,263 @@
+//! 历史统计：在历史记录（`runs::list_runs`）上算一些汇总，帮忙发现“某个项目已经悄悄失败一周了”。
+//!
+//! 口径（大白话）：
+//! - 错过的定时（`missed`）没真的跑，单独计数，不算进成功率和平均用时
+//! - 每一项：跑了几次、成功几次、传了多少字节、最后一次成功是什么时候、最近连续失败了几次
+//! - 每个目标（`user@host:port`）：成功率、平均用时；旧记录没有目标的归到 `unknown`
+//! - 按天（本机时区）的次数和字节数，看趋势
+//! - 和上一次比：每一项最近一次的结果和它的上一次不一样的（刚开始失败 / 刚恢复）
+
+use crate::runs::{self, RunStatus, SyncRunSummary};
+use chrono::{Local, TimeZone};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemStats {
+  pub item_id: String,
+  pub label: String,
+  pub runs: u32,
+  pub ok: u32,
+  pub failed: u32,
+  /// 0 ~ 1
+  pub success_rate: f64,
+  pub bytes: u64,
+  pub last_ok_at_ms: Option<u64>,
+  /// 距离最后一次成功过了多久；从没成功过为空
+  pub since_last_ok_ms: Option<u64>,
+  /// 最近连续失败的次数（最近一次成功了就是 0）
+  pub consecutive_failures: u32,
+  /// 最近一次失败的说明
+  pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetStats {
+  pub target: String,
+  pub runs: u32,
+  pub ok: u32,
+  pub success_rate: f64,
+  pub avg_duration_ms: u64,
+  pub last_ok_at_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DayStats {
+  /// 本机时区的日期：`2026-01-31`
+  pub day: String,
+  pub runs: u32,
+  pub ok: u32,
+  pub bytes: u64,
+}
+
+/// 这一项最近一次的结果和它的上一次不一样。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemChange {
+  pub item_id: String,
+  pub label: String,
+  pub run_id: String,
+  pub at_ms: u64,
+  pub ok: bool,
+  pub previous_ok: bool,
+  pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryStats {
+  /// 只统计这个时间之后开始的运行；为空表示全部
+  pub since_ms: Option<u64>,
+  pub runs: u32,
+  pub ok_runs: u32,
+  pub missed: u32,
+  pub success_rate: f64,
+  pub avg_duration_ms: u64,
+  pub total_bytes: u64,
+  /// 失败次数最多的项（没有失败就为空）
+  pub most_failing_item_id: Option<String>,
+  /// 按最后一次成功的时间排，很久没成功的在前
+  pub items: Vec<ItemStats>,
+  pub targets: Vec<TargetStats>,
+  /// 按日期从早到晚
+  pub daily: Vec<DayStats>,
+  /// 最近一次结果和上一次不一样的项（刚开始失败 / 刚恢复）
+  pub changes: Vec<ItemChange>,
+}
+
+fn now_ms() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_millis() as u64
+}
+
+fn rate(ok: u32, total: u32) -> f64 {
+  if total == 0 {
+    0.0
+  } else {
+    ok as f64 / total as f64
+  }
+}
+
+fn day_of(ms: u64) -> String {
+  Local
+    .timestamp_millis_opt(ms as i64)
+    .single()
+    .map(|t| t.format("%Y-%m-%d").to_string())
+    .unwrap_or_default()
+}
+
+fn duration_ms(r: &SyncRunSummary) -> u64 {
+  r.ended_at_ms.saturating_sub(r.started_at_ms)
+}
+
+/// 统计最近 `days` 天（为空则全部）的历史。
+pub fn history_stats(days: Option<u32>) -> Result<HistoryStats, String> {
+  let now = now_ms();
+  let since_ms = days.map(|d| now.saturating_sub(d as u64 * 24 * 3600 * 1000));
+  // list_runs 是新的在前；这里倒过来按时间顺序累计，“连续失败”之类的才好算
+  let mut list: Vec<SyncRunSummary> = runs::list_runs()?
+    .into_iter()
+    .filter(|r| since_ms.is_none_or(|s| r.started_at_ms >= s))
+    .collect();
+  list.reverse();
+
+  let missed = list.iter().filter(|r| r.status == RunStatus::Missed).count() as u32;
+  let ran: Vec<&SyncRunSummary> = list.iter().filter(|r| r.status != RunStatus::Missed).collect();
+  let ok_runs = ran.iter().filter(|r| r.ok).count() as u32;
+  let total_duration: u64 = ran.iter().map(|r| duration_ms(r)).sum();
+
+  let mut items: BTreeMap<String, ItemStats> = BTreeMap::new();
+  let mut targets: BTreeMap<String, (TargetStats, u64)> = BTreeMap::new();
+  let mut daily: BTreeMap<String, DayStats> = BTreeMap::new();
+  // 每一项最近两次的结果，用来找“变了”的项
+  let mut latest: BTreeMap<String, (Option<bool>, ItemChange)> = BTreeMap::new();
+
+  for r in &ran {
+    let run_bytes: u64 = r.items.iter().map(|i| i.bytes).sum();
+
+    let day = daily.entry(day_of(r.started_at_ms)).or_insert_with_key(|day| DayStats {
+      day: day.clone(),
+      runs: 0,
+      ok: 0,
+      bytes: 0,
+    });
+    day.runs += 1;
+    day.ok += r.ok as u32;
+    day.bytes += run_bytes;
+
+    let target = r.target.clone().unwrap_or_else(|| "unknown".to_string());
+    let (t, duration) = targets.entry(target).or_insert_with_key(|target| {
+      (
+        TargetStats {
+          target: target.clone(),
+          runs: 0,
+          ok: 0,
+          success_rate: 0.0,
+          avg_duration_ms: 0,
+          last_ok_at_ms: None,
+        },
+        0,
+      )
+    });
+    t.runs += 1;
+    *duration += duration_ms(r);
+    if r.ok {
+      t.ok += 1;
+      t.last_ok_at_ms = Some(r.ended_at_ms);
+    }
+
+    for i in r.items.iter().filter(|i| !i.item_id.is_empty()) {
+      let s = items.entry(i.item_id.clone()).or_insert_with(|| ItemStats {
+        item_id: i.item_id.clone(),
+        label: i.label.clone(),
+        runs: 0,
+        ok: 0,
+        failed: 0,
+        success_rate: 0.0,
+        bytes: 0,
+        last_ok_at_ms: None,
+        since_last_ok_ms: None,
+        consecutive_failures: 0,
+        last_error: None,
+      });
+      s.label = i.label.clone();
+      s.runs += 1;
+      s.bytes += i.bytes;
+      if i.ok {
+        s.ok += 1;
+        s.last_ok_at_ms = Some(r.ended_at_ms);
+        s.consecutive_failures = 0;
+      } else {
+        s.failed += 1;
+        s.consecutive_failures += 1;
+        s.last_error = Some(i.message.clone());
+      }
+
+      let prev = latest.get(&i.item_id).map(|(_, last)| last.ok);
+      let current = ItemChange {
+        item_id: i.item_id.clone(),
+        label: i.label.clone(),
+        run_id: r.run_id.clone(),
+        at_ms: r.started_at_ms,
+        ok: i.ok,
+        previous_ok: prev.unwrap_or(i.ok),
+        message: i.message.clone(),
+      };
+      latest.insert(i.item_id.clone(), (prev, current));
+    }
+  }
+
+  let mut changes: Vec<ItemChange> = latest
+    .into_values()
+    .filter(|(prev, last)| prev.is_some_and(|p| p != last.ok))
+    .map(|(_, last)| last)
+    .collect();
+  changes.sort_by_key(|c| std::cmp::Reverse(c.at_ms));
+
+  let mut items: Vec<ItemStats> = items
+    .into_values()
+    .map(|mut s| {
+      s.success_rate = rate(s.ok, s.runs);
+      s.since_last_ok_ms = s.last_ok_at_ms.map(|t| now.saturating_sub(t));
+      s
+    })
+    .collect();
+  // 从没成功过的排最前，其余按最后一次成功从早到晚
+  items.sort_by_key(|s| s.last_ok_at_ms.unwrap_or(0));
+
+  let most_failing_item_id = items
+    .iter()
+    .filter(|s| s.failed > 0)
+    .max_by_key(|s| s.failed)
+    .map(|s| s.item_id.clone());
+
+  let targets = targets
+    .into_values()
+    .map(|(mut t, duration)| {
+      t.success_rate = rate(t.ok, t.runs);
+      t.avg_duration_ms = if t.runs == 0 { 0 } else { duration / t.runs as u64 };
+      t
+    })
+    .collect();
+
+  Ok(HistoryStats {
+    since_ms,
+    runs: ran.len() as u32,
+    ok_runs,
+    missed,
+    success_rate: rate(ok_runs, ran.len() as u32),
+    avg_duration_ms: if ran.is_empty() { 0 } else { total_duration / ran.len() as u64 },
+    total_bytes: ran.iter().flat_map(|r| r.items.iter()).map(|i| i.bytes).sum(),
+    most_failing_item_id,
+    items,
+    targets,
+    daily: daily.into_values().collect(),
+    changes,
+  })
+}
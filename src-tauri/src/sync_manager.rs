@@ -1,11 +1,15 @@
 //! 同步任务状态管理（用于“显示进度 / 切换页面不影响同步”）。
 
+use crate::config::AgentSyncConfig;
+use crate::rclone::{self, RunOptions};
 use crate::runs::{SyncItemResult, SyncRunSummary};
 use once_cell::sync::Lazy;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SyncStatus {
   pub running: bool,
@@ -40,6 +44,13 @@ struct SyncRunState {
 
 static STATE: Lazy<Mutex<SyncRunState>> = Lazy::new(|| Mutex::new(SyncRunState::default()));
 
+fn now_ms() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_millis() as u64
+}
+
 fn lock_state() -> Result<std::sync::MutexGuard<'static, SyncRunState>, String> {
   STATE
     .lock()
@@ -51,6 +62,8 @@ pub fn start_run(run_id: String, started_at_ms: u64, total_items: u32) -> Result
   if s.running {
     return Err("已有同步任务在运行中，请稍后再试。".to_string());
   }
+  // 状态一标成“在跑”就能取消了：旧的取消请求在这里（拿着锁）清掉，而不是等后台线程跑起来再清
+  rclone::reset_cancel();
   *s = SyncRunState {
     running: true,
     run_id: Some(run_id),
@@ -144,3 +157,61 @@ pub fn get_status() -> Result<SyncStatus, String> {
     summary: s.summary.clone(),
   })
 }
+
+struct Progress;
+
+impl rclone::SyncProgress for Progress {
+  fn on_item_start(&mut self, label: &str) {
+    let _ = set_current_label(label.to_string());
+  }
+
+  fn on_line(&mut self, line: &str) {
+    let _ = push_line(line.to_string());
+  }
+
+  fn on_item_done(&mut self, result: &SyncItemResult) {
+    let _ = push_item_result(result.clone());
+  }
+}
+
+/// 在后台线程启动一次同步，立即返回 run_id。
+/// “一键同步”、监听模式、定时同步都走这里，所以同一时间最多只有一个同步在跑。
+pub fn spawn_run(config: AgentSyncConfig, opts: RunOptions) -> Result<String, String> {
+  rclone::validate_for_run(&config)?;
+  let run_id = Uuid::new_v4().simple().to_string();
+  let total = rclone::estimate_total_items(&config, opts.only.as_deref())?;
+  start_run(run_id.clone(), now_ms(), total)?;
+
+  std::thread::spawn({
+    let run_id = run_id.clone();
+    move || {
+      let mut progress = Progress;
+      let result = rclone::run_sync_with_id(&config, run_id.clone(), &opts, &mut progress);
+
+      match result {
+        Ok(summary) => {
+          let _ = finish_ok(summary);
+        }
+        Err(e) => {
+          let _ = finish_err(run_id, now_ms(), e);
+        }
+      }
+    }
+  });
+
+  Ok(run_id)
+}
+
+/// 取消正在跑的同步：当前项的 rclone 会被结束，后面的项标记为“已取消”，历史照常写入。
+pub fn cancel() -> Result<(), String> {
+  if !is_running() {
+    return Err("当前没有正在运行的同步".to_string());
+  }
+  rclone::request_cancel();
+  let _ = set_current_label("正在取消…".to_string());
+  Ok(())
+}
+
+pub fn is_running() -> bool {
+  lock_state().map(|s| s.running).unwrap_or(false)
+}
@@ -0,0 +1,236 @@
+//! 系统托盘 + 桌面通知（只在界面进程里用）。
+//!
+//! 大白话：
+//! - 托盘图标三种状态：空闲（原色）/ 同步中（偏蓝）/ 上次失败（偏红），悬停提示里也写着
+//! - 托盘菜单：立即同步 / 取消同步 / 打开最近一次日志 / 显示窗口 / 退出
+//! - 每 2 秒问一次守护进程的同步状态；一次同步结束（不管是界面、定时还是监听模式触发的）就弹系统通知
+//! - 关窗口只是藏到托盘里，后台同步和通知照常
+
+use crate::daemon;
+use crate::runs::{self, SyncRunSummary};
+use crate::sync_manager::SyncStatus;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tauri::image::Image;
+use tauri::menu::{Menu, MenuItem, PredefinedMenuItem};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_notification::NotificationExt;
+use tauri_plugin_opener::OpenerExt;
+
+const TRAY_ID: &str = "main";
+const POLL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TrayState {
+  Idle,
+  Syncing,
+  Failed,
+}
+
+struct TrayIcons {
+  idle: Image<'static>,
+  syncing: Image<'static>,
+  failed: Image<'static>,
+}
+
+/// 把图标往某个颜色上染一半，用来区分状态（不用额外带图标文件）。
+fn tint(base: &Image<'_>, rgb: [u8; 3]) -> Image<'static> {
+  let mut rgba = base.rgba().to_vec();
+  for px in rgba.chunks_exact_mut(4) {
+    for (c, t) in px.iter_mut().zip(rgb) {
+      *c = ((*c as u16 + t as u16) / 2) as u8;
+    }
+  }
+  Image::new(&rgba, base.width(), base.height()).to_owned()
+}
+
+fn trigger_text(trigger: Option<&str>) -> &'static str {
+  match trigger {
+    Some(runs::TRIGGER_WATCH) => "监听模式",
+    Some(runs::TRIGGER_CLI) => "命令行",
+    Some(t) if t.starts_with(runs::TRIGGER_SCHEDULE_PREFIX) => "定时同步",
+    Some(t) if t.starts_with(runs::TRIGGER_RESUME_PREFIX) => "续跑",
+    _ => "同步",
+  }
+}
+
+fn notify_finished(app: &AppHandle, st: &SyncStatus) {
+  let (title, body) = match &st.summary {
+    Some(s) => summary_text(s),
+    None => (
+      "AgentSync：同步失败".to_string(),
+      st.error.clone().unwrap_or_else(|| "原因未知，请查看日志".to_string()),
+    ),
+  };
+  let _ = app.notification().builder().title(title).body(body).show();
+}
+
+fn summary_text(s: &SyncRunSummary) -> (String, String) {
+  let what = trigger_text(s.trigger.as_deref());
+  let secs = s.ended_at_ms.saturating_sub(s.started_at_ms) / 1000;
+  if s.ok {
+    return (
+      format!("AgentSync：{}完成", what),
+      format!("{} 项全部成功，用时 {} 秒", s.items.len(), secs),
+    );
+  }
+  let failed: Vec<&str> = s
+    .items
+    .iter()
+    .filter(|i| !i.ok)
+    .map(|i| i.label.as_str())
+    .collect();
+  let body = if failed.is_empty() {
+    s.note.clone().unwrap_or_else(|| "同步前后命令失败，请查看日志".to_string())
+  } else {
+    format!("失败的项：{}", failed.join("、"))
+  };
+  (format!("AgentSync：{}失败", what), body)
+}
+
+fn show_main_window(app: &AppHandle) {
+  if let Some(w) = app.get_webview_window("main") {
+    let _ = w.show();
+    let _ = w.unminimize();
+    let _ = w.set_focus();
+  }
+}
+
+fn open_last_log(app: &AppHandle) {
+  let last = runs::list_runs()
+    .ok()
+    .and_then(|list| list.into_iter().find(|r| !r.log_path.is_empty()));
+  match last {
+    Some(r) => {
+      let _ = app.opener().open_path(r.log_path, None::<&str>);
+    }
+    None => {
+      let _ = app
+        .notification()
+        .builder()
+        .title("AgentSync")
+        .body("还没有同步日志")
+        .show();
+    }
+  }
+}
+
+fn on_menu(app: &AppHandle, id: &str) {
+  match id {
+    "sync_now" => {
+      let app = app.clone();
+      std::thread::spawn(move || {
+        let params = json!({ "trigger": runs::TRIGGER_MANUAL });
+        if let Err(e) = crate::daemon_call::<Value>("sync_start", params) {
+          let _ = app
+            .notification()
+            .builder()
+            .title("AgentSync：没能开始同步")
+            .body(e)
+            .show();
+        }
+      });
+    }
+    "cancel" => {
+      std::thread::spawn(|| {
+        let _ = daemon::request("sync_cancel", Value::Null);
+      });
+    }
+    "open_log" => open_last_log(app),
+    "show" => show_main_window(app),
+    "quit" => app.exit(0),
+    _ => {}
+  }
+}
+
+pub fn setup(app: &tauri::App) -> tauri::Result<()> {
+  let status_i = MenuItem::with_id(app, "status", "AgentSync：空闲", false, None::<&str>)?;
+  let sync_i = MenuItem::with_id(app, "sync_now", "立即同步", true, None::<&str>)?;
+  let cancel_i = MenuItem::with_id(app, "cancel", "取消同步", false, None::<&str>)?;
+  let log_i = MenuItem::with_id(app, "open_log", "打开最近一次日志", true, None::<&str>)?;
+  let show_i = MenuItem::with_id(app, "show", "显示窗口", true, None::<&str>)?;
+  let quit_i = MenuItem::with_id(app, "quit", "退出", true, None::<&str>)?;
+  let menu = Menu::with_items(
+    app,
+    &[
+      &status_i,
+      &PredefinedMenuItem::separator(app)?,
+      &sync_i,
+      &cancel_i,
+      &log_i,
+      &PredefinedMenuItem::separator(app)?,
+      &show_i,
+      &quit_i,
+    ],
+  )?;
+
+  let Some(base) = app.default_window_icon().cloned() else {
+    return Ok(());
+  };
+  let icons = TrayIcons {
+    syncing: tint(&base, [79, 70, 229]),
+    failed: tint(&base, [225, 29, 72]),
+    idle: base.to_owned(),
+  };
+
+  TrayIconBuilder::with_id(TRAY_ID)
+    .icon(icons.idle.clone())
+    .tooltip("AgentSync：空闲")
+    .menu(&menu)
+    .on_menu_event(|app, event| on_menu(app, event.id.as_ref()))
+    .build(app)?;
+
+  let app = app.handle().clone();
+  std::thread::spawn(move || {
+    let mut state = TrayState::Idle;
+    // 打开界面前就已经结束的那次同步不再通知
+    let mut notified: Option<String> = None;
+    let mut first = true;
+
+    loop {
+      let st = daemon::request("sync_status", Value::Null)
+        .ok()
+        .and_then(|v| serde_json::from_value::<SyncStatus>(v).ok());
+      if let Some(st) = st {
+        if !st.running {
+          if let Some(run_id) = st.run_id.clone() {
+            if notified.as_deref() != Some(run_id.as_str()) {
+              if !first {
+                notify_finished(&app, &st);
+              }
+              notified = Some(run_id);
+            }
+          }
+        }
+        first = false;
+
+        let next = if st.running {
+          TrayState::Syncing
+        } else if st.ok == Some(false) {
+          TrayState::Failed
+        } else {
+          TrayState::Idle
+        };
+        if next != state {
+          state = next;
+          let (icon, text) = match state {
+            TrayState::Idle => (&icons.idle, "AgentSync：空闲"),
+            TrayState::Syncing => (&icons.syncing, "AgentSync：同步中…"),
+            TrayState::Failed => (&icons.failed, "AgentSync：上次同步失败"),
+          };
+          if let Some(tray) = app.tray_by_id(TRAY_ID) {
+            let _ = tray.set_icon(Some(icon.clone()));
+            let _ = tray.set_tooltip(Some(text));
+          }
+          let _ = status_i.set_text(text);
+          let _ = sync_i.set_enabled(state != TrayState::Syncing);
+          let _ = cancel_i.set_enabled(state == TrayState::Syncing);
+        }
+      }
+      std::thread::sleep(POLL);
+    }
+  });
+
+  Ok(())
+}
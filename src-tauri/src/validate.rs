@@ -0,0 +1,335 @@
+//! 配置检查：一次列出所有问题（带 JSON 路径，界面能定位到具体字段），不再“遇到第一个错就停”。
+//!
+//! 两个级别（大白话）：
+//! - `error`：同步前就会被拦下（`validate_for_run` 只看这一级），例如两个项目会互相覆盖、排除规则写错
+//! - `warning`：能同步，但结果可能不是你想要的，例如某个项目目录不存在（这一项会失败）、项目套着项目
+//!
+//! 测试连接、校验、列备份这些不看项目的操作只查连接和目标端字段（`validate_connection`），
+//! 改项目改到一半也能测连接。
+//!
+//! 路径里的变量（`${HOME}`、`${env:...}`、自定义变量）先展开再检查；展开不了的字段直接报 `error`。
+
+use crate::config::AgentSyncConfig;
+use crate::excludes::ExcludeMatcher;
+use crate::rclone;
+use crate::scheduler;
+use crate::vars;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+  Error,
+  Warning,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigProblem {
+  /// 出问题的字段，例如 `projects[2].remoteDirName`、`excludes[0]`
+  pub path: String,
+  pub severity: Severity,
+  pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationReport {
+  /// 没有 `error` 级别的问题
+  pub ok: bool,
+  pub problems: Vec<ConfigProblem>,
+}
+
+#[derive(Default)]
+struct Problems(Vec<ConfigProblem>);
+
+impl Problems {
+  fn error(&mut self, path: impl Into<String>, message: impl Into<String>) {
+    self.push(path, Severity::Error, message);
+  }
+
+  fn warning(&mut self, path: impl Into<String>, message: impl Into<String>) {
+    self.push(path, Severity::Warning, message);
+  }
+
+  fn push(&mut self, path: impl Into<String>, severity: Severity, message: impl Into<String>) {
+    self.0.push(ConfigProblem {
+      path: path.into(),
+      severity,
+      message: message.into(),
+    });
+  }
+
+  fn required(&mut self, path: &str, label: &str, value: &str) -> bool {
+    let ok = !value.trim().is_empty();
+    if !ok {
+      self.error(path, format!("{}不能为空", label));
+    }
+    ok
+  }
+}
+
+/// 目标端路径按 `/` 拆开（`~/`、`\`、多余的 `/` 都去掉）。
+fn remote_segments(path: &str) -> Vec<String> {
+  rclone::normalize_remote_path(path)
+    .split('/')
+    .filter(|s| !s.is_empty() && *s != ".")
+    .map(str::to_string)
+    .collect()
+}
+
+fn escapes(path: &str) -> bool {
+  remote_segments(path).iter().any(|s| s == "..")
+}
+
+/// `child` 是不是 `parent` 本身或者在它下面（都是目标端路径）。
+fn remote_inside(child: &str, parent: &str) -> bool {
+  let (c, p) = (remote_segments(child), remote_segments(parent));
+  !p.is_empty() && c.starts_with(&p)
+}
+
+/// 本机路径尽量解析成真实路径（符号链接、`..`），不存在就按原样比较。
+fn local_key(path: &str) -> PathBuf {
+  let p = Path::new(path.trim());
+  fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf())
+}
+
+fn check_connection(cfg: &AgentSyncConfig, out: &mut Problems) {
+  let c = &cfg.connection;
+  out.required("connection.host", "Host", &c.host);
+  out.required("connection.user", "User", &c.user);
+  if c.port == 0 {
+    out.error("connection.port", "端口不能是 0");
+  }
+  if !out.required("connection.keyPath", "SSH 私钥路径", &c.key_path) {
+    return;
+  }
+  let key = Path::new(&c.key_path);
+  if !key.is_file() {
+    out.error("connection.keyPath", format!("SSH 私钥文件不存在：{}", c.key_path));
+    return;
+  }
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    if let Ok(meta) = fs::metadata(key) {
+      let mode = meta.permissions().mode() & 0o777;
+      if mode & 0o077 != 0 {
+        out.warning(
+          "connection.keyPath",
+          format!(
+            "SSH 私钥文件权限太宽（{:o}），别人也能读；ssh 会拒绝使用这种私钥，建议 chmod 600 {}",
+            mode, c.key_path
+          ),
+        );
+      }
+    }
+  }
+}
+
+fn check_remote(cfg: &AgentSyncConfig, out: &mut Problems) {
+  let r = &cfg.remote;
+  let projects_ok = out.required("remote.projectsRoot", "目标端项目根目录", &r.projects_root);
+  let backup_ok = out.required("remote.backupRoot", "备份根目录", &r.backup_root);
+  for (path, value) in [("remote.projectsRoot", &r.projects_root), ("remote.backupRoot", &r.backup_root)] {
+    if escapes(value) {
+      out.error(path, format!("目标端路径里不能有 `..`：{}", value));
+    }
+  }
+  if !(projects_ok && backup_ok) {
+    return;
+  }
+  if remote_inside(&r.backup_root, &r.projects_root) {
+    out.error(
+      "remote.backupRoot",
+      format!(
+        "备份根目录不能放在项目根目录里面（{} 在 {} 下），否则镜像删除会把备份也当成多余文件处理",
+        r.backup_root, r.projects_root
+      ),
+    );
+  } else if remote_inside(&r.projects_root, &r.backup_root) {
+    out.error(
+      "remote.projectsRoot",
+      format!("项目根目录不能放在备份根目录里面（{} 在 {} 下）", r.projects_root, r.backup_root),
+    );
+  }
+}
+
+fn check_projects(cfg: &AgentSyncConfig, out: &mut Problems) {
+  let mut ids: BTreeMap<&str, usize> = BTreeMap::new();
+  let mut dir_names: BTreeMap<String, usize> = BTreeMap::new();
+  let mut locals: Vec<(usize, PathBuf)> = Vec::new();
+  let mut remotes: Vec<(usize, Vec<String>)> = Vec::new();
+
+  for (i, p) in cfg.projects.iter().enumerate() {
+    let at = |field: &str| format!("projects[{}].{}", i, field);
+
+    if out.required(&at("id"), "项目 id", &p.id) {
+      if let Some(first) = ids.insert(p.id.as_str(), i) {
+        out.error(at("id"), format!("项目 id 和 projects[{}] 重复：{}", first, p.id));
+      }
+    }
+
+    // 目标端目录名：不能为空、不能跳出项目根目录、不能和别的项目重复（会互相覆盖）
+    if out.required(&at("remoteDirName"), "目标端目录名", &p.remote_dir_name) {
+      if escapes(&p.remote_dir_name) {
+        out.error(at("remoteDirName"), format!("目标端目录名里不能有 `..`：{}", p.remote_dir_name));
+      }
+      let segments = remote_segments(&p.remote_dir_name);
+      let key = segments.join("/");
+      remotes.push((i, segments));
+      match dir_names.get(&key) {
+        Some(&first) => {
+          let other = &cfg.projects[first];
+          let message = format!(
+            "目标端目录名和 projects[{}]（{}）重复：{}，两个项目会互相覆盖",
+            first, other.name, p.remote_dir_name
+          );
+          // 有一个没启用时暂时不会出事，先提醒
+          if p.enabled && other.enabled {
+            out.error(at("remoteDirName"), message);
+          } else {
+            out.warning(at("remoteDirName"), message);
+          }
+          // 后面再重复时优先和启用的那个比
+          if p.enabled && !other.enabled {
+            dir_names.insert(key, i);
+          }
+        }
+        None => {
+          dir_names.insert(key, i);
+        }
+      }
+    }
+
+    if !out.required(&at("localPath"), "本机目录", &p.local_path) {
+      continue;
+    }
+    let local = Path::new(p.local_path.trim());
+    if !local.exists() {
+      let message = format!("本机目录不存在：{}", p.local_path);
+      if p.enabled {
+        out.warning(at("localPath"), format!("{}（同步时这一项会失败）", message));
+      } else {
+        out.warning(at("localPath"), message);
+      }
+      continue;
+    }
+    if !local.is_dir() {
+      out.error(at("localPath"), format!("本机路径不是文件夹：{}", p.local_path));
+      continue;
+    }
+    locals.push((i, local_key(&p.local_path)));
+  }
+
+  // 目标端目录套目录（`app` 和 `app/sub`）：外面的项目镜像删除时会把里面那个项目的文件当成多余的删掉
+  for (i, a) in &remotes {
+    for (j, b) in &remotes {
+      if i == j || a.len() <= b.len() || !a.starts_with(b) {
+        continue;
+      }
+      let (mine, other) = (&cfg.projects[*i], &cfg.projects[*j]);
+      let message = format!(
+        "目标端目录 {} 在 projects[{}]（{}）的目标端目录 {} 里面，两个项目会互相覆盖 / 删掉对方的文件",
+        mine.remote_dir_name, j, other.name, other.remote_dir_name
+      );
+      let at = format!("projects[{}].remoteDirName", i);
+      if mine.enabled && other.enabled {
+        out.error(at, message);
+      } else {
+        out.warning(at, message);
+      }
+    }
+  }
+
+  // 项目套项目：里面那个会被同步两次（外面的项目也会带上它）
+  for (i, a) in &locals {
+    for (j, b) in &locals {
+      if i == j {
+        continue;
+      }
+      if a == b {
+        if i > j {
+          out.warning(
+            format!("projects[{}].localPath", i),
+            format!("和 projects[{}] 是同一个本机目录：{}", j, a.display()),
+          );
+        }
+      } else if a.starts_with(b) {
+        out.warning(
+          format!("projects[{}].localPath", i),
+          format!(
+            "在 projects[{}]（{}）的目录里面，会被同步两次：{}",
+            j,
+            cfg.projects[*j].name,
+            a.display()
+          ),
+        );
+      }
+    }
+  }
+}
+
+fn check_excludes(cfg: &AgentSyncConfig, out: &mut Problems) {
+  for (i, pattern) in cfg.excludes.iter().enumerate() {
+    if let Err(e) = ExcludeMatcher::new(std::slice::from_ref(pattern)) {
+      out.error(format!("excludes[{}]", i), e);
+    }
+  }
+}
+
+fn check_schedules(cfg: &AgentSyncConfig, out: &mut Problems) {
+  for (i, s) in cfg.schedules.iter().enumerate() {
+    if let Err(e) = scheduler::parse_spec(&s.spec) {
+      let at = format!("schedules[{}].spec", i);
+      if s.enabled {
+        out.error(at, e);
+      } else {
+        out.warning(at, e);
+      }
+    }
+  }
+}
+
+/// 检查整份配置，列出所有问题（`error` 在前）。
+pub fn validate(cfg: &AgentSyncConfig) -> ValidationReport {
+  run_checks(cfg, true)
+}
+
+/// 只查连接、目标端目录、传输设置（项目、排除规则、定时同步有问题不影响测试连接这些操作）。
+pub fn validate_connection(cfg: &AgentSyncConfig) -> ValidationReport {
+  run_checks(cfg, false)
+}
+
+fn run_checks(cfg: &AgentSyncConfig, full: bool) -> ValidationReport {
+  let mut out = Problems::default();
+  let (expanded, mut failed) = vars::expand_config(cfg);
+  let cfg = &expanded;
+  check_connection(cfg, &mut out);
+  check_remote(cfg, &mut out);
+  if let Err(e) = rclone::validate_performance(&cfg.performance) {
+    out.error("performance", e);
+  }
+  if full {
+    check_projects(cfg, &mut out);
+    check_excludes(cfg, &mut out);
+    check_schedules(cfg, &mut out);
+  } else {
+    failed.retain(|(path, _)| path.starts_with("connection.") || path.starts_with("remote."));
+  }
+
+  // 展开不了的字段只报展开的问题（按原样再检查只会多出一条“目录不存在”之类的）
+  out.0.retain(|p| failed.iter().all(|(path, _)| *path != p.path));
+  for (path, e) in failed {
+    out.error(path, e);
+  }
+  let mut problems = out.0;
+  problems.sort_by_key(|p| p.severity != Severity::Error);
+  ValidationReport {
+    ok: problems.iter().all(|p| p.severity != Severity::Error),
+    problems,
+  }
+}
@@ -0,0 +1,114 @@
+//! 路径里的变量：同一份配置模板给整个团队用，每台电脑的用户名、盘符不一样也不用改模板。
+//!
+//! 能写的（大白话）：
+//! - `~`（只能写在最前面）、`${HOME}`：用户目录
+//! - `${env:名字}`：环境变量，没设置就报错
+//! - `${名字}`：配置里 `variables` 定义的变量；它的值里也能写 `~`、`${HOME}`、`${env:...}`，但不能再套别的自定义变量
+//!
+//! 用在 `projects[].localPath`、`connection.keyPath`、`remote.projectsRoot`、`remote.backupRoot`。
+//! 配置文件里保存的是原样（带变量），同步 / 检查时才展开。
+//! 目标端路径里的 `~`、`${HOME}` 指的是目标电脑的用户目录（目标端路径本来就从那里算起）。
+
+use crate::config::AgentSyncConfig;
+use std::collections::BTreeMap;
+use std::env;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Side {
+  Local,
+  Remote,
+}
+
+fn home(side: Side) -> Result<String, String> {
+  match side {
+    Side::Local => dirs::home_dir()
+      .map(|h| h.display().to_string())
+      .ok_or_else(|| "找不到本机用户目录（home directory）".to_string()),
+    Side::Remote => Ok("~".to_string()),
+  }
+}
+
+fn lookup(name: &str, user: Option<&BTreeMap<String, String>>, side: Side) -> Result<String, String> {
+  if name == "HOME" {
+    return home(side);
+  }
+  if let Some(var) = name.strip_prefix("env:") {
+    return env::var(var).map_err(|_| format!("环境变量 {} 没有设置", var));
+  }
+  let Some(user) = user else {
+    return Err(format!("变量的值里不能再引用自定义变量：${{{}}}", name));
+  };
+  match user.get(name) {
+    Some(value) => expand_text(value, None, side).map_err(|e| format!("变量 {} 的值有问题：{}", name, e)),
+    None => Err(format!("没有定义变量 ${{{}}}（可以在配置的 variables 里定义）", name)),
+  }
+}
+
+fn expand_text(text: &str, user: Option<&BTreeMap<String, String>>, side: Side) -> Result<String, String> {
+  let mut out = String::new();
+  let mut rest = text;
+
+  // `~` 只在最前面算用户目录（`~/x`、`~\x` 或者单独一个 `~`）
+  if let Some(after) = rest.strip_prefix('~') {
+    if after.is_empty() || after.starts_with('/') || after.starts_with('\\') {
+      out.push_str(&home(side)?);
+      rest = after;
+    }
+  }
+
+  while let Some(start) = rest.find("${") {
+    out.push_str(&rest[..start]);
+    let after = &rest[start + 2..];
+    let end = after.find('}').ok_or_else(|| format!("`${{` 没有配对的 `}}`：{}", text))?;
+    let name = after[..end].trim();
+    if name.is_empty() {
+      return Err(format!("变量名不能为空：{}", text));
+    }
+    out.push_str(&lookup(name, user, side)?);
+    rest = &after[end + 1..];
+  }
+  out.push_str(rest);
+  Ok(out)
+}
+
+/// 展开本机路径里的变量（`user` 是配置里的 `variables`）。
+pub fn expand_local(text: &str, user: &BTreeMap<String, String>) -> Result<String, String> {
+  expand_text(text, Some(user), Side::Local)
+}
+
+/// 展开整份配置里能写变量的字段。展开失败的字段保持原样，连同字段路径一起返回（给配置检查用）。
+pub fn expand_config(cfg: &AgentSyncConfig) -> (AgentSyncConfig, Vec<(String, String)>) {
+  let mut out = cfg.clone();
+  let mut errors: Vec<(String, String)> = Vec::new();
+  let vars = &cfg.variables;
+
+  let mut apply = |path: String, value: &mut String, side: Side| {
+    match expand_text(value, Some(vars), side) {
+      Ok(v) => *value = v,
+      Err(e) => errors.push((path, e)),
+    }
+  };
+  apply("connection.keyPath".to_string(), &mut out.connection.key_path, Side::Local);
+  apply("remote.projectsRoot".to_string(), &mut out.remote.projects_root, Side::Remote);
+  apply("remote.backupRoot".to_string(), &mut out.remote.backup_root, Side::Remote);
+  for (i, p) in out.projects.iter_mut().enumerate() {
+    apply(format!("projects[{}].localPath", i), &mut p.local_path, Side::Local);
+  }
+  (out, errors)
+}
+
+/// 同步前用：展开整份配置，有一个字段展开不了就报错。
+pub fn expanded(cfg: &AgentSyncConfig) -> Result<AgentSyncConfig, String> {
+  let (out, errors) = expand_config(cfg);
+  if errors.is_empty() {
+    return Ok(out);
+  }
+  Err(format!(
+    "配置里的变量展开不了：{}",
+    errors
+      .iter()
+      .map(|(path, e)| format!("{}（{}）", e, path))
+      .collect::<Vec<_>>()
+      .join("；")
+  ))
+}
@@ -0,0 +1,324 @@
+//! 监听模式：盯住本机各同步项的目录，有改动且“安静”一段时间后，自动只同步改动过的项。
+//!
+//! 设计要点（大白话）：
+//! - 用 notify 监听文件变化；命中排除规则的改动（比如 node_modules）直接忽略
+//! - 改动先攒着，等最后一次改动之后安静 `quiet_secs` 秒再触发，避免保存一次就跑一次
+//! - 触发时走和“一键同步”同一条路径（sync_manager::spawn_run）；已有同步在跑就等下一轮再试
+//! - 同步项的目录还不存在（项目后来才建）：先盯着最近一层存在的上级目录，等它建出来再补上监听，并同步一次
+
+use crate::config::AgentSyncConfig;
+use crate::excludes::ExcludeMatcher;
+use crate::rclone::{self, LocalItemRoot, RunOptions};
+use crate::runs;
+use crate::sync_manager;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchStatus {
+  pub running: bool,
+  pub watched_items: Vec<String>,
+  pub pending_items: Vec<String>,
+  pub last_run_id: Option<String>,
+  pub last_error: Option<String>,
+}
+
+enum WatchMsg {
+  Fs(notify::Result<Event>),
+  Stop,
+}
+
+struct WatchHandle {
+  tx: mpsc::Sender<WatchMsg>,
+}
+
+/// 已经挂上的监听（watcher 放在监听线程里：线程退出就停止）。
+struct Arms {
+  watcher: RecommendedWatcher,
+  armed: Vec<(PathBuf, RecursiveMode)>,
+}
+
+/// 目录还不存在的同步项：等 `target` 建出来再监听。
+struct Waiting {
+  item_id: String,
+  label: String,
+  target: PathBuf,
+  mode: RecursiveMode,
+}
+
+impl Arms {
+  /// 已有的监听是不是已经盖住了这个目录（同一目录重复监听会把“递归”改成“不递归”，要避开）。
+  fn covered(&self, dir: &Path, mode: RecursiveMode) -> bool {
+    self.armed.iter().any(|(p, m)| {
+      (*m == RecursiveMode::Recursive && dir.starts_with(p))
+        || (mode == RecursiveMode::NonRecursive && p == dir)
+    })
+  }
+
+  fn watch(&mut self, dir: &Path, mode: RecursiveMode) -> Result<(), String> {
+    if self.covered(dir, mode) {
+      return Ok(());
+    }
+    self
+      .watcher
+      .watch(dir, mode)
+      .map_err(|e| format!("监听目录失败：{}（{}）", dir.display(), e))?;
+    self.armed.push((dir.to_path_buf(), mode));
+    Ok(())
+  }
+
+  /// 目录在就直接监听，返回 true；不在就盯着最近一层存在的上级目录，返回 false。
+  fn arm(&mut self, target: &Path, mode: RecursiveMode) -> Result<bool, String> {
+    if target.exists() {
+      self.watch(target, mode)?;
+      return Ok(true);
+    }
+    let Some(parent) = target.ancestors().skip(1).find(|p| p.is_dir()) else {
+      return Ok(false);
+    };
+    self.watch(parent, RecursiveMode::NonRecursive)?;
+    // 挂上级监听的这一下目录可能刚好建出来了
+    if target.exists() {
+      self.watch(target, mode)?;
+      return Ok(true);
+    }
+    Ok(false)
+  }
+}
+
+static HANDLE: Lazy<Mutex<Option<WatchHandle>>> = Lazy::new(|| Mutex::new(None));
+static STATUS: Lazy<Mutex<WatchStatus>> = Lazy::new(|| Mutex::new(WatchStatus::default()));
+
+fn lock_handle() -> Result<std::sync::MutexGuard<'static, Option<WatchHandle>>, String> {
+  HANDLE
+    .lock()
+    .map_err(|_| "监听状态锁已损坏（poisoned mutex）".to_string())
+}
+
+fn update_status<F: FnOnce(&mut WatchStatus)>(f: F) {
+  if let Ok(mut s) = STATUS.lock() {
+    f(&mut s);
+  }
+}
+
+/// 把一个变动的路径归到某个同步项上；被排除的改动返回 None。
+fn match_item<'a>(
+  roots: &'a [(LocalItemRoot, ExcludeMatcher)],
+  path: &Path,
+) -> Option<&'a LocalItemRoot> {
+  for (root, excludes) in roots {
+    if root.is_file {
+      if path == root.path {
+        return Some(root);
+      }
+      continue;
+    }
+    if let Ok(rel) = path.strip_prefix(&root.path) {
+      if excludes.is_excluded(rel) {
+        return None;
+      }
+      return Some(root);
+    }
+  }
+  None
+}
+
+/// 事件落在还不存在的目录（或它的上级）上：试着补上监听；建出来的项记为待同步。
+fn rearm(arms: &mut Arms, waiting: &mut Vec<Waiting>, paths: &[PathBuf], pending: &mut BTreeSet<String>) -> bool {
+  let mut changed = false;
+  let mut i = 0;
+  while i < waiting.len() {
+    let w = &waiting[i];
+    if !paths.iter().any(|p| w.target.starts_with(p)) {
+      i += 1;
+      continue;
+    }
+    match arms.arm(&w.target, w.mode) {
+      Ok(true) => {
+        let w = waiting.remove(i);
+        update_status(|s| s.watched_items.push(w.label.clone()));
+        pending.insert(w.item_id);
+        changed = true;
+      }
+      Ok(false) => i += 1,
+      Err(e) => {
+        update_status(|s| s.last_error = Some(e));
+        i += 1;
+      }
+    }
+  }
+  changed
+}
+
+fn watch_loop(
+  config: AgentSyncConfig,
+  roots: Vec<(LocalItemRoot, ExcludeMatcher)>,
+  mut arms: Arms,
+  mut waiting: Vec<Waiting>,
+  rx: mpsc::Receiver<WatchMsg>,
+) {
+  let quiet = Duration::from_secs(config.watch.quiet_secs.max(1));
+  let tick = Duration::from_secs(1);
+  let mut pending: BTreeSet<String> = BTreeSet::new();
+  let mut last_change = Instant::now();
+
+  loop {
+    match rx.recv_timeout(tick) {
+      Ok(WatchMsg::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+      Ok(WatchMsg::Fs(Ok(event))) => {
+        // 只读访问（包括 rclone 自己读文件）不算改动
+        if matches!(event.kind, EventKind::Access(_)) {
+          continue;
+        }
+        let mut changed = !waiting.is_empty() && rearm(&mut arms, &mut waiting, &event.paths, &mut pending);
+        for path in &event.paths {
+          if let Some(root) = match_item(&roots, path) {
+            pending.insert(root.item_id.clone());
+            last_change = Instant::now();
+            changed = true;
+          }
+        }
+        if changed {
+          last_change = Instant::now();
+          let labels = pending_labels(&roots, &pending);
+          update_status(|s| s.pending_items = labels);
+        }
+      }
+      Ok(WatchMsg::Fs(Err(e))) => {
+        update_status(|s| s.last_error = Some(format!("监听出错（{}）", e)));
+      }
+      Err(mpsc::RecvTimeoutError::Timeout) => {}
+    }
+
+    if pending.is_empty() || last_change.elapsed() < quiet || sync_manager::is_running() {
+      continue;
+    }
+
+    let ids: Vec<String> = pending.iter().cloned().collect();
+    let opts = RunOptions {
+      only: Some(ids),
+      trigger: Some(runs::TRIGGER_WATCH.to_string()),
+      on_drift: None,
+    };
+    match sync_manager::spawn_run(config.clone(), opts) {
+      Ok(run_id) => {
+        pending.clear();
+        update_status(|s| {
+          s.pending_items.clear();
+          s.last_run_id = Some(run_id);
+          s.last_error = None;
+        });
+      }
+      Err(e) => {
+        // 可能刚好被别的同步抢先启动了：保留待同步项，下一轮再试
+        update_status(|s| s.last_error = Some(e));
+        last_change = Instant::now();
+      }
+    }
+  }
+}
+
+fn pending_labels(roots: &[(LocalItemRoot, ExcludeMatcher)], pending: &BTreeSet<String>) -> Vec<String> {
+  roots
+    .iter()
+    .filter(|(r, _)| pending.contains(&r.item_id))
+    .map(|(r, _)| r.label.clone())
+    .collect()
+}
+
+/// 按配置开始监听；失败原因也记进 `last_error`（守护进程启动时自动开的，界面上也能看到为什么没开成）。
+pub fn start(config: AgentSyncConfig) -> Result<WatchStatus, String> {
+  start_watching(config).inspect_err(|e| update_status(|s| s.last_error = Some(e.clone())))
+}
+
+fn start_watching(config: AgentSyncConfig) -> Result<WatchStatus, String> {
+  rclone::validate_for_run(&config)?;
+  stop()?;
+
+  let mut roots = Vec::new();
+  for root in rclone::local_item_roots(&config)? {
+    let excludes = if root.use_excludes {
+      ExcludeMatcher::new(&config.excludes)?
+    } else {
+      ExcludeMatcher::empty()
+    };
+    roots.push((root, excludes));
+  }
+
+  let (tx, rx) = mpsc::channel::<WatchMsg>();
+  let fs_tx = tx.clone();
+  let watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+    let _ = fs_tx.send(WatchMsg::Fs(res));
+  })
+  .map_err(|e| format!("启动文件监听失败（{}）", e))?;
+
+  let mut arms = Arms {
+    watcher,
+    armed: Vec::new(),
+  };
+  let mut watched = Vec::new();
+  let mut waiting = Vec::new();
+  for (root, _) in &roots {
+    // 单个文件（Codex config.toml）监听它所在目录：编辑器保存时常是“写新文件再改名”
+    let (target, mode) = if root.is_file {
+      match root.path.parent() {
+        Some(parent) => (parent.to_path_buf(), RecursiveMode::NonRecursive),
+        None => continue,
+      }
+    } else {
+      (root.path.clone(), RecursiveMode::Recursive)
+    };
+    if arms.arm(&target, mode)? {
+      watched.push(root.label.clone());
+    } else {
+      waiting.push(Waiting {
+        item_id: root.item_id.clone(),
+        label: root.label.clone(),
+        target,
+        mode,
+      });
+    }
+  }
+
+  // 先把状态置好再起线程：线程里补上监听（rearm）时写的状态不会被这里覆盖
+  update_status(|s| {
+    *s = WatchStatus {
+      running: true,
+      watched_items: watched,
+      ..WatchStatus::default()
+    }
+  });
+  *lock_handle()? = Some(WatchHandle { tx });
+  std::thread::spawn(move || watch_loop(config, roots, arms, waiting, rx));
+  status()
+}
+
+pub fn stop() -> Result<(), String> {
+  let handle = lock_handle()?.take();
+  if let Some(h) = handle {
+    let _ = h.tx.send(WatchMsg::Stop);
+  }
+  update_status(|s| {
+    s.running = false;
+    s.pending_items.clear();
+  });
+  Ok(())
+}
+
+pub fn is_running() -> bool {
+  lock_handle().map(|h| h.is_some()).unwrap_or(false)
+}
+
+pub fn status() -> Result<WatchStatus, String> {
+  STATUS
+    .lock()
+    .map(|s| s.clone())
+    .map_err(|_| "监听状态锁已损坏（poisoned mutex）".to_string())
+}
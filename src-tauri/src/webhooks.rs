@@ -0,0 +1,447 @@
+//! 同步结果对外通知：每次同步写完历史后，按 `config.notifications` 往外发一次。
+//!
+//! 三种格式（大白话）：
+//! - `webhook`：POST 一份 JSON（本机名、目标、结束时间、用时、字节数、失败项），自己写服务接
+//!   （会发到团队共用的频道里：不带备份目录、日志路径、命令输出这些本机细节）
+//! - `slack`：POST `{"text": "..."}`，Slack 以及兼容它的 Incoming Webhook 都能用
+//! - `ntfy`：POST 纯文本到 topic 地址（第一行是标题），优先级 / 标签放在请求头里
+//!
+//! 发送失败（连不上、5xx、429）最多试 3 次，间隔 2 秒、4 秒；4xx 说明配置不对，不重试。
+//! 最坏要等半分钟以上，所以同步结束后在后台线程里发（`send_run_background`），不占着同步的位置。
+
+use crate::config::{AgentSyncConfig, NotifyKind, NotifyTarget};
+use crate::runs::{self, RunStatus, SyncItemResult, SyncRunSummary};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::json;
+use std::fs::OpenOptions;
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const ATTEMPTS: u32 = 3;
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// 重试间隔的起点：第 n 次失败后等 `BACKOFF * 2^(n-1)`
+#[cfg(not(test))]
+const BACKOFF: Duration = Duration::from_secs(2);
+#[cfg(test)]
+const BACKOFF: Duration = Duration::from_millis(10);
+
+/// 后台还没发完的通知（命令行进程退出前要等它们）
+static PENDING: Lazy<Mutex<Vec<JoinHandle<()>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotifyResult {
+  pub target_id: String,
+  pub name: String,
+  pub ok: bool,
+  pub attempts: u32,
+  pub message: String,
+}
+
+/// 通用 webhook 的请求体。
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookPayload<'a> {
+  event: &'static str,
+  /// 发出这次同步的电脑
+  machine: String,
+  /// 同步到哪：`user@host:port`
+  target: String,
+  run_id: &'a str,
+  ok: bool,
+  status: RunStatus,
+  trigger: Option<&'a str>,
+  ended_at_ms: u64,
+  duration_ms: u64,
+  bytes: u64,
+  item_count: usize,
+  failed_items: Vec<FailedItem<'a>>,
+  note: Option<&'a str>,
+}
+
+/// 失败项只带名字和原因（命令输出、目标端改动的文件路径留在本机历史里）。
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FailedItem<'a> {
+  item_id: &'a str,
+  label: &'a str,
+  message: &'a str,
+}
+
+impl<'a> From<&'a SyncItemResult> for FailedItem<'a> {
+  fn from(i: &'a SyncItemResult) -> Self {
+    FailedItem {
+      item_id: &i.item_id,
+      label: &i.label,
+      message: &i.message,
+    }
+  }
+}
+
+fn machine_name() -> String {
+  if let Ok(name) = std::env::var("COMPUTERNAME") {
+    return name;
+  }
+  Command::new("hostname")
+    .output()
+    .ok()
+    .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+    .filter(|s| !s.is_empty())
+    .unwrap_or_else(|| "unknown".to_string())
+}
+
+pub(crate) fn human_bytes(n: u64) -> String {
+  let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+  let mut v = n as f64;
+  let mut i = 0;
+  while v >= 1024.0 && i < units.len() - 1 {
+    v /= 1024.0;
+    i += 1;
+  }
+  if i == 0 {
+    format!("{} B", n)
+  } else {
+    format!("{:.1} {}", v, units[i])
+  }
+}
+
+fn status_text(s: &SyncRunSummary) -> &'static str {
+  match s.status {
+    RunStatus::Missed => "错过",
+    RunStatus::Interrupted => "中断",
+    RunStatus::Cancelled => "已取消",
+    RunStatus::Completed if s.ok => "成功",
+    RunStatus::Completed => "失败",
+  }
+}
+
+/// 给聊天类通知用的一段文字：标题 + 每个失败项一行。
+fn text_message(machine: &str, target: &str, s: &SyncRunSummary) -> (String, String) {
+  let secs = s.ended_at_ms.saturating_sub(s.started_at_ms) / 1000;
+  let bytes: u64 = s.items.iter().map(|i| i.bytes).sum();
+  let title = format!("AgentSync {} → {}：同步{}", machine, target, status_text(s));
+  let mut body = format!(
+    "{} 项，用时 {} 秒，传输 {}",
+    s.items.len(),
+    secs,
+    human_bytes(bytes)
+  );
+  for item in s.items.iter().filter(|i| !i.ok) {
+    body.push_str(&format!("\n• {}：{}", item.label, item.message));
+  }
+  if let Some(note) = &s.note {
+    body.push_str(&format!("\n{}", note));
+  }
+  (title, body)
+}
+
+enum SendError {
+  /// 值得重试（连不上 / 5xx / 429）
+  Retry(String),
+  Fatal(String),
+}
+
+fn send_once(t: &NotifyTarget, machine: &str, target: &str, s: &SyncRunSummary) -> Result<(), SendError> {
+  let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+  let mut req = agent.post(t.url.trim());
+  for (k, v) in &t.headers {
+    req = req.set(k, v);
+  }
+
+  let res = match t.kind {
+    NotifyKind::Webhook => {
+      let payload = WebhookPayload {
+        event: "run.finished",
+        machine: machine.to_string(),
+        target: target.to_string(),
+        run_id: &s.run_id,
+        ok: s.ok,
+        status: s.status,
+        trigger: s.trigger.as_deref(),
+        ended_at_ms: s.ended_at_ms,
+        duration_ms: s.ended_at_ms.saturating_sub(s.started_at_ms),
+        bytes: s.items.iter().map(|i| i.bytes).sum(),
+        item_count: s.items.len(),
+        failed_items: s.items.iter().filter(|i| !i.ok).map(FailedItem::from).collect(),
+        note: s.note.as_deref(),
+      };
+      let body = serde_json::to_string(&payload).map_err(|e| SendError::Fatal(format!("序列化通知失败（{}）", e)))?;
+      req.set("Content-Type", "application/json").send_string(&body)
+    }
+    NotifyKind::Slack => {
+      let (title, body) = text_message(machine, target, s);
+      let text = json!({ "text": format!("*{}*\n{}", title, body) }).to_string();
+      req.set("Content-Type", "application/json").send_string(&text)
+    }
+    NotifyKind::Ntfy => {
+      let (title, body) = text_message(machine, target, s);
+      let (priority, tags) = if s.ok { ("default", "white_check_mark") } else { ("high", "x") };
+      // 标题里有中文，放 HTTP 头容易出编码问题，直接当正文第一行
+      req
+        .set("Priority", priority)
+        .set("Tags", tags)
+        .send_string(&format!("{}\n{}", title, body))
+    }
+  };
+
+  match res {
+    Ok(_) => Ok(()),
+    Err(ureq::Error::Status(code, resp)) => {
+      let text = resp.into_string().unwrap_or_default();
+      let msg = format!("HTTP {}：{}", code, text.trim());
+      if code >= 500 || code == 429 {
+        Err(SendError::Retry(msg))
+      } else {
+        Err(SendError::Fatal(msg))
+      }
+    }
+    Err(e) => Err(SendError::Retry(format!("请求失败（{}）", e))),
+  }
+}
+
+fn send_with_retry(t: &NotifyTarget, machine: &str, target: &str, s: &SyncRunSummary) -> NotifyResult {
+  if t.url.trim().is_empty() {
+    return NotifyResult {
+      target_id: t.id.clone(),
+      name: t.name.clone(),
+      ok: false,
+      attempts: 0,
+      message: "没有填写通知地址".to_string(),
+    };
+  }
+  let mut attempts = 0;
+  let mut last_err = String::new();
+  while attempts < ATTEMPTS {
+    attempts += 1;
+    match send_once(t, machine, target, s) {
+      Ok(()) => {
+        return NotifyResult {
+          target_id: t.id.clone(),
+          name: t.name.clone(),
+          ok: true,
+          attempts,
+          message: "已发送".to_string(),
+        }
+      }
+      Err(SendError::Fatal(e)) => {
+        last_err = e;
+        break;
+      }
+      Err(SendError::Retry(e)) => {
+        last_err = e;
+        if attempts < ATTEMPTS {
+          std::thread::sleep(BACKOFF * (1 << (attempts - 1)));
+        }
+      }
+    }
+  }
+  NotifyResult {
+    target_id: t.id.clone(),
+    name: t.name.clone(),
+    ok: false,
+    attempts,
+    message: last_err,
+  }
+}
+
+/// 把一次运行结果发给所有启用的通知目标（`only_failures` 的目标只在失败时发）。
+pub fn send_run(cfg: &AgentSyncConfig, summary: &SyncRunSummary) -> Vec<NotifyResult> {
+  let targets: Vec<&NotifyTarget> = cfg
+    .notifications
+    .iter()
+    .filter(|t| t.enabled && !t.url.trim().is_empty())
+    .filter(|t| !(t.only_failures && summary.ok))
+    .collect();
+  if targets.is_empty() {
+    return vec![];
+  }
+
+  let machine = machine_name();
+  let target = cfg.connection.target_text();
+  targets
+    .into_iter()
+    .map(|t| send_with_retry(t, &machine, &target, summary))
+    .collect()
+}
+
+/// 在后台线程里发 `send_run`，结果追加到这次运行的日志里（`notify <名称>：...`）。
+pub fn send_run_background(cfg: &AgentSyncConfig, summary: &SyncRunSummary) {
+  if !cfg.notifications.iter().any(|t| t.enabled) {
+    return;
+  }
+  let cfg = cfg.clone();
+  let summary = summary.clone();
+  let handle = std::thread::spawn(move || {
+    let results = send_run(&cfg, &summary);
+    let Ok(mut log) = OpenOptions::new().append(true).open(&summary.log_path) else {
+      return;
+    };
+    for r in results {
+      let _ = runs::append_log_line(
+        &mut log,
+        &format!("notify {}：{}（尝试 {} 次）", r.name, r.message, r.attempts),
+      );
+    }
+  });
+  if let Ok(mut pending) = PENDING.lock() {
+    pending.retain(|h| !h.is_finished());
+    pending.push(handle);
+  }
+}
+
+/// 等后台的通知都发完（命令行跑完同步、进程退出前调用，不然通知会跟着进程一起没了）。
+pub fn wait_pending() {
+  let handles = match PENDING.lock() {
+    Ok(mut pending) => std::mem::take(&mut *pending),
+    Err(_) => return,
+  };
+  for h in handles {
+    let _ = h.join();
+  }
+}
+
+/// 发一条示例结果给所有通知目标（包括没启用的），用来检查配置。
+pub fn send_test(cfg: &AgentSyncConfig) -> Vec<NotifyResult> {
+  let now = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_millis() as u64;
+  let sample = SyncRunSummary {
+    run_id: "test".to_string(),
+    started_at_ms: now.saturating_sub(12_000),
+    ended_at_ms: now,
+    ok: true,
+    backup_root: "".to_string(),
+    log_path: "".to_string(),
+    items: vec![],
+    trigger: Some("test".to_string()),
+    status: RunStatus::Completed,
+    note: Some("这是一条测试通知".to_string()),
+    hooks: vec![],
+    manifest_path: None,
+    target: Some(cfg.connection.target_text()),
+    log_missing: false,
+  };
+
+  let machine = machine_name();
+  let target = cfg.connection.target_text();
+  cfg
+    .notifications
+    .iter()
+    .map(|t| send_with_retry(t, &machine, &target, &sample))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::BTreeMap;
+  use std::sync::mpsc;
+  use tiny_http::{Response, Server};
+
+  /// 本机起一个假的通知服务：按 `statuses` 的顺序回状态码，收到的请求体发回给测试。
+  fn stand_in(statuses: Vec<u16>) -> (String, mpsc::Receiver<String>) {
+    let server = Server::http("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", server.server_addr().to_ip().unwrap());
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+      for code in statuses {
+        let Ok(mut req) = server.recv() else {
+          return;
+        };
+        let mut body = String::new();
+        let _ = req.as_reader().read_to_string(&mut body);
+        let _ = tx.send(body);
+        let _ = req.respond(Response::from_string("x").with_status_code(code));
+      }
+    });
+    (url, rx)
+  }
+
+  fn target(url: String) -> NotifyTarget {
+    NotifyTarget {
+      id: "t1".to_string(),
+      name: "测试".to_string(),
+      enabled: true,
+      kind: NotifyKind::Webhook,
+      url,
+      only_failures: false,
+      headers: BTreeMap::new(),
+    }
+  }
+
+  fn failed_run() -> SyncRunSummary {
+    SyncRunSummary {
+      run_id: "r1".to_string(),
+      started_at_ms: 1_000,
+      ended_at_ms: 4_000,
+      ok: false,
+      backup_root: "backups/r1".to_string(),
+      log_path: "/home/me/.agentsync/logs/r1.log".to_string(),
+      items: vec![SyncItemResult {
+        item_id: "p1".to_string(),
+        label: "项目: one".to_string(),
+        ok: false,
+        message: "rclone 退出码 1".to_string(),
+        bytes: 42,
+        hooks: vec![],
+        drift: vec![],
+        git: None,
+      }],
+      trigger: Some("manual".to_string()),
+      status: RunStatus::Completed,
+      note: None,
+      hooks: vec![],
+      manifest_path: None,
+      target: None,
+      log_missing: false,
+    }
+  }
+
+  #[test]
+  fn retries_on_5xx_until_success() {
+    let (url, rx) = stand_in(vec![500, 503, 200]);
+    let r = send_with_retry(&target(url), "m", "u@h:22", &failed_run());
+    assert!(r.ok, "{}", r.message);
+    assert_eq!(r.attempts, 3);
+    assert_eq!(rx.try_iter().count(), 3);
+  }
+
+  #[test]
+  fn gives_up_after_three_5xx() {
+    let (url, _rx) = stand_in(vec![502, 502, 502]);
+    let r = send_with_retry(&target(url), "m", "u@h:22", &failed_run());
+    assert!(!r.ok);
+    assert_eq!(r.attempts, ATTEMPTS);
+    assert!(r.message.contains("502"), "{}", r.message);
+  }
+
+  #[test]
+  fn no_retry_on_4xx() {
+    let (url, rx) = stand_in(vec![400, 200]);
+    let r = send_with_retry(&target(url), "m", "u@h:22", &failed_run());
+    assert!(!r.ok);
+    assert_eq!(r.attempts, 1);
+    assert!(r.message.contains("400"), "{}", r.message);
+    assert!(rx.recv_timeout(Duration::from_secs(1)).is_ok());
+    assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+  }
+
+  #[test]
+  fn webhook_payload_leaves_out_local_details() {
+    let (url, rx) = stand_in(vec![200]);
+    let r = send_with_retry(&target(url), "m", "u@h:22", &failed_run());
+    assert!(r.ok, "{}", r.message);
+    let body: serde_json::Value = serde_json::from_str(&rx.recv().unwrap()).unwrap();
+    assert_eq!(body["target"], "u@h:22");
+    assert_eq!(body["durationMs"], 3_000);
+    assert_eq!(body["bytes"], 42);
+    assert_eq!(body["failedItems"][0]["label"], "项目: one");
+    assert!(body.get("summary").is_none());
+    assert!(!body.to_string().contains("backups/r1"));
+    assert!(!body.to_string().contains(".agentsync/logs"));
+  }
+}
@@ -1,19 +1,42 @@
 import { invoke } from "@tauri-apps/api/core";
 import type {
   AgentSyncConfig,
+  CompactResult,
+  ConfigSaveResult,
   ConnectionTestResult,
+  DiscoverResult,
+  DriftAction,
+  DriftItemReport,
   EnsureSshKeypairResult,
+  ExportOptions,
+  ExportResult,
+  HistoryStats,
+  HostKeyInfo,
+  ImportOptions,
+  ImportResult,
+  ManifestEntry,
+  ManifestHit,
+  NotifyResult,
+  RetentionResult,
   ShareStartResult,
+  RunPage,
+  RunQuery,
   RunRecord,
+  SyncEstimate,
+  ScheduleStatus,
   SyncStatus,
   SyncRunSummary,
+  ValidationReport,
+  VerifyResult,
+  WatchStatus,
 } from "./types";
 
 export async function configGet(): Promise<AgentSyncConfig> {
   return invoke("config_get");
 }
 
-export async function configSave(config: AgentSyncConfig): Promise<void> {
+/** 保存配置，返回新的版本号；别处改过时报“配置冲突：…” */
+export async function configSave(config: AgentSyncConfig): Promise<ConfigSaveResult> {
   return invoke("config_save", { config });
 }
 
@@ -23,26 +46,138 @@ export async function connectionTest(
   return invoke("connection_test", { config });
 }
 
+export async function hostKeyCheck(config: AgentSyncConfig): Promise<HostKeyInfo> {
+  return invoke("host_key_check", { config });
+}
+
+export async function hostKeyTrust(
+  config: AgentSyncConfig,
+  fingerprint: string,
+): Promise<HostKeyInfo> {
+  return invoke("host_key_trust", { config, fingerprint });
+}
+
 export async function syncRun(config: AgentSyncConfig): Promise<SyncRunSummary> {
   return invoke("sync_run", { config });
 }
 
-export async function syncStart(config: AgentSyncConfig): Promise<string> {
-  return invoke("sync_start", { config });
+/** onDrift 不传则按配置里的 flags.onDrift 处理目标端改动 */
+export async function syncStart(config: AgentSyncConfig, onDrift?: DriftAction): Promise<string> {
+  return invoke("sync_start", { config, onDrift: onDrift ?? null });
+}
+
+/** 同步前检查：每一项上次同步后目标端被改过的文件（只读），用来让用户选拉回 / 跳过 / 另存 */
+export async function syncDriftCheck(config: AgentSyncConfig, itemIds?: string[]): Promise<DriftItemReport[]> {
+  return invoke("sync_drift_check", { config, itemIds: itemIds ?? null });
 }
 
 export async function syncStatus(): Promise<SyncStatus> {
   return invoke("sync_status");
 }
 
+/** 取消正在跑的同步（当前项会被中断，后面的项不再跑） */
+export async function syncCancel(): Promise<void> {
+  return invoke("sync_cancel");
+}
+
+/** 逐项比较本机和目标端（只读）；itemIds 不传则检查全部启用项 */
+export async function syncVerify(config: AgentSyncConfig, itemIds?: string[]): Promise<VerifyResult> {
+  return invoke("sync_verify", { config, itemIds: itemIds ?? null });
+}
+
+/** 同步前估算每项的文件数和大小（只看本机），超过阈值时带提醒 */
+export async function syncEstimate(config: AgentSyncConfig, itemIds?: string[]): Promise<SyncEstimate> {
+  return invoke("sync_estimate", { config, itemIds: itemIds ?? null });
+}
+
+export async function watchStart(config: AgentSyncConfig): Promise<WatchStatus> {
+  return invoke("watch_start", { config });
+}
+
+export async function watchStop(): Promise<void> {
+  return invoke("watch_stop");
+}
+
+export async function watchStatus(): Promise<WatchStatus> {
+  return invoke("watch_status");
+}
+
+export async function scheduleStatus(): Promise<ScheduleStatus[]> {
+  return invoke("schedule_status");
+}
+
+/** 检查配置（没保存的也行），一次列出所有问题和对应字段 */
+export async function configValidate(config: AgentSyncConfig): Promise<ValidationReport> {
+  return invoke("config_validate", { config });
+}
+
+/** 扫描可以同步的项目和工具数据目录（只给建议，不改配置）；roots 不传时用配置里的 discover.roots */
+export async function projectsDiscover(
+  config: AgentSyncConfig,
+  roots?: string[],
+  maxDepth?: number,
+): Promise<DiscoverResult> {
+  return invoke("projects_discover", { config, roots: roots ?? null, maxDepth: maxDepth ?? null });
+}
+
+/** 把配置（可选隐去密钥路径）、历史、日志导出成一个 .tar.gz */
+export async function bundleExport(path: string, options?: ExportOptions): Promise<ExportResult> {
+  return invoke("bundle_export", { path, options: options ?? null });
+}
+
+/** 把导出的包合并进本机配置；dryRun 只看会导入什么 */
+export async function bundleImport(path: string, options?: ImportOptions): Promise<ImportResult> {
+  return invoke("bundle_import", { path, options: options ?? null });
+}
+
 export async function runsList(): Promise<RunRecord[]> {
   return invoke("runs_list");
 }
 
+/** 分页 / 过滤查询历史（新的在前），顺带报告解析不了的行 */
+export async function runsQuery(query: RunQuery): Promise<RunPage> {
+  return invoke("runs_query", { query });
+}
+
+/** 整理历史：去重、keepDays 天（默认 180）前的挪进归档、坏行挪到 history.corrupt.jsonl */
+export async function historyCompact(keepDays?: number): Promise<CompactResult> {
+  return invoke("history_compact", { keepDays: keepDays ?? null });
+}
+
+/** 按保留策略压缩 / 删除旧日志（平时每次同步结束会自动做） */
+export async function logsCleanup(config: AgentSyncConfig): Promise<RetentionResult> {
+  return invoke("logs_cleanup", { config });
+}
+
 export async function runLogRead(runId: string): Promise<string> {
   return invoke("run_log_read", { run_id: runId });
 }
 
+/** 历史统计（成功率、用时、字节趋势、很久没成功的项）；days 不传则统计全部 */
+export async function historyStats(days?: number): Promise<HistoryStats> {
+  return invoke("history_stats", { days: days ?? null });
+}
+
+/** 某次运行传输 / 删除 / 备份了哪些文件 */
+export async function runManifestRead(runId: string): Promise<ManifestEntry[]> {
+  return invoke("run_manifest_read", { runId });
+}
+
+/** 跨所有运行查文件（新的在前）；query 带 * ? 时按通配符匹配整个路径，否则按包含匹配 */
+export async function manifestFind(query: string, itemId?: string, limit?: number): Promise<ManifestHit[]> {
+  return invoke("manifest_find", { query, itemId: itemId ?? null, limit: limit ?? null });
+}
+
+/** 给所有通知目标发一条测试消息（用传入的、可能还没保存的配置） */
+export async function notifyTest(config: AgentSyncConfig): Promise<NotifyResult[]> {
+  return invoke("notify_test", { config });
+}
+
+/** 续跑被中断的运行（history 里 status 为 interrupted 的记录），返回新的 run_id */
+export async function runResume(runId: string): Promise<string> {
+  return invoke("run_resume", { run_id: runId });
+}
+
 export async function sshKeypairEnsure(
   force = false,
 ): Promise<EnsureSshKeypairResult> {
@@ -3,6 +3,7 @@ import type { AgentSyncConfig } from "./types";
 export function defaultConfig(): AgentSyncConfig {
   return {
     schemaVersion: 1,
+    revision: 0,
     rclonePath: null,
     connection: {
       host: "",
@@ -14,10 +15,18 @@ export function defaultConfig(): AgentSyncConfig {
       projectsRoot: "AgentSync/projects",
       backupRoot: "AgentSync/.agentsync-backup",
     },
+    performance: {
+      bwlimit: "",
+      transfers: null,
+      checkers: null,
+      sftpConcurrency: null,
+    },
     flags: {
       mirrorDelete: true,
       syncCodex: true,
       syncAgents: true,
+      checksum: false,
+      onDrift: "warn",
     },
     excludes: [
       "node_modules/**",
@@ -28,6 +37,33 @@ export function defaultConfig(): AgentSyncConfig {
       ".DS_Store",
     ],
     projects: [],
+    watch: {
+      enabled: false,
+      quietSecs: 10,
+    },
+    schedules: [],
+    hooks: {
+      preRun: "",
+      postRun: "",
+      items: {},
+    },
+    notifications: [],
+    logRetention: {
+      compressAfterDays: 1,
+      maxAgeDays: 90,
+      maxCount: 0,
+      maxTotalMb: 1024,
+    },
+    variables: {},
+    discover: {
+      roots: ["~/code", "~/src", "~/projects", "~/dev", "~/work"],
+      maxDepth: 3,
+    },
+    estimate: {
+      warnItemMb: 1024,
+      warnTotalMb: 4096,
+      hugeFileMb: 100,
+    },
   };
 }
 
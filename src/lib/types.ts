@@ -4,10 +4,17 @@ export type ProjectItem = {
   localPath: string;
   remoteDirName: string;
   enabled: boolean;
+  /** 仓库历史怎么同步：off = 不管（.git 跟着排除规则走），bundle = 打包推过去、目标端分支 / HEAD 对齐，
+   *  fetch = 把目标端的新提交取回本机 refs/remotes/agentsync/ */
+  git?: GitMode;
 };
 
+export type GitMode = "off" | "bundle" | "fetch";
+
 export type AgentSyncConfig = {
   schemaVersion: 1;
+  /** 每保存一次加 1；保存时和磁盘上的对不上会报“配置冲突” */
+  revision?: number;
   rclonePath?: string | null;
   connection: {
     host: string;
@@ -19,18 +26,388 @@ export type AgentSyncConfig = {
     projectsRoot: string;
     backupRoot: string;
   };
+  performance: PerformanceConfig;
   flags: {
     mirrorDelete: boolean;
     syncCodex: boolean;
     syncAgents: boolean;
+    /** 按哈希（目标端 md5sum / sha1sum）判断是否需要传输，而不是大小 + 修改时间 */
+    checksum?: boolean;
+    /** 目标端在上次同步后被改过的文件怎么处理，默认 warn */
+    onDrift?: DriftAction;
   };
   excludes: string[];
   projects: ProjectItem[];
+  watch: WatchConfig;
+  schedules: ScheduleConfig[];
+  hooks: HooksConfig;
+  notifications: NotifyTarget[];
+  /** 运行日志保留多久；旧配置里没有时按默认值 */
+  logRetention?: LogRetentionConfig;
+  /** 自定义路径变量：本机目录、私钥路径、目标端根目录里写 `${名字}`（还能写 `~`、`${HOME}`、`${env:名字}`） */
+  variables?: Record<string, string>;
+  /** 自动发现项目时扫描哪些目录；旧配置里没有时按默认值 */
+  discover?: DiscoverConfig;
+  /** 同步前估算的提醒阈值；旧配置里没有时按默认值 */
+  estimate?: EstimateConfig;
+};
+
+export type ConfigSaveResult = {
+  revision: number;
+  /** 配置已经保存，但守护进程没能按新配置开监听时的原因 */
+  warning?: string | null;
+};
+
+export type DiscoverConfig = {
+  /** 扫描的根目录（可以写 ~、${HOME}、自定义变量），不存在的跳过 */
+  roots: string[];
+  /** 往下找几层 */
+  maxDepth: number;
+};
+
+/** 扫描到的一个可同步的文件夹 */
+export type DiscoveredItem = {
+  kind: "project" | "agentData";
+  /** 数据目录属于哪个工具 */
+  tool?: string | null;
+  /** 命中的标记，例如 .git、AGENTS.md */
+  markers: string[];
+  /** 建议加进配置的项目 */
+  proposal: ProjectItem;
+  /** 对应的内置开关：不用加项目，打开开关就行 */
+  builtinFlag?: "syncCodex" | "syncAgents" | null;
+  /** 按排除规则算的大小 */
+  bytes: number;
+  files: number;
+  /** 文件太多，大小只数了一部分 */
+  sizeTruncated: boolean;
+  /** 已经在同步了：已有项目的 id，或者 flags.syncCodex 这类开关 */
+  coveredBy?: string | null;
+  note?: string | null;
+  /** 加进配置时要一起加的排除规则（登录信息文件） */
+  excludes: string[];
+};
+
+export type DiscoverResult = {
+  /** 实际扫描了的根目录 */
+  roots: string[];
+  items: DiscoveredItem[];
+  errors: string[];
+};
+
+/** 运行日志保留策略：数字填 0 表示不按这一条限制 */
+export type LogRetentionConfig = {
+  /** 超过几天的日志压缩成 .log.gz（读日志时自动解压） */
+  compressAfterDays: number;
+  maxAgeDays: number;
+  /** 最多留几份（新的优先） */
+  maxCount: number;
+  /** 所有日志加起来最多占多少 MB（超出时从最旧的删起） */
+  maxTotalMb: number;
+};
+
+export type RetentionResult = {
+  compressed: number;
+  deleted: number;
+  /** 历史里改了 logPath / 标了 logMissing 的记录数 */
+  historyUpdated: number;
+  bytesBefore: number;
+  bytesAfter: number;
+  errors: string[];
+};
+
+/** 同步结束后对外发通知：webhook = 通用 JSON；slack = {"text": ...}；ntfy = 纯文本到 topic 地址 */
+export type NotifyTarget = {
+  id: string;
+  name: string;
+  enabled: boolean;
+  kind: "webhook" | "slack" | "ntfy";
+  url: string;
+  /** 只在同步失败时通知 */
+  onlyFailures: boolean;
+  /** 额外请求头，例如 Authorization */
+  headers: Record<string, string>;
+};
+
+export type NotifyResult = {
+  targetId: string;
+  name: string;
+  ok: boolean;
+  attempts: number;
+  message: string;
+};
+
+/**
+ * 目标端改动的处理方式：
+ * warn 照常同步并列出来 / skip 跳过有改动的项 / pull 先拉回本机 / backup 先另存到本机 / off 不检查
+ */
+export type DriftAction = "warn" | "skip" | "pull" | "backup" | "off";
+
+/** 上次同步后在目标端被改过的文件（路径相对该项的根目录） */
+export type DriftFile = {
+  path: string;
+  size: number;
+  modTime: string;
+  /** 本机没有同名文件时，镜像删除会把它挪进备份目录 */
+  localExists: boolean;
+};
+
+export type DriftItemReport = {
+  itemId: string;
+  label: string;
+  /** 上次成功同步的结束时间；从没同步成功过的项为 null（不检查） */
+  lastSyncedAtMs: number | null;
+  files: DriftFile[];
+  error: string | null;
+};
+
+export type ItemStats = {
+  itemId: string;
+  label: string;
+  runs: number;
+  ok: number;
+  failed: number;
+  /** 0 ~ 1 */
+  successRate: number;
+  bytes: number;
+  lastOkAtMs: number | null;
+  /** 距离最后一次成功多久；从没成功过为 null */
+  sinceLastOkMs: number | null;
+  consecutiveFailures: number;
+  lastError: string | null;
+};
+
+export type TargetStats = {
+  target: string;
+  runs: number;
+  ok: number;
+  successRate: number;
+  avgDurationMs: number;
+  lastOkAtMs: number | null;
+};
+
+/** 每一项最近一次结果和它的上一次不一样（刚开始失败 / 刚恢复） */
+export type ItemChange = {
+  itemId: string;
+  label: string;
+  runId: string;
+  atMs: number;
+  ok: boolean;
+  previousOk: boolean;
+  message: string;
+};
+
+/** 历史统计；错过的定时只计入 missed，不算进成功率和平均用时 */
+export type HistoryStats = {
+  sinceMs: number | null;
+  runs: number;
+  okRuns: number;
+  missed: number;
+  successRate: number;
+  avgDurationMs: number;
+  totalBytes: number;
+  mostFailingItemId: string | null;
+  /** 很久没成功的在前 */
+  items: ItemStats[];
+  targets: TargetStats[];
+  /** 本机时区按天，从早到晚 */
+  daily: { day: string; runs: number; ok: number; bytes: number }[];
+  changes: ItemChange[];
+};
+
+/** 文件清单里的一行：added 新传 / updated 替换 / deleted 镜像删除 / error 出错 */
+export type ManifestEntry = {
+  itemId: string;
+  /** 相对该项根目录的路径（单文件项就是文件名） */
+  path: string;
+  action: "added" | "updated" | "deleted" | "error";
+  /** 目标端备份目录里的旧版本（updated / deleted 才有） */
+  backupPath?: string | null;
+};
+
+/** 跨运行查文件的一条结果 */
+export type ManifestHit = ManifestEntry & {
+  runId: string;
+  startedAtMs: number;
+};
+
+/** 单个同步项的校验结果（文件路径相对该项的根目录） */
+export type VerifyItemResult = {
+  itemId: string;
+  label: string;
+  ok: boolean;
+  /** hash：按哈希比较；size：两边没有共同的哈希算法，只比较了大小 */
+  method: "hash" | "size";
+  matched: number;
+  /** 本机有、目标端没有 */
+  missing: string[];
+  /** 目标端有、本机没有（镜像删除关闭时不算失败） */
+  extra: string[];
+  differ: string[];
+  errors: string[];
+  message: string;
+};
+
+export type VerifyResult = {
+  ok: boolean;
+  items: VerifyItemResult[];
+};
+
+/** 同步前估算的提醒阈值（MB，0 表示不按这一条提醒） */
+export type EstimateConfig = {
+  /** 单个同步项超过多少 MB 提醒 */
+  warnItemMb: number;
+  /** 这次同步加起来超过多少 MB 提醒 */
+  warnTotalMb: number;
+  /** 单个文件超过多少 MB 算大文件 */
+  hugeFileMb: number;
+};
+
+export type ItemEstimate = {
+  itemId: string;
+  label: string;
+  local: string;
+  files: number;
+  bytes: number;
+  /** 文件太多没数完 */
+  truncated: boolean;
+  /** 最大的几个第一层子目录 */
+  biggestDirs: { path: string; files: number; bytes: number }[];
+  hugeFiles: { path: string; bytes: number }[];
+  skipMessage?: string | null;
+  warnings: string[];
+};
+
+/** 同步前估算：本机要同步的总量（没变的文件不会再传，所以是上限） */
+export type SyncEstimate = {
+  items: ItemEstimate[];
+  totalFiles: number;
+  totalBytes: number;
+  /** 为空表示没什么要注意的 */
+  warnings: string[];
+};
+
+/** 传输调优；不填用 rclone 默认值 */
+export type PerformanceConfig = {
+  /** rclone --bwlimit：例如 "10M" / "off" / "08:00,2M 18:00,off" / "Mon-08:00,2M"；空字符串表示不限速 */
+  bwlimit: string;
+  transfers?: number | null;
+  checkers?: number | null;
+  sftpConcurrency?: number | null;
+};
+
+/** 同步前后命令；空字符串表示不跑 */
+export type HooksConfig = {
+  /** 整次同步开始前在本机跑；失败则本次所有项都跳过 */
+  preRun: string;
+  /** 整次同步结束后在本机跑（环境变量 AGENTSYNC_OK=1/0） */
+  postRun: string;
+  /** 按同步项 id 配置 */
+  items: Record<string, ItemHooks>;
+};
+
+export type ItemHooks = {
+  /** 同步前在本机项目目录里跑；失败就跳过这一项 */
+  preSync: string;
+  /** 同步成功后通过 SSH 在目标端项目目录里跑，例如 "npm ci" */
+  postSyncRemote: string;
+};
+
+export type ScheduleConfig = {
+  id: string;
+  name: string;
+  enabled: boolean;
+  /** 例如 "every 2h" / "daily 03:00" / "weekdays 18:00" / "mon,wed 09:30" */
+  spec: string;
+  /** 为空表示全部启用的同步项 */
+  itemIds: string[];
+};
+
+export type ScheduleStatus = {
+  id: string;
+  name: string;
+  enabled: boolean;
+  spec: string;
+  error?: string | null;
+  lastAtMs?: number | null;
+  nextAtMs?: number | null;
+};
+
+export type WatchConfig = {
+  enabled: boolean;
+  quietSecs: number;
+};
+
+export type WatchStatus = {
+  running: boolean;
+  watchedItems: string[];
+  pendingItems: string[];
+  lastRunId?: string | null;
+  lastError?: string | null;
 };
 
 export type ConnectionTestResult = {
+  /** 没有 fail 的步骤 */
   ok: boolean;
   message: string;
+  /** 每一步诊断和同步前检查的结果（按固定顺序，没做到的是 skipped） */
+  checks?: ConnectionCheck[];
+  /** 目标电脑的身份指纹；status 不是 trusted 时要用户核对后确认（hostKeyTrust） */
+  hostKey?: HostKeyInfo | null;
+};
+
+export type HostKeyInfo = {
+  /** host 或 [host]:port */
+  host: string;
+  keyType: string;
+  /** 目标电脑现在出示的指纹 SHA256:... */
+  fingerprint: string;
+  /** new = 还没记下过；trusted = 和记下的一致；changed = 和记下的不一样 */
+  status: "new" | "trusted" | "changed";
+  pinnedFingerprint?: string | null;
+};
+
+export type ConnectionCheckId =
+  | "dns"
+  | "tcp"
+  | "banner"
+  | "hostKey"
+  | "auth"
+  | "sftp"
+  | "writeProjectsRoot"
+  | "writeBackupRoot"
+  | "remoteOs"
+  | "diskSpace"
+  | "caseSensitivity"
+  | "fileNames";
+
+export type ConnectionFailureReason =
+  | "dnsFailed"
+  | "remoteLoginDisabled"
+  | "timeout"
+  | "networkUnreachable"
+  | "notSsh"
+  | "hostKeyUnconfirmed"
+  | "hostKeyChanged"
+  | "hostKeyUnavailable"
+  | "keyFileProblem"
+  | "keyNotAuthorized"
+  | "sftpDisabled"
+  | "permissionDenied"
+  | "diskFull"
+  | "toolMissing"
+  | "other";
+
+export type ConnectionCheck = {
+  id: ConnectionCheckId;
+  label: string;
+  status: "ok" | "warn" | "fail" | "skipped";
+  message: string;
+  /** 失败原因（只有 fail 有） */
+  reason?: ConnectionFailureReason | null;
+  elapsedMs: number;
+  /** 有问题的文件（最多 10 个） */
+  examples: string[];
 };
 
 export type EnsureSshKeypairResult = {
@@ -47,9 +424,39 @@ export type ShareStartResult = {
 };
 
 export type SyncItemResult = {
+  /** 同步项 id（项目 id / codex:sessions / codex:config / agents）；旧记录里为空 */
+  itemId?: string;
   label: string;
   ok: boolean;
   message: string;
+  /** rclone 报告的传输字节数 */
+  bytes?: number;
+  hooks?: HookResult[];
+  /** 同步前发现的目标端改动 */
+  drift?: DriftFile[];
+  /** 本机仓库状态（不是 git 仓库时为空） */
+  git?: GitState | null;
+};
+
+export type GitState = {
+  branch?: string | null;
+  head?: string | null;
+  dirty: boolean;
+  changedFiles: number;
+  /** bundle 模式推过去 / fetch 模式取回来的包大小（没有新提交时是 0） */
+  bundleBytes?: number | null;
+  /** 目标端仓库已经对齐到这个 HEAD */
+  remoteUpdated?: boolean;
+  /** fetch 模式取回了目标端几个分支 */
+  fetchedBranches?: number | null;
+};
+
+export type HookResult = {
+  stage: "preRun" | "postRun" | "preSync" | "postSyncRemote";
+  command: string;
+  ok: boolean;
+  exitCode?: number | null;
+  message: string;
 };
 
 export type SyncRunSummary = {
@@ -60,10 +467,146 @@ export type SyncRunSummary = {
   backupRoot: string;
   logPath: string;
   items: SyncItemResult[];
+  /** "manual" / "watch" / "cli" / "schedule:<id>" / "resume:<run_id>" */
+  trigger?: string | null;
+  status?: "completed" | "missed" | "interrupted" | "cancelled";
+  note?: string | null;
+  hooks?: HookResult[];
+  /** 本次文件清单（~/.agentsync/manifests/<run_id>.jsonl）；没有文件变化时为空 */
+  manifestPath?: string | null;
+  /** 同步到哪台电脑：user@host:port（旧记录、错过的定时没有） */
+  target?: string | null;
+  /** 日志已按保留策略删掉（logPath 打不开了） */
+  logMissing?: boolean;
 };
 
 export type RunRecord = SyncRunSummary;
 
+/** 历史查询条件；都不填就是“全部，新的在前” */
+export type RunQuery = {
+  offset?: number;
+  limit?: number | null;
+  /** user@host:port，包含即可 */
+  target?: string | null;
+  status?: "completed" | "missed" | "interrupted" | "cancelled" | null;
+  ok?: boolean | null;
+  fromMs?: number | null;
+  toMs?: number | null;
+  /** 同步项名字或 id，包含即可（不分大小写） */
+  item?: string | null;
+  /** 也查整理时挪进归档的旧记录 */
+  includeArchived?: boolean;
+};
+
+/** 历史文件里解析不了的一行 */
+export type CorruptLine = {
+  file: string;
+  lineNo: number;
+  text: string;
+  error: string;
+};
+
+export type RunPage = {
+  /** 符合条件的总条数（分页前） */
+  total: number;
+  offset: number;
+  runs: RunRecord[];
+  corruptCount: number;
+  /** 最多列 20 条 */
+  corrupt: CorruptLine[];
+};
+
+/** 配置检查的一个问题：error 会拦下同步，warning 只是提醒 */
+export type ConfigProblem = {
+  /** 出问题的字段，例如 projects[2].remoteDirName、excludes[0] */
+  path: string;
+  severity: "error" | "warning";
+  message: string;
+};
+
+export type ValidationReport = {
+  /** 没有 error 级别的问题 */
+  ok: boolean;
+  problems: ConfigProblem[];
+};
+
+export type ExportOptions = {
+  /** 清空 connection.keyPath */
+  redactKeyPaths?: boolean;
+  includeHistory?: boolean;
+  includeLogs?: boolean;
+};
+
+export type ExportResult = {
+  path: string;
+  projects: number;
+  runs: number;
+  logs: number;
+  bytes: number;
+};
+
+/** 项目路径替换：以 from 开头（整段目录）的换成 to */
+export type PathMapping = {
+  from: string;
+  to: string;
+};
+
+export type ImportOptions = {
+  /** 按顺序试，第一条匹配的生效；都不匹配时再把源电脑的用户目录换成本机的 */
+  pathMap?: PathMapping[];
+  includeHistory?: boolean;
+  /** 导入单项命令（会在本机 / 目标端执行）；默认不导入，只在结果里列出 */
+  includeHooks?: boolean;
+  /** 只看会导入什么，不写任何文件 */
+  dryRun?: boolean;
+};
+
+export type ImportedProject = {
+  id: string;
+  name: string;
+  sourcePath: string;
+  localPath: string;
+  /** 本机目录不存在（这种项目先不启用） */
+  missing: boolean;
+};
+
+export type ImportedHook = {
+  itemId: string;
+  stage: "preSync" | "postSyncRemote";
+  command: string;
+};
+
+export type ImportResult = {
+  dryRun: boolean;
+  bundle: {
+    version: number;
+    createdAtMs: number;
+    sourceHome?: string | null;
+    keyPathsRedacted: boolean;
+  };
+  projectsAdded: ImportedProject[];
+  projectsSkipped: string[];
+  excludesAdded: string[];
+  variablesAdded: string[];
+  schedulesAdded: number;
+  /** 加进本机配置的单项命令（includeHooks 时） */
+  hooksAdded: ImportedHook[];
+  /** 包里有但没导入的单项命令（没开 includeHooks） */
+  hooksIgnored: ImportedHook[];
+  connectionImported: boolean;
+  runsImported: number;
+  logsImported: number;
+};
+
+export type CompactResult = {
+  kept: number;
+  archived: number;
+  duplicatesRemoved: number;
+  corruptMoved: number;
+  bytesBefore: number;
+  bytesAfter: number;
+};
+
 export type SyncStatus = {
   running: boolean;
   runId?: string | null;
@@ -1,13 +1,35 @@
-import { useCallback, useEffect, useMemo, useState } from "react";
+import { listen } from "@tauri-apps/api/event";
+import { useCallback, useEffect, useMemo, useRef, useState } from "react";
 import { configGet, configSave } from "./api";
 import { defaultConfig } from "./defaults";
 import type { AgentSyncConfig } from "./types";
 
+/** 后端在 ~/.agentsync/config.json 被别处（命令行 / 另一个窗口）改动时发这个事件 */
+const CONFIG_CHANGED = "config-changed";
+/** 后端保存冲突的报错以这个开头 */
+const CONFLICT_PREFIX = "配置冲突";
+
 export function useAgentSyncConfig() {
   const [config, setConfig] = useState<AgentSyncConfig>(defaultConfig());
   const [loading, setLoading] = useState(true);
   const [saving, setSaving] = useState(false);
   const [error, setError] = useState<string | null>(null);
+  // 别处改过配置、这里又有没保存的改动：需要重新载入（会丢掉这里的改动）
+  const [conflict, setConflict] = useState(false);
+  // 最近一次从磁盘读到 / 保存成功的内容：和它一样就说明没有没保存的改动
+  const savedRef = useRef<string>("");
+  const configRef = useRef(config);
+  configRef.current = config;
+  // 自己保存也会触发文件变化事件：保存期间收到的不算“别处改的”
+  const savingRef = useRef(false);
+
+  const reload = useCallback(async () => {
+    const loaded = await configGet();
+    savedRef.current = JSON.stringify(loaded);
+    setConfig(loaded);
+    setError(null);
+    setConflict(false);
+  }, []);
 
   useEffect(() => {
     let canceled = false;
@@ -15,6 +37,7 @@ export function useAgentSyncConfig() {
       try {
         const loaded = await configGet();
         if (canceled) return;
+        savedRef.current = JSON.stringify(loaded);
         setConfig(loaded);
         setError(null);
       } catch (e) {
@@ -30,6 +53,23 @@ export function useAgentSyncConfig() {
     };
   }, []);
 
+  // 别处改了配置：没有没保存的改动就直接重新载入，有的话提示一下（保存时会报冲突）
+  useEffect(() => {
+    const unlisten = listen<{ revision: number }>(CONFIG_CHANGED, (event) => {
+      const current = configRef.current;
+      if (savingRef.current || event.payload.revision === current.revision) return;
+      if (JSON.stringify(current) === savedRef.current) {
+        reload().catch((e) => setError(String(e)));
+      } else {
+        setError("配置已在别处（另一个窗口或命令行）修改过，保存前请先重新载入");
+        setConflict(true);
+      }
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, [reload]);
+
   const isValidForRun = useMemo(() => {
     return (
       config.connection.host.trim().length > 0 &&
@@ -41,13 +81,20 @@ export function useAgentSyncConfig() {
 
   const save = useCallback(async () => {
     setSaving(true);
+    savingRef.current = true;
     try {
-      await configSave(config);
-      setError(null);
+      const { revision, warning } = await configSave(config);
+      const saved = { ...config, revision };
+      savedRef.current = JSON.stringify(saved);
+      setConfig(saved);
+      setError(warning ?? null);
+      setConflict(false);
     } catch (e) {
       setError(String(e));
+      if (String(e).startsWith(CONFLICT_PREFIX)) setConflict(true);
       throw e;
     } finally {
+      savingRef.current = false;
       setSaving(false);
     }
   }, [config]);
@@ -59,8 +106,9 @@ export function useAgentSyncConfig() {
     saving,
     error,
     setError,
+    conflict,
     isValidForRun,
     save,
+    reload,
   };
 }
-
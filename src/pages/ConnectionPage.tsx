@@ -1,7 +1,8 @@
 import { open as openDialog } from "@tauri-apps/plugin-dialog";
 import { useState } from "react";
-import { connectionTest } from "../lib/api";
+import { connectionTest, hostKeyTrust } from "../lib/api";
 import { useAgentSyncConfig } from "../lib/useAgentSyncConfig";
+import type { ConnectionCheck, HostKeyInfo } from "../lib/types";
 
 function Field(props: {
   label: string;
@@ -32,11 +33,22 @@ function Input(props: React.InputHTMLAttributes<HTMLInputElement>) {
   );
 }
 
+const CHECK_ICONS: Record<ConnectionCheck["status"], string> = {
+  ok: "✓",
+  warn: "!",
+  fail: "✗",
+  skipped: "–",
+};
+
 export function ConnectionPage() {
-  const { config, setConfig, saving, save, error, setError } =
+  const { config, setConfig, saving, save, error, setError, conflict, reload } =
     useAgentSyncConfig();
   const [testing, setTesting] = useState(false);
   const [testResult, setTestResult] = useState<string | null>(null);
+  const [checks, setChecks] = useState<ConnectionCheck[]>([]);
+  // 指纹没确认过 / 变了：让用户核对后确认
+  const [hostKey, setHostKey] = useState<HostKeyInfo | null>(null);
+  const [trusting, setTrusting] = useState(false);
 
   async function pickKeyFile() {
     const selected = await openDialog({
@@ -66,9 +78,13 @@ export function ConnectionPage() {
   async function onTest() {
     setTesting(true);
     setTestResult(null);
+    setChecks([]);
     try {
       const res = await connectionTest(config);
-      setTestResult(res.message);
+      const list = res.checks ?? [];
+      setChecks(list);
+      setHostKey(res.hostKey && res.hostKey.status !== "trusted" ? res.hostKey : null);
+      setTestResult(list.length ? (res.ok ? "检查完成" : "有检查没通过") : res.message);
       setError(null);
     } catch (e) {
       setTestResult("测试失败：" + String(e));
@@ -77,6 +93,20 @@ export function ConnectionPage() {
     }
   }
 
+  async function onTrustHostKey() {
+    if (!hostKey) return;
+    setTrusting(true);
+    try {
+      await hostKeyTrust(config, hostKey.fingerprint);
+      setHostKey(null);
+      await onTest();
+    } catch (e) {
+      setTestResult("记下指纹失败：" + String(e));
+    } finally {
+      setTrusting(false);
+    }
+  }
+
   return (
     <div className="space-y-6">
       <div>
@@ -87,8 +117,16 @@ export function ConnectionPage() {
       </div>
 
       {error ? (
-        <div className="rounded-xl border border-rose-200 bg-rose-50 p-4 text-sm text-rose-900">
-          {error}
+        <div className="flex flex-wrap items-center justify-between gap-3 rounded-xl border border-rose-200 bg-rose-50 p-4 text-sm text-rose-900">
+          <div>{error}</div>
+          {conflict ? (
+            <button
+              className="rounded-2xl border border-rose-200 bg-white/80 px-4 py-2 text-sm font-bold text-rose-900 shadow-sm transition-all hover:bg-white active:scale-[0.98]"
+              onClick={() => reload().catch((e) => setError(String(e)))}
+            >
+              重新载入（放弃这里没保存的改动）
+            </button>
+          ) : null}
         </div>
       ) : null}
 
@@ -223,9 +261,80 @@ export function ConnectionPage() {
         </button>
       </div>
 
+      {hostKey ? (
+        <div
+          className={[
+            "rounded-xl border p-4 text-sm",
+            hostKey.status === "changed"
+              ? "border-rose-200 bg-rose-50 text-rose-900"
+              : "border-amber-200 bg-amber-50 text-amber-900",
+          ].join(" ")}
+        >
+          <div className="font-semibold">
+            {hostKey.status === "changed"
+              ? "目标电脑的身份指纹变了，已拒绝连接"
+              : "第一次连接这台电脑，请核对身份指纹"}
+          </div>
+          <div className="mt-2 break-all font-mono text-xs">
+            {hostKey.host}　{hostKey.keyType} {hostKey.fingerprint}
+          </div>
+          {hostKey.status === "changed" && hostKey.pinnedFingerprint ? (
+            <div className="mt-1 break-all font-mono text-xs opacity-80">
+              之前记下的：{hostKey.pinnedFingerprint}
+            </div>
+          ) : null}
+          <div className="mt-2 text-xs opacity-80">
+            {hostKey.status === "changed"
+              ? "可能是目标电脑重装过系统，也可能有人在中间冒充。请先在目标电脑上核对，确认无误再点下面的按钮。"
+              : "在目标电脑上运行 ssh-keygen -lf /etc/ssh/ssh_host_ed25519_key.pub，看输出的指纹是否和上面一样。"}
+          </div>
+          <button
+            className="mt-3 rounded-lg border border-slate-200 bg-white px-3 py-1.5 text-sm font-semibold text-slate-700 transition hover:bg-slate-50 disabled:cursor-not-allowed disabled:opacity-60"
+            onClick={onTrustHostKey}
+            disabled={trusting || testing}
+          >
+            {trusting ? "记下中…" : "指纹一致，确认是这台电脑"}
+          </button>
+        </div>
+      ) : null}
+
       {testResult ? (
         <div className="rounded-xl border border-slate-200 bg-slate-50 p-4 text-sm text-slate-800">
-          {testResult}
+          <div className="font-medium">{testResult}</div>
+          {checks.length ? (
+            <div className="mt-2 space-y-1">
+              {checks.map((c) => (
+                <div key={c.id} className="flex items-start gap-2">
+                  <div
+                    className={
+                      c.status === "ok"
+                        ? "text-emerald-700"
+                        : c.status === "warn"
+                          ? "text-amber-700"
+                          : c.status === "fail"
+                            ? "text-rose-700"
+                            : "text-slate-400"
+                    }
+                  >
+                    {CHECK_ICONS[c.status]}
+                  </div>
+                  <div className="min-w-0">
+                    <div>
+                      <span className="font-medium">{c.label}</span>：{c.message}
+                      {c.status !== "skipped" ? (
+                        <span className="ml-1 text-xs text-slate-500">{c.elapsedMs} ms</span>
+                      ) : null}
+                    </div>
+                    {c.examples.map((ex) => (
+                      <div key={ex} className="truncate text-xs text-slate-600">
+                        {ex}
+                      </div>
+                    ))}
+                  </div>
+                </div>
+              ))}
+            </div>
+          ) : null}
         </div>
       ) : null}
     </div>
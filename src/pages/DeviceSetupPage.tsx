@@ -2,12 +2,13 @@ import { open as openDialog } from "@tauri-apps/plugin-dialog";
 import { useEffect, useMemo, useState } from "react";
 import {
   connectionTest,
+  hostKeyTrust,
   shareStart,
   sshKeypairEnsure,
   sshPublicKeyRead,
 } from "../lib/api";
 import { useAgentSyncConfig } from "../lib/useAgentSyncConfig";
-import type { ShareStartResult } from "../lib/types";
+import type { HostKeyInfo, ShareStartResult } from "../lib/types";
 
 function Input(props: React.InputHTMLAttributes<HTMLInputElement>) {
   return (
@@ -100,7 +101,7 @@ function generateSetupScript(params: {
 }
 
 export function DeviceSetupPage() {
-  const { config, setConfig, saving, save, error, setError } =
+  const { config, setConfig, saving, save, error, setError, conflict, reload } =
     useAgentSyncConfig();
 
   const [targetOs, setTargetOs] = useState<"mac_linux" | "windows">("mac_linux");
@@ -111,6 +112,9 @@ export function DeviceSetupPage() {
 
   const [testing, setTesting] = useState(false);
   const [testResult, setTestResult] = useState<string | null>(null);
+  // 指纹没确认过 / 变了：让用户核对后确认
+  const [hostKey, setHostKey] = useState<HostKeyInfo | null>(null);
+  const [trusting, setTrusting] = useState(false);
 
   const [copyHint, setCopyHint] = useState<string | null>(null);
   const [share, setShare] = useState<ShareStartResult | null>(null);
@@ -214,6 +218,7 @@ export function DeviceSetupPage() {
     try {
       const res = await connectionTest(config);
       setTestResult(res.message);
+      setHostKey(res.hostKey && res.hostKey.status !== "trusted" ? res.hostKey : null);
       setError(null);
     } catch (e) {
       setTestResult("测试失败：" + String(e));
@@ -222,6 +227,20 @@ export function DeviceSetupPage() {
     }
   }
 
+  async function onTrustHostKey() {
+    if (!hostKey) return;
+    setTrusting(true);
+    try {
+      await hostKeyTrust(config, hostKey.fingerprint);
+      setHostKey(null);
+      await onTest();
+    } catch (e) {
+      setTestResult("记下指纹失败：" + String(e));
+    } finally {
+      setTrusting(false);
+    }
+  }
+
   async function onShareScript() {
     if (!setupScript) return;
     setSharing(true);
@@ -247,8 +266,16 @@ export function DeviceSetupPage() {
       </div>
 
       {error ? (
-        <div className="rounded-xl border border-rose-200 bg-rose-50 p-4 text-sm text-rose-900">
-          {error}
+        <div className="flex flex-wrap items-center justify-between gap-3 rounded-xl border border-rose-200 bg-rose-50 p-4 text-sm text-rose-900">
+          <div>{error}</div>
+          {conflict ? (
+            <button
+              className="rounded-2xl border border-rose-200 bg-white/80 px-4 py-2 text-sm font-bold text-rose-900 shadow-sm transition-all hover:bg-white active:scale-[0.98]"
+              onClick={() => reload().catch((e) => setError(String(e)))}
+            >
+              重新载入（放弃这里没保存的改动）
+            </button>
+          ) : null}
         </div>
       ) : null}
 
@@ -579,8 +606,45 @@ export function DeviceSetupPage() {
         </button>
       </div>
 
+      {hostKey ? (
+        <div
+          className={[
+            "rounded-3xl border p-6 text-sm shadow-sm",
+            hostKey.status === "changed"
+              ? "border-rose-200 bg-rose-50 text-rose-900"
+              : "border-amber-200 bg-amber-50 text-amber-900",
+          ].join(" ")}
+        >
+          <div className="font-semibold">
+            {hostKey.status === "changed"
+              ? "目标电脑的身份指纹变了，已拒绝连接"
+              : "第一次连接这台电脑，请核对身份指纹"}
+          </div>
+          <div className="mt-2 break-all font-mono text-xs">
+            {hostKey.host}　{hostKey.keyType} {hostKey.fingerprint}
+          </div>
+          {hostKey.status === "changed" && hostKey.pinnedFingerprint ? (
+            <div className="mt-1 break-all font-mono text-xs opacity-80">
+              之前记下的：{hostKey.pinnedFingerprint}
+            </div>
+          ) : null}
+          <div className="mt-2 text-xs opacity-80">
+            {hostKey.status === "changed"
+              ? "可能是目标电脑重装过系统，也可能有人在中间冒充。请先在目标电脑上核对，确认无误再点下面的按钮。"
+              : "在目标电脑上运行 ssh-keygen -lf /etc/ssh/ssh_host_ed25519_key.pub，看输出的指纹是否和上面一样。"}
+          </div>
+          <button
+            className="mt-3 rounded-lg border border-slate-200 bg-white px-3 py-1.5 text-sm font-semibold text-slate-700 transition hover:bg-slate-50 disabled:cursor-not-allowed disabled:opacity-60"
+            onClick={onTrustHostKey}
+            disabled={trusting || testing}
+          >
+            {trusting ? "记下中…" : "指纹一致，确认是这台电脑"}
+          </button>
+        </div>
+      ) : null}
+
       {testResult ? (
-        <div className="rounded-3xl border border-slate-200/60 bg-white/80 p-6 text-sm text-slate-800 shadow-sm backdrop-blur">
+        <div className="rounded-3xl border border-slate-200/60 bg-white/80 p-6 whitespace-pre-line text-sm text-slate-800 shadow-sm backdrop-blur">
           {testResult}
         </div>
       ) : null}
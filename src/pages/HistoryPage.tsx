@@ -1,7 +1,22 @@
 import { openPath } from "@tauri-apps/plugin-opener";
 import { useEffect, useMemo, useState } from "react";
-import { runLogRead, runsList } from "../lib/api";
-import type { RunRecord } from "../lib/types";
+import { historyStats, runLogRead, runResume, runsList } from "../lib/api";
+import type { HistoryStats, RunRecord } from "../lib/types";
+
+function statusText(r: RunRecord) {
+  if (r.status === "interrupted") return "中断";
+  if (r.status === "missed") return "错过";
+  if (r.status === "cancelled") return "已取消";
+  return r.ok ? "成功" : "失败";
+}
+
+function agoText(ms: number | null | undefined) {
+  if (ms == null) return "从没成功过";
+  const hours = Math.floor(ms / 3_600_000);
+  if (hours < 1) return "1 小时内";
+  if (hours < 48) return `${hours} 小时前`;
+  return `${Math.floor(hours / 24)} 天前`;
+}
 
 function msToLocal(ms: number) {
   try {
@@ -17,6 +32,8 @@ export function HistoryPage() {
   const [logText, setLogText] = useState<string>("");
   const [q, setQ] = useState("");
   const [err, setErr] = useState<string | null>(null);
+  const [resumeMsg, setResumeMsg] = useState<string | null>(null);
+  const [stats, setStats] = useState<HistoryStats | null>(null);
 
   useEffect(() => {
     let canceled = false;
@@ -26,6 +43,8 @@ export function HistoryPage() {
         if (canceled) return;
         setRuns(list);
         setSelectedId(list[0]?.runId ?? null);
+        setStats(await historyStats(30));
+        if (canceled) return;
         setErr(null);
       } catch (e) {
         if (canceled) return;
@@ -61,6 +80,17 @@ export function HistoryPage() {
     [runs, selectedId],
   );
 
+  async function onResume(runId: string) {
+    try {
+      const newId = await runResume(runId);
+      setResumeMsg(`已开始续跑（run_id: ${newId}），可在“执行同步”页查看进度`);
+      setErr(null);
+    } catch (e) {
+      setResumeMsg(null);
+      setErr(String(e));
+    }
+  }
+
   const filteredLog = useMemo(() => {
     const query = q.trim();
     if (!query) return logText;
@@ -85,6 +115,47 @@ export function HistoryPage() {
         </div>
       ) : null}
 
+      {stats && stats.items.length > 0 ? (
+        <div className="rounded-3xl border border-slate-200/60 bg-white/80 p-4 shadow-sm backdrop-blur">
+          <div className="flex flex-wrap items-center justify-between gap-2 px-1">
+            <div className="text-xs font-bold text-slate-400 uppercase tracking-widest">
+              近 30 天各项情况
+            </div>
+            <div className="text-xs text-slate-500">
+              共 {stats.runs} 次，成功率 {Math.round(stats.successRate * 100)}%
+              {stats.missed > 0 ? `，错过 ${stats.missed} 次` : ""}
+            </div>
+          </div>
+          <div className="mt-3 grid grid-cols-1 gap-2 md:grid-cols-2">
+            {stats.items.map((s) => (
+              <div
+                key={s.itemId}
+                className={[
+                  "rounded-2xl border px-3 py-2 text-sm",
+                  s.consecutiveFailures > 0 || s.lastOkAtMs == null
+                    ? "border-rose-200 bg-rose-50 text-rose-900"
+                    : "border-slate-200 bg-white/80 text-slate-800",
+                ].join(" ")}
+              >
+                <div className="flex items-center justify-between gap-2">
+                  <div className="font-semibold">{s.label}</div>
+                  <div className="text-xs opacity-80">
+                    成功率 {Math.round(s.successRate * 100)}%（{s.ok}/{s.runs}）
+                  </div>
+                </div>
+                <div className="mt-1 text-xs opacity-80">
+                  最后一次成功：{agoText(s.sinceLastOkMs)}
+                  {s.consecutiveFailures > 0 ? `，已连续失败 ${s.consecutiveFailures} 次` : ""}
+                </div>
+                {s.consecutiveFailures > 0 && s.lastError ? (
+                  <div className="mt-1 text-xs opacity-80">{s.lastError}</div>
+                ) : null}
+              </div>
+            ))}
+          </div>
+        </div>
+      ) : null}
+
       <div className="grid grid-cols-1 gap-4 md:grid-cols-3">
         <div className="rounded-3xl border border-slate-200/60 bg-white/80 p-4 shadow-sm backdrop-blur md:col-span-1">
           <div className="px-1 text-xs font-bold text-slate-400 uppercase tracking-widest">
@@ -106,7 +177,7 @@ export function HistoryPage() {
                 onClick={() => setSelectedId(r.runId)}
               >
                 <div className="flex items-center justify-between gap-2">
-                  <div className="font-medium">{r.ok ? "成功" : "失败"}</div>
+                  <div className="font-medium">{statusText(r)}</div>
                   <div className="text-xs opacity-80">{r.runId}</div>
                 </div>
                 <div className="mt-1 text-xs opacity-80">
@@ -122,7 +193,7 @@ export function HistoryPage() {
             <div className="text-xs font-bold text-slate-400 uppercase tracking-widest">
               日志内容
             </div>
-            {selected?.logPath ? (
+            {selected?.logPath && !selected.logMissing ? (
               <button
                 className="rounded-2xl border border-slate-200 bg-white/80 px-4 py-2 text-sm font-bold text-slate-800 shadow-sm transition-all hover:bg-white active:scale-[0.98]"
                 onClick={() => openPath(selected.logPath)}
@@ -142,7 +213,7 @@ export function HistoryPage() {
           </div>
 
           <pre className="mt-3 max-h-[520px] overflow-auto rounded-2xl border border-slate-200 bg-slate-950 p-4 text-xs text-slate-100">
-            {filteredLog || "（暂无日志）"}
+            {filteredLog || (selected?.logMissing ? "（日志已按保留策略清理）" : "（暂无日志）")}
           </pre>
 
           {selected ? (
@@ -150,6 +221,24 @@ export function HistoryPage() {
               备份目录：{selected.backupRoot}
             </div>
           ) : null}
+
+          {selected?.note ? (
+            <div className="mt-2 text-xs text-slate-600">{selected.note}</div>
+          ) : null}
+
+          {selected?.status === "interrupted" ? (
+            <div className="mt-3 flex flex-wrap items-center gap-3">
+              <button
+                className="rounded-2xl bg-indigo-600 px-4 py-2 text-sm font-bold text-white shadow-sm transition-all hover:bg-indigo-700 active:scale-[0.98]"
+                onClick={() => onResume(selected.runId)}
+              >
+                续跑没完成的项
+              </button>
+              {resumeMsg ? (
+                <div className="text-xs text-slate-600">{resumeMsg}</div>
+              ) : null}
+            </div>
+          ) : null}
         </div>
       </div>
     </div>
@@ -1,7 +1,19 @@
 import { useMemo, useState } from "react";
+import { syncEstimate } from "../lib/api";
 import { useAgentSyncConfig } from "../lib/useAgentSyncConfig";
 import { useSyncRun } from "../lib/syncRun";
-import type { SyncRunSummary } from "../lib/types";
+import type { SyncEstimate, SyncRunSummary } from "../lib/types";
+
+function formatSize(bytes: number) {
+  const units = ["B", "KB", "MB", "GB", "TB"];
+  let n = bytes;
+  let i = 0;
+  while (n >= 1024 && i < units.length - 1) {
+    n /= 1024;
+    i += 1;
+  }
+  return `${i === 0 ? n : n.toFixed(1)} ${units[i]}`;
+}
 
 function SummaryBox(props: { summary: SyncRunSummary }) {
   return (
@@ -28,6 +40,16 @@ function SummaryBox(props: { summary: SyncRunSummary }) {
             <div className="min-w-0">
               <div className="text-slate-900">{it.label}</div>
               <div className="truncate text-xs text-slate-600">{it.message}</div>
+              {it.git ? (
+                <div className="text-xs text-slate-500">
+                  git：{it.git.branch ?? "HEAD 游离"}@{it.git.head?.slice(0, 7) ?? "（还没有提交）"}
+                  {it.git.dirty ? `，${it.git.changedFiles} 个文件未提交` : "，没有未提交的改动"}
+                  {it.git.remoteUpdated ? "，目标端已对齐" : ""}
+                  {it.git.fetchedBranches != null
+                    ? `，取回目标端 ${it.git.fetchedBranches} 个分支`
+                    : ""}
+                </div>
+              ) : null}
             </div>
           </div>
         ))}
@@ -41,6 +63,9 @@ export function SyncExecutionPage() {
   const { status, refreshing, start, refresh } = useSyncRun();
   const [confirm, setConfirm] = useState(false);
   const [err, setErr] = useState<string | null>(null);
+  // 估算有提醒时先停下来让人确认（例如忘了排除 target/，一下要推几十 GB）
+  const [estimating, setEstimating] = useState(false);
+  const [pendingEstimate, setPendingEstimate] = useState<SyncEstimate | null>(null);
 
   const running = !!status?.running;
   const canRun = isValidForRun && !running;
@@ -55,8 +80,8 @@ export function SyncExecutionPage() {
     return Math.min(100, Math.max(0, p));
   }, [status?.lastLine]);
 
-  async function onRun() {
-    setErr(null);
+  async function startNow() {
+    setPendingEstimate(null);
     try {
       await start(config);
     } catch (e) {
@@ -64,6 +89,24 @@ export function SyncExecutionPage() {
     }
   }
 
+  async function onRun() {
+    setErr(null);
+    setEstimating(true);
+    let estimate: SyncEstimate | null = null;
+    try {
+      estimate = await syncEstimate(config);
+    } catch {
+      // 估算失败（例如配置有问题）不拦着，开始同步时会报具体原因
+    } finally {
+      setEstimating(false);
+    }
+    if (estimate && estimate.warnings.length > 0) {
+      setPendingEstimate(estimate);
+      return;
+    }
+    await startNow();
+  }
+
   return (
     <div className="space-y-6">
       <div>
@@ -105,11 +148,11 @@ export function SyncExecutionPage() {
       <div className="flex items-center gap-2">
         <button
           className="rounded-2xl bg-indigo-600 px-5 py-3 text-sm font-bold text-white shadow-lg shadow-indigo-200/70 transition-all hover:bg-indigo-700 active:scale-[0.98] disabled:cursor-not-allowed disabled:bg-slate-300 disabled:shadow-none"
-          disabled={!canRun || (needsConfirm && !confirm)}
+          disabled={!canRun || estimating || !!pendingEstimate || (needsConfirm && !confirm)}
           onClick={onRun}
           title={needsConfirm && !confirm ? "请先勾选确认" : undefined}
         >
-          {running ? "同步中…" : "开始同步"}
+          {running ? "同步中…" : estimating ? "估算中…" : "开始同步"}
         </button>
         <button
           className="rounded-2xl border border-slate-200 bg-white/80 px-5 py-3 text-sm font-bold text-slate-800 shadow-sm transition-all hover:bg-white active:scale-[0.98] disabled:cursor-not-allowed disabled:bg-slate-100"
@@ -121,6 +164,47 @@ export function SyncExecutionPage() {
         </button>
       </div>
 
+      {pendingEstimate ? (
+        <div className="rounded-xl border border-amber-200 bg-amber-50 p-4 text-sm text-amber-900">
+          <div className="font-medium">
+            这次最多要同步 {formatSize(pendingEstimate.totalBytes)}（
+            {pendingEstimate.totalFiles} 个文件），有几点需要注意
+          </div>
+          <ul className="mt-2 list-disc space-y-1 pl-5 leading-relaxed">
+            {pendingEstimate.warnings.map((w) => (
+              <li key={w}>{w}</li>
+            ))}
+          </ul>
+          <div className="mt-2 space-y-0.5 text-xs text-amber-800">
+            {pendingEstimate.items
+              .filter((i) => !i.skipMessage)
+              .map((i) => (
+                <div key={i.itemId}>
+                  {i.label}：{formatSize(i.bytes)}
+                  {i.truncated ? "以上" : ""}，{i.files} 个文件
+                </div>
+              ))}
+          </div>
+          <div className="mt-2 text-xs text-amber-800">
+            已经同步过、没变的文件不会再传，所以实际传输量可能小得多。
+          </div>
+          <div className="mt-3 flex items-center gap-2">
+            <button
+              className="rounded-xl bg-amber-600 px-4 py-2 text-sm font-bold text-white transition hover:bg-amber-700"
+              onClick={() => void startNow()}
+            >
+              仍然开始同步
+            </button>
+            <button
+              className="rounded-xl border border-amber-200 bg-white px-4 py-2 text-sm font-semibold text-amber-900 transition hover:bg-amber-100"
+              onClick={() => setPendingEstimate(null)}
+            >
+              先不同步（去改排除规则）
+            </button>
+          </div>
+        </div>
+      ) : null}
+
       {err ? (
         <div className="rounded-xl border border-rose-200 bg-rose-50 p-4 text-sm text-rose-900">
           {err}
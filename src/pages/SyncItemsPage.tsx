@@ -1,7 +1,22 @@
 import { open as openDialog } from "@tauri-apps/plugin-dialog";
-import { useMemo } from "react";
+import { useMemo, useState } from "react";
+import { projectsDiscover } from "../lib/api";
 import { useAgentSyncConfig } from "../lib/useAgentSyncConfig";
-import type { ProjectItem } from "../lib/types";
+import type {
+  DiscoverResult,
+  DiscoveredItem,
+  GitMode,
+  ProjectItem,
+} from "../lib/types";
+
+const GIT_MODES: { value: GitMode; label: string }[] = [
+  { value: "off", label: "不管 .git（按排除规则走）" },
+  { value: "bundle", label: "打包推送 .git，目标端分支 / HEAD 和本机对齐（目标端要装 git）" },
+  {
+    value: "fetch",
+    label: "取回目标端的提交，放到本机 refs/remotes/agentsync/（不动本机分支，目标端要装 git）",
+  },
+];
 
 function Input(props: React.InputHTMLAttributes<HTMLInputElement>) {
   return (
@@ -35,6 +50,17 @@ function lastSegment(path: string) {
   return parts[parts.length - 1] || "project";
 }
 
+function formatSize(bytes: number) {
+  const units = ["B", "KB", "MB", "GB", "TB"];
+  let n = bytes;
+  let i = 0;
+  while (n >= 1024 && i < units.length - 1) {
+    n /= 1024;
+    i += 1;
+  }
+  return `${i === 0 ? n : n.toFixed(1)} ${units[i]}`;
+}
+
 function Toggle(props: {
   checked: boolean;
   onChange: (v: boolean) => void;
@@ -64,6 +90,60 @@ export function SyncItemsPage() {
 
   const excludesText = useMemo(() => config.excludes.join("\n"), [config]);
 
+  // 扫描项目：勾选后加进列表（还要点“保存”）
+  const [discovering, setDiscovering] = useState(false);
+  const [discovered, setDiscovered] = useState<DiscoverResult | null>(null);
+  const [picked, setPicked] = useState<Set<string>>(new Set());
+  const [discoverError, setDiscoverError] = useState<string | null>(null);
+
+  async function discover() {
+    setDiscovering(true);
+    setDiscoverError(null);
+    try {
+      const res = await projectsDiscover(config);
+      setDiscovered(res);
+      // 默认勾上还没在同步的项目；工具数据目录让人自己决定
+      setPicked(
+        new Set(
+          res.items
+            .filter((i) => !i.coveredBy && i.kind === "project")
+            .map((i) => i.proposal.id),
+        ),
+      );
+    } catch (e) {
+      setDiscoverError(String(e));
+    } finally {
+      setDiscovering(false);
+    }
+  }
+
+  function togglePicked(item: DiscoveredItem, on: boolean) {
+    const next = new Set(picked);
+    if (on) next.add(item.proposal.id);
+    else next.delete(item.proposal.id);
+    setPicked(next);
+  }
+
+  function addPicked() {
+    if (!discovered) return;
+    const items = discovered.items.filter(
+      (i) => !i.coveredBy && picked.has(i.proposal.id),
+    );
+    const flags = { ...config.flags };
+    const projects = [...config.projects];
+    const excludes = [...config.excludes];
+    for (const i of items) {
+      if (i.builtinFlag) flags[i.builtinFlag] = true;
+      else projects.push(i.proposal);
+      // 带登录信息的数据目录：一起加排除规则，别把凭据同步过去
+      for (const rule of i.excludes) {
+        if (!excludes.some((e) => e.trim() === rule)) excludes.push(rule);
+      }
+    }
+    setConfig({ ...config, flags, projects, excludes });
+    setDiscovered(null);
+  }
+
   async function addProject() {
     const selected = await openDialog({
       multiple: false,
@@ -79,6 +159,7 @@ export function SyncItemsPage() {
       localPath: selected,
       remoteDirName: name,
       enabled: true,
+      git: "off",
     };
 
     setConfig({ ...config, projects: [...config.projects, item] });
@@ -182,14 +263,107 @@ export function SyncItemsPage() {
               远端目录名默认用文件夹名字；如果两个项目同名，记得改一下避免覆盖。
             </div>
           </div>
-          <button
-            className="rounded-2xl bg-indigo-600 px-4 py-2.5 text-sm font-bold text-white shadow-lg shadow-indigo-200/70 transition-all hover:bg-indigo-700 active:scale-[0.98]"
-            onClick={addProject}
-          >
-            添加项目
-          </button>
+          <div className="flex items-center gap-2">
+            <button
+              className="rounded-2xl border border-slate-200 bg-white px-4 py-2.5 text-sm font-bold text-slate-700 transition hover:bg-slate-50 disabled:cursor-not-allowed disabled:opacity-60"
+              onClick={discover}
+              disabled={discovering}
+            >
+              {discovering ? "扫描中…" : "扫描项目"}
+            </button>
+            <button
+              className="rounded-2xl bg-indigo-600 px-4 py-2.5 text-sm font-bold text-white shadow-lg shadow-indigo-200/70 transition-all hover:bg-indigo-700 active:scale-[0.98]"
+              onClick={addProject}
+            >
+              添加项目
+            </button>
+          </div>
         </div>
 
+        {discoverError ? (
+          <div className="mt-4 rounded-2xl border border-rose-200 bg-rose-50 p-4 text-sm text-rose-700">
+            {discoverError}
+          </div>
+        ) : null}
+
+        {discovered ? (
+          <div className="mt-4 rounded-2xl border border-indigo-100 bg-indigo-50/40 p-4">
+            <div className="text-xs text-slate-500">
+              扫描了：{discovered.roots.length > 0 ? discovered.roots.join("、") : "（配置里的目录都不存在）"}
+            </div>
+            {discovered.errors.map((e) => (
+              <div key={e} className="mt-1 text-xs text-rose-600">
+                {e}
+              </div>
+            ))}
+            {discovered.items.length === 0 ? (
+              <div className="mt-3 text-sm text-slate-500">没找到可以同步的文件夹。</div>
+            ) : null}
+            <div className="mt-3 space-y-2">
+              {discovered.items.map((i) => (
+                <label
+                  key={i.proposal.id}
+                  className={[
+                    "flex items-start gap-3 rounded-xl border border-slate-200 bg-white p-3",
+                    i.coveredBy ? "opacity-60" : "cursor-pointer",
+                  ].join(" ")}
+                >
+                  <input
+                    type="checkbox"
+                    className="mt-1 h-4 w-4 accent-indigo-600"
+                    disabled={!!i.coveredBy}
+                    checked={!i.coveredBy && picked.has(i.proposal.id)}
+                    onChange={(e) => togglePicked(i, e.target.checked)}
+                  />
+                  <div className="min-w-0 flex-1">
+                    <div className="flex flex-wrap items-center gap-2 text-sm font-medium">
+                      <span className="truncate">{i.proposal.name}</span>
+                      {i.markers.map((m) => (
+                        <span
+                          key={m}
+                          className="rounded bg-slate-100 px-1.5 py-0.5 text-[11px] text-slate-500"
+                        >
+                          {m}
+                        </span>
+                      ))}
+                    </div>
+                    <div className="truncate text-xs text-slate-500">
+                      {i.proposal.localPath}
+                    </div>
+                    <div className="mt-0.5 text-xs text-slate-500">
+                      {i.builtinFlag
+                        ? "打开对应的开关同步"
+                        : `远端目录名：${i.proposal.remoteDirName}`}
+                      {" · "}
+                      {i.files} 个文件，{formatSize(i.bytes)}
+                      {i.sizeTruncated ? "以上" : ""}
+                      {i.coveredBy ? " · 已在同步" : ""}
+                    </div>
+                    {i.note ? (
+                      <div className="mt-0.5 text-xs text-amber-600">{i.note}</div>
+                    ) : null}
+                  </div>
+                </label>
+              ))}
+            </div>
+            <div className="mt-3 flex items-center gap-2">
+              <button
+                className="rounded-xl bg-indigo-600 px-4 py-2 text-sm font-bold text-white transition hover:bg-indigo-700 disabled:cursor-not-allowed disabled:bg-slate-300"
+                onClick={addPicked}
+                disabled={picked.size === 0}
+              >
+                加入选中的 {picked.size} 项
+              </button>
+              <button
+                className="rounded-xl border border-slate-200 bg-white px-4 py-2 text-sm font-semibold text-slate-700 transition hover:bg-slate-50"
+                onClick={() => setDiscovered(null)}
+              >
+                取消
+              </button>
+            </div>
+          </div>
+        ) : null}
+
         <div className="mt-4 space-y-3">
           {config.projects.length === 0 ? (
             <div className="rounded-2xl border border-dashed border-slate-300 p-8 text-center text-sm text-slate-500">
@@ -243,6 +417,26 @@ export function SyncItemsPage() {
                     placeholder="例如 my-project"
                   />
                 </div>
+                <div>
+                  <div className="text-xs font-medium text-slate-700">
+                    git 仓库
+                  </div>
+                  {GIT_MODES.map((m) => (
+                    <label
+                      key={m.value}
+                      className="mt-2 flex items-center gap-2 text-sm text-slate-700"
+                    >
+                      <input
+                        type="radio"
+                        name={`git-${p.id}`}
+                        className="accent-indigo-600"
+                        checked={(p.git ?? "off") === m.value}
+                        onChange={() => updateProject(p.id, { git: m.value })}
+                      />
+                      {m.label}
+                    </label>
+                  ))}
+                </div>
               </div>
             </div>
           ))}